@@ -0,0 +1,130 @@
+use lead::air::air::Reg;
+use miette::Diagnostic;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors that stop a running program. Their codes start with `R`, apart from the `E` codes of
+/// errors found while compiling.
+#[derive(Error, Debug, Diagnostic, Clone, PartialEq, Eq)]
+pub enum VmError {
+    #[error(
+        "memory access at address {addr:#x} is out of bounds for memory of size {memory_size:#x}"
+    )]
+    #[diagnostic(
+        code(R0001),
+        help("give the program more memory with `-m`, or the `#memory` pragma")
+    )]
+    OutOfBoundsAccess { addr: usize, memory_size: usize },
+    #[error(
+        "instruction {pc} accessed address {addr:#x}, which is out of bounds for memory of size {memory_size:#x}"
+    )]
+    #[diagnostic(
+        code(R0002),
+        help("check the index is within the array, or give the program more memory with `-m`")
+    )]
+    MemoryFault {
+        addr: usize,
+        memory_size: usize,
+        pc: usize,
+    },
+    #[error("division by zero at instruction {pc}")]
+    #[diagnostic(
+        code(R0003),
+        help("check that the divisor is not zero before dividing by it")
+    )]
+    DivisionByZero { pc: usize },
+    #[error("arithmetic overflowed at instruction {pc}")]
+    #[diagnostic(
+        code(R0004),
+        help("run with `--overflow wrapping` to let results wrap around instead")
+    )]
+    ArithmeticOverflow { pc: usize },
+    #[error(
+        "program argument {index} was read at instruction {pc}, but the program was given {count}"
+    )]
+    #[diagnostic(
+        code(R0005),
+        help("pass the program's arguments after `--`, as in `leadc run main.ed -- 3 5`")
+    )]
+    MissingArgument {
+        index: usize,
+        count: usize,
+        pc: usize,
+    },
+    #[error("the program trapped at instruction {pc}")]
+    #[diagnostic(
+        code(R0006),
+        help(
+            "`--check-bounds` traps on an index outside the array, so check the index is within it"
+        )
+    )]
+    Trap { pc: usize },
+    #[error(
+        "instruction {pc} cannot allocate {requested} bytes, only {available} are free on the heap"
+    )]
+    #[diagnostic(
+        code(R0007),
+        help("give the program more memory with `-m`, or the `#memory` pragma")
+    )]
+    HeapExhausted {
+        requested: usize,
+        available: usize,
+        pc: usize,
+    },
+    #[error("the program ran for more than {limit} steps")]
+    #[diagnostic(
+        code(R0008),
+        help("check that every loop ends, or allow the program more steps")
+    )]
+    StepLimitExceeded { limit: u64 },
+    #[error("the program needs {size} bytes of memory, but is limited to {limit}")]
+    #[diagnostic(
+        code(R0009),
+        help("run the program with less memory, or raise the limit")
+    )]
+    MemoryLimitExceeded { size: usize, limit: usize },
+    #[error("the program yielded more than {limit} values")]
+    #[diagnostic(
+        code(R0010),
+        help("check that every loop that yields ends, or raise the limit")
+    )]
+    OutputLimitExceeded { limit: usize },
+    #[error("the program ran for longer than {limit:?}")]
+    #[diagnostic(
+        code(R0011),
+        help("check that every loop ends, or allow the program more time")
+    )]
+    Timeout { limit: Duration },
+    #[error("register {reg} was read at instruction {pc} before anything was saved in it")]
+    #[diagnostic(
+        code(R0012),
+        help("save a value in the register before the instruction reads it")
+    )]
+    UninitialisedRegister { reg: Reg, pc: usize },
+    #[error("instruction {pc} branches to the label `{label}`, which is not in the program")]
+    #[diagnostic(
+        code(R0013),
+        help("add the label with `LBL`, or branch to one that exists")
+    )]
+    UnknownLabel { label: String, pc: usize },
+    #[error("the program's output was closed while it was running")]
+    #[diagnostic(code(R0014))]
+    OutputClosed,
+}
+
+impl VmError {
+    /// Returns the index of the instruction that caused the error, if one did.
+    pub fn pc(&self) -> Option<usize> {
+        match self {
+            Self::MemoryFault { pc, .. }
+            | Self::DivisionByZero { pc }
+            | Self::ArithmeticOverflow { pc }
+            | Self::MissingArgument { pc, .. }
+            | Self::Trap { pc }
+            | Self::HeapExhausted { pc, .. }
+            | Self::UninitialisedRegister { pc, .. }
+            | Self::UnknownLabel { pc, .. } => Some(*pc),
+            _ => None,
+        }
+    }
+}
@@ -1,19 +1,67 @@
+pub mod error;
 #[cfg(test)]
 mod tests;
 
-use lead::air::air::{Flag, Instruction, Mode, Reg};
+use error::VmError;
+use lead::air::{
+    air::{Flag, Instruction, Mode, Reg, Rounding, Stream},
+    label_table, AirModule,
+};
+use lead::lex::span::Span;
 use log::{debug, info};
-use std::collections::HashMap;
-use std::sync::mpsc::Sender;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub const DEFAULT_MEMORY_SIZE: usize = 256;
 pub const DEFAULT_VERBOSITY: u8 = 1;
+/// Word size in bytes. Words are stored in memory big endian.
+pub const WORD_SIZE: usize = 4;
+/// The most registers the register file holds densely. Registers numbered beyond it, which only
+/// hand-written AIR reaches, are kept apart so a huge register number cannot exhaust memory.
+const DENSE_REGISTERS: usize = 1 << 20;
 
 #[derive(Debug, Clone, Copy)]
 pub struct VMFlags {
     pub memory_size: usize,
     /// the logging verbosity. 0 for quiet, 1 for normal, 2 for verbose, 3 for very verbose.
     pub verbosity: u8,
+    /// log every memory access, whatever the verbosity.
+    pub trace_memory: bool,
+    /// what `ADD`, `SUB`, `MUL`, `INC` and `DEC` do with a result that does not fit in a word.
+    pub overflow_mode: OverflowMode,
+}
+
+/// How arithmetic handles a result outside the range of an unsigned word. Words are treated as
+/// unsigned, so adding a negative number in two's complement overflows unless wrapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// Keep the low bits of the result, which is also signed arithmetic in two's complement.
+    #[default]
+    Wrapping,
+    /// Clamp the result to 0 or `u32::MAX`.
+    Saturating,
+    /// Stop the program with an error.
+    Trapping,
+}
+
+impl std::str::FromStr for OverflowMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "wrapping" => Ok(Self::Wrapping),
+            "saturating" => Ok(Self::Saturating),
+            "trapping" => Ok(Self::Trapping),
+            _ => Err(format!(
+                "unknown overflow mode `{mode}`, expected `wrapping`, `saturating` or `trapping`"
+            )),
+        }
+    }
 }
 
 pub enum Verbosity {
@@ -28,6 +76,8 @@ impl VMFlags {
         Self {
             memory_size: DEFAULT_MEMORY_SIZE,
             verbosity: DEFAULT_VERBOSITY,
+            trace_memory: false,
+            overflow_mode: OverflowMode::Wrapping,
         }
     }
 
@@ -35,25 +85,101 @@ impl VMFlags {
         Self {
             memory_size,
             verbosity,
+            trace_memory: false,
+            overflow_mode: OverflowMode::Wrapping,
+        }
+    }
+}
+
+/// Limits on the resources an untrusted program may use, enforced together by
+/// [`Machine::run_sandboxed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sandbox {
+    /// the most instructions the program may execute
+    pub max_steps: u64,
+    /// the most bytes of memory the machine may have, including the program's static allocations
+    pub max_memory: usize,
+    /// the most values the program may yield, to any stream
+    pub max_yields: usize,
+    /// the longest the program may run for
+    pub timeout: Duration,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            max_steps: 1_000_000,
+            max_memory: 64 * 1024,
+            max_yields: 10_000,
+            timeout: Duration::from_secs(1),
         }
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Message {
-    Yield(u32),
+    /// A value yielded by the program, to be written to the given stream.
+    Yield {
+        stream: Stream,
+        value: u32,
+    },
+    /// The program stopped with an error. Every value yielded before the error is sent first.
+    Error(VmError),
     Done,
 }
 
 pub struct Machine {
     instructions: Vec<Instruction>,
-    registers: HashMap<u32, u32>,
+    /// the register file, indexed by register number and sized for the highest register the
+    /// program uses, up to [`DENSE_REGISTERS`]. `None` marks a register not saved in yet.
+    registers: Vec<Option<u32>>,
+    /// the registers numbered beyond the register file, which are set
+    sparse_registers: HashMap<u32, u32>,
     memory: Vec<u8>,
     yield_callback: Sender<Message>,
     /// program counter
     pc: usize,
+    /// the number of instructions executed so far
+    steps: u64,
+    /// the values the program was run with, read by `ARG`
+    arguments: Vec<u32>,
+    /// the address of the next free byte on the heap, which `ALLOC` reserves from. The heap starts
+    /// after the program's static allocations and grows up to the end of memory.
+    heap: usize,
     flags: Flags,
     vm_flags: VMFlags,
+    /// the state changed by recent steps, when recording
+    history: Option<History>,
+    /// how many of the most recent yields to keep
+    yield_history_size: usize,
+    /// the most recent yields, oldest first. This holds up to twice `yield_history_size` values
+    /// so old values are dropped in batches rather than on every yield.
+    recent_yields: Vec<u32>,
+    /// set from another thread to stop the program
+    stop: Option<Arc<AtomicBool>>,
+    /// the index of each label in the program
+    labels: HashMap<String, usize>,
+    /// the names of the variables held in registers, for debugging
+    symbols: HashMap<Reg, String>,
+    /// the source span of each instruction, if known
+    spans: Vec<Span>,
+}
+
+/// A bounded record of recent steps, oldest first.
+struct History {
+    depth: usize,
+    snapshots: VecDeque<Snapshot>,
+}
+
+/// The state overwritten by a single step, enough to undo it.
+struct Snapshot {
+    pc: usize,
+    flags: u16,
+    heap: usize,
+    /// previous values of the registers written, `None` if the register was unset
+    registers: Vec<(u32, Option<u32>)>,
+    /// previous values of the bytes written
+    memory: Vec<(usize, u8)>,
 }
 
 impl Machine {
@@ -62,34 +188,309 @@ impl Machine {
         yield_sender: Sender<Message>,
         vm_flags: VMFlags,
     ) -> Self {
+        let labels = label_table(instructions.iter());
+        Self::with_tables(
+            instructions,
+            labels,
+            HashMap::new(),
+            Vec::new(),
+            0,
+            yield_sender,
+            vm_flags,
+        )
+    }
+
+    /// Create a machine whose memory starts with `memory`, such as tables of data the program
+    /// reads. Memory is enlarged to fit it if needed, and the heap starts after it.
+    pub fn with_memory(
+        instructions: Vec<Instruction>,
+        memory: Vec<u8>,
+        yield_sender: Sender<Message>,
+        mut vm_flags: VMFlags,
+    ) -> Self {
+        vm_flags.memory_size = vm_flags.memory_size.max(memory.len());
+        let labels = label_table(instructions.iter());
+        let mut machine = Self::with_tables(
+            instructions,
+            labels,
+            HashMap::new(),
+            Vec::new(),
+            memory.len().next_multiple_of(WORD_SIZE),
+            yield_sender,
+            vm_flags,
+        );
+        machine.memory[..memory.len()].copy_from_slice(&memory);
+        machine
+    }
+
+    fn with_tables(
+        instructions: Vec<Instruction>,
+        labels: HashMap<String, usize>,
+        symbols: HashMap<Reg, String>,
+        spans: Vec<Span>,
+        heap: usize,
+        yield_sender: Sender<Message>,
+        vm_flags: VMFlags,
+    ) -> Self {
+        let register_count = instructions
+            .iter()
+            .flat_map(|inst| [inst.read_registers(), inst.written_registers()].concat())
+            .map(|reg| *reg as usize + 1)
+            .filter(|&count| count <= DENSE_REGISTERS)
+            .max()
+            .unwrap_or(0);
         Self {
+            labels,
+            symbols,
+            spans,
             instructions,
             memory: vec![0; vm_flags.memory_size],
-            registers: HashMap::new(),
+            registers: vec![None; register_count],
+            sparse_registers: HashMap::new(),
             yield_callback: yield_sender,
             pc: 0,
+            steps: 0,
+            arguments: Vec::new(),
+            heap,
             flags: Flags::empty(),
             vm_flags,
+            history: None,
+            yield_history_size: 0,
+            recent_yields: Vec::new(),
+            stop: None,
         }
     }
 
+    /// Create a machine to run a compiled module. Memory is enlarged to fit the module's static
+    /// allocations if needed, and starts with the module's data tables. The names of its variables
+    /// are kept for [`Machine::dump_state`], and the spans of its instructions for
+    /// [`Machine::run_until_line`].
+    pub fn from_air_module(
+        module: AirModule,
+        yield_sender: Sender<Message>,
+        mut vm_flags: VMFlags,
+    ) -> Self {
+        vm_flags.memory_size = vm_flags
+            .memory_size
+            .max(module.static_memory)
+            .max(module.data.len());
+        let labels = module.labels();
+        let symbols = module
+            .variables
+            .iter()
+            .map(|(name, reg)| (*reg, name.clone()))
+            .collect();
+        let (instructions, spans) = module
+            .instructions
+            .into_iter()
+            .map(|inst| (inst.instruction, inst.span))
+            .unzip();
+        // the heap starts at the first word after the static allocations.
+        let heap = module.static_memory.next_multiple_of(WORD_SIZE);
+        let mut machine = Self::with_tables(
+            instructions,
+            labels,
+            symbols,
+            spans,
+            heap,
+            yield_sender,
+            vm_flags,
+        );
+        machine.memory[..module.data.len()].copy_from_slice(&module.data);
+        machine
+    }
+
+    /// Stop running the program once `stop` is set, such as by another thread enforcing a time
+    /// limit. The program is checked before each step.
+    pub fn stop_when(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = Some(stop);
+    }
+
+    /// Run the program with these arguments, which it reads by index with `ARG`.
+    pub fn set_arguments(&mut self, arguments: Vec<u32>) {
+        self.arguments = arguments;
+    }
+
+    /// Keep the `size` most recently yielded values, to be read with [`Machine::recent_yields`].
+    pub fn record_yields(&mut self, size: usize) {
+        self.yield_history_size = size;
+        self.recent_yields = Vec::with_capacity(2 * size);
+    }
+
+    /// The most recently yielded values, oldest first, when recording yields.
+    pub fn recent_yields(&self) -> &[u32] {
+        let start = self
+            .recent_yields
+            .len()
+            .saturating_sub(self.yield_history_size);
+        &self.recent_yields[start..]
+    }
+
+    /// Record the state changed by each step so it can be undone with [`Machine::step_back`],
+    /// keeping at most `depth` steps. Yielded values can not be taken back.
+    pub fn record_history(&mut self, depth: usize) {
+        self.history = Some(History {
+            depth,
+            snapshots: VecDeque::with_capacity(depth),
+        });
+    }
+
     pub fn run(&mut self) {
         while self.step() {}
-        self.yield_callback.send(Message::Done).expect("oh oh!");
+        // a receiver that has gone away no longer needs to know the program finished.
+        let _ = self.yield_callback.send(Message::Done);
     }
 
-    /// Take one step through the program, returning false when the program has terminated, true otherwise
-    fn step(&mut self) -> bool {
+    /// Run the program within the limits of `sandbox`, returning the values it yielded to its
+    /// output, or the error it stopped with. Exceeding a limit stops the program with the error
+    /// for that limit. Yields go to the returned values rather than to the machine's callback.
+    pub fn run_sandboxed(mut self, sandbox: Sandbox) -> Result<Vec<u32>, VmError> {
+        if self.memory.len() > sandbox.max_memory {
+            return Err(VmError::MemoryLimitExceeded {
+                size: self.memory.len(),
+                limit: sandbox.max_memory,
+            });
+        }
+
+        let (sender, receiver) = channel();
+        self.yield_callback = sender;
+        let start = Instant::now();
+        let (mut output, mut yields) = (Vec::new(), 0);
+        loop {
+            if self.steps >= sandbox.max_steps && self.pc < self.instructions.len() {
+                return Err(VmError::StepLimitExceeded {
+                    limit: sandbox.max_steps,
+                });
+            }
+            // reading the clock is slow next to a step, so the timeout is checked periodically.
+            if self.steps.is_multiple_of(1024) && start.elapsed() > sandbox.timeout {
+                return Err(VmError::Timeout {
+                    limit: sandbox.timeout,
+                });
+            }
+            let running = self.step();
+            for message in receiver.try_iter() {
+                match message {
+                    Message::Yield { stream, value } => {
+                        yields += 1;
+                        if yields > sandbox.max_yields {
+                            return Err(VmError::OutputLimitExceeded {
+                                limit: sandbox.max_yields,
+                            });
+                        }
+                        if stream == Stream::Out {
+                            output.push(value);
+                        }
+                    }
+                    Message::Error(err) => return Err(err),
+                    Message::Done => (),
+                }
+            }
+            if !running {
+                return Ok(output);
+            }
+        }
+    }
+
+    /// Step through the program until the next instruction comes from `line` of `src`, counting
+    /// from 1, returning true if it paused there and false if the program terminated first. This
+    /// returns immediately when already paused on the line, so step once to move past it. Only
+    /// machines made with [`Machine::from_air_module`] know where their instructions came from.
+    pub fn run_until_line(&mut self, src: &str, line: usize) -> bool {
+        loop {
+            if self
+                .spans
+                .get(self.pc)
+                .is_some_and(|span| span.line_col(src).0 == line)
+            {
+                return true;
+            }
+            if !self.step() {
+                return false;
+            }
+        }
+    }
+
+    /// Take one step through the program, returning false when the program has terminated, true otherwise.
+    /// A program that errors is terminated, after sending the error to the yield callback.
+    pub fn step(&mut self) -> bool {
+        if let Some(stop) = &self.stop {
+            if stop.load(atomic::Ordering::Relaxed) {
+                return false;
+            }
+        }
         match self.instructions.get(self.pc) {
             None => false,
             Some(instruction) => {
-                self.process(&instruction.clone());
+                let (pc, flags, heap) = (self.pc, self.flags.0, self.heap);
+                if let Some(history) = &mut self.history {
+                    if history.depth == 0 {
+                        history.snapshots.clear();
+                    } else {
+                        if history.snapshots.len() == history.depth {
+                            history.snapshots.pop_front();
+                        }
+                        history.snapshots.push_back(Snapshot {
+                            pc,
+                            flags,
+                            heap,
+                            registers: Vec::new(),
+                            memory: Vec::new(),
+                        });
+                    }
+                }
+                if let Err(err) = self.process(&instruction.clone()) {
+                    if self.log_is_normal() {
+                        info!("stopping with error: {err}")
+                    }
+                    let _ = self.yield_callback.send(Message::Error(err));
+                    return false;
+                }
                 self.advance(1);
+                self.steps += 1;
                 true
             }
         }
     }
 
+    /// The number of instructions executed so far, not counting steps that were undone.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Undo the most recent recorded step, returning false when there is no step to undo.
+    pub fn step_back(&mut self) -> bool {
+        let Some(snapshot) = self
+            .history
+            .as_mut()
+            .and_then(|history| history.snapshots.pop_back())
+        else {
+            return false;
+        };
+
+        if self.log_is_verbose() {
+            debug!("stepping back to pc = {}", snapshot.pc)
+        }
+        for (reg, val) in snapshot.registers.into_iter().rev() {
+            self.set_register(reg, val);
+        }
+        for (addr, byte) in snapshot.memory.into_iter().rev() {
+            self.memory[addr] = byte;
+        }
+        self.flags = Flags(snapshot.flags);
+        self.heap = snapshot.heap;
+        self.pc = snapshot.pc;
+        self.steps = self.steps.saturating_sub(1);
+        true
+    }
+
+    /// The snapshot of the step currently being taken, if recording.
+    fn snapshot(&mut self) -> Option<&mut Snapshot> {
+        self.history
+            .as_mut()
+            .and_then(|history| history.snapshots.back_mut())
+    }
+
     /// Advance the program counter by `n` steps.
     fn advance(&mut self, count: usize) {
         if self.log_is_very_verbose() {
@@ -98,165 +499,384 @@ impl Machine {
         self.pc += count;
     }
 
-    fn process(&mut self, instruction: &Instruction) {
+    fn process(&mut self, instruction: &Instruction) -> Result<(), VmError> {
         if self.log_is_verbose() {
             debug!("processing instruction: {instruction}")
         }
 
         match instruction {
-            Instruction::ADD(rd, rx, ry) => self.save(rd, &((self.get(rx)) + self.get(ry))),
-            Instruction::SUB(rd, rx, ry) => self.save(rd, &((self.get(rx)) - self.get(ry))),
-            Instruction::MUL(rd, rx, ry) => self.save(rd, &((self.get(rx)) * self.get(ry))),
-            Instruction::DIV(rd, rx, ry) => self.save(rd, &((self.get(rx)) / self.get(ry))),
-            Instruction::CMP(rx, ry, _) => self.set_flags(rx, ry),
+            // arithmetic wraps by default, so it is the same for signed numbers in two's complement.
+            Instruction::ADD(rd, rx, ry) => {
+                let sum =
+                    self.overflowing(self.get(rx)?.overflowing_add(self.get(ry)?), u32::MAX)?;
+                self.save(rd, &sum)
+            }
+            Instruction::SUB(rd, rx, ry) => {
+                let difference =
+                    self.overflowing(self.get(rx)?.overflowing_sub(self.get(ry)?), 0)?;
+                self.save(rd, &difference)
+            }
+            Instruction::MUL(rd, rx, ry) => {
+                let product =
+                    self.overflowing(self.get(rx)?.overflowing_mul(self.get(ry)?), u32::MAX)?;
+                self.save(rd, &product)
+            }
+            Instruction::DIV(rd, rx, ry) => {
+                let divisor = self.divisor(ry)?;
+                self.save(rd, &((self.get(rx)?) / divisor))
+            }
+            Instruction::SDIV(rd, rx, ry, rounding) => {
+                let divisor = self.divisor(ry)? as i32;
+                let quotient = divide_signed(self.get(rx)? as i32, divisor, *rounding);
+                self.save(rd, &(quotient as u32))
+            }
+            Instruction::MOD(rd, rx, ry) => {
+                let divisor = self.divisor(ry)? as i32;
+                // the minimum value modulo `-1` is 0, where `%` would overflow.
+                let remainder = (self.get(rx)? as i32).wrapping_rem(divisor);
+                self.save(rd, &(remainder as u32))
+            }
+            Instruction::INC(rd) => {
+                let value = self.overflowing(self.get(rd)?.overflowing_add(1), u32::MAX)?;
+                self.save(rd, &value)
+            }
+            Instruction::DEC(rd) => {
+                let value = self.overflowing(self.get(rd)?.overflowing_sub(1), 0)?;
+                self.save(rd, &value)
+            }
+            Instruction::CYC(rd) => self.save(rd, &(self.steps as u32)),
+            Instruction::ARG(rd, rx) => {
+                let index = self.get(rx)? as usize;
+                let argument = *self.arguments.get(index).ok_or(VmError::MissingArgument {
+                    index,
+                    count: self.arguments.len(),
+                    pc: self.pc,
+                })?;
+                self.save(rd, &argument)
+            }
+            Instruction::ALLOC(rd, rx) => {
+                let address = self.allocate(self.get(rx)? as usize)?;
+                self.save(rd, &(address as u32))
+            }
+            Instruction::CMP(rx, ry, _) => self.set_flags(self.get(rx)?.cmp(&self.get(ry)?)),
+            Instruction::SCMP(rx, ry, _) => {
+                self.set_flags((self.get(rx)? as i32).cmp(&(self.get(ry)? as i32)))
+            }
             Instruction::CON(rd, val) => self.save(rd, val),
-            Instruction::MOV(rd, rx) => self.save(rd, &self.get(rx)),
-            Instruction::NOT(rd, rx) => self.save(rd, &!self.get(rx)),
-            Instruction::BRA(label) => self.branch(label),
-            Instruction::YLD(rx) => self.yield_register(rx),
+            Instruction::LEA(rd, address) => self.save(rd, &(*address as u32)),
+            Instruction::MOV(rd, rx) => self.save(rd, &self.get(rx)?),
+            Instruction::NOT(rd, rx) => self.save(rd, &!self.get(rx)?),
+            Instruction::TST(rx, mask) => self.set_flags((self.get(rx)? & mask).cmp(&0)),
+            Instruction::BIC(rd, rx, mask) => self.save(rd, &(self.get(rx)? & !mask)),
+            Instruction::AND(rd, rx, ry) => self.save(rd, &(self.get(rx)? & self.get(ry)?)),
+            Instruction::ORR(rd, rx, ry) => self.save(rd, &(self.get(rx)? | self.get(ry)?)),
+            Instruction::BRA(label) => self.branch(label)?,
+            Instruction::BRAcc(flag, label) => {
+                if self.flags.contains(*flag) {
+                    self.branch(label)?
+                }
+            }
+            Instruction::YLD(rx, stream) => self.yield_register(rx, *stream)?,
             Instruction::LBL(_) => (),
+            // the following advance leaves the program counter past the end of the program.
+            Instruction::HALT => self.pc = self.instructions.len(),
+            Instruction::TRAP => return Err(VmError::Trap { pc: self.pc }),
             Instruction::CHK(flag) => {
                 if !self.flags.contains(*flag) {
                     self.advance(1)
                 }
             }
-            Instruction::STR(data, addr, mode) => self.store(addr, &self.get(data), mode),
+            Instruction::STR(data, addr, mode) => self.store(addr, &self.get(data)?, mode)?,
             Instruction::LDR(rd, addr, mode) => {
-                let data = &self.load(addr, mode);
+                let data = &self.load(addr, mode)?;
                 self.save(rd, data)
             }
         }
+        Ok(())
+    }
+
+    /// Reserve `bytes` from the heap, rounded up to a whole number of words so that every
+    /// allocation is word aligned, returning the address of the first.
+    fn allocate(&mut self, bytes: usize) -> Result<usize, VmError> {
+        let available = self.memory.len().saturating_sub(self.heap);
+        match bytes.checked_next_multiple_of(WORD_SIZE) {
+            Some(reserved) if reserved <= available => {
+                let address = self.heap;
+                self.heap += reserved;
+                Ok(address)
+            }
+            _ => Err(VmError::HeapExhausted {
+                requested: bytes,
+                available,
+                pc: self.pc,
+            }),
+        }
+    }
+
+    /// Handle the result of an arithmetic instruction as the overflow mode says, clamping it to
+    /// `saturated` or erroring if it overflowed.
+    fn overflowing(
+        &self,
+        (value, overflowed): (u32, bool),
+        saturated: u32,
+    ) -> Result<u32, VmError> {
+        match (overflowed, self.vm_flags.overflow_mode) {
+            (false, _) | (true, OverflowMode::Wrapping) => Ok(value),
+            (true, OverflowMode::Saturating) => Ok(saturated),
+            (true, OverflowMode::Trapping) => Err(VmError::ArithmeticOverflow { pc: self.pc }),
+        }
     }
 
-    /// Get the value in a register, unchecked.
-    fn get(&self, reg: &Reg) -> u32 {
-        let val = *self.registers.get(&(*reg)).unwrap();
+    /// Get the value in a register to divide by, erroring if it is zero.
+    fn divisor(&self, reg: &Reg) -> Result<u32, VmError> {
+        match self.get(reg)? {
+            0 => Err(VmError::DivisionByZero { pc: self.pc }),
+            divisor => Ok(divisor),
+        }
+    }
+
+    /// Get the value in a register, erroring if nothing has been saved in it.
+    fn get(&self, reg: &Reg) -> Result<u32, VmError> {
+        let val = match self.registers.get(**reg as usize) {
+            Some(val) => *val,
+            None => self.sparse_registers.get(reg).copied(),
+        }
+        .ok_or(VmError::UninitialisedRegister {
+            reg: *reg,
+            pc: self.pc,
+        })?;
 
         if self.log_is_very_verbose() {
             debug!("getting {reg}, got {val}")
         }
-        val
+        Ok(val)
     }
 
     /// Save a value in a register
     fn save(&mut self, reg: &Reg, val: &u32) {
         if self.log_is_very_verbose() {
-            debug!("saving {reg} with value {val}")
+            debug!("saving {} with value {val}", self.describe_register(*reg))
+        }
+        let previous = self.set_register(**reg, Some(*val));
+        if let Some(snapshot) = self.snapshot() {
+            snapshot.registers.push((**reg, previous));
         }
-        self.registers.insert(**reg, *val);
     }
 
-    fn store(&mut self, rd: &Reg, value: &u32, mode: &Mode) {
-        let bytes = value.to_be_bytes();
+    /// Set or unset a register, returning its previous value.
+    fn set_register(&mut self, reg: u32, val: Option<u32>) -> Option<u32> {
+        match (self.registers.get_mut(reg as usize), val) {
+            (Some(slot), val) => std::mem::replace(slot, val),
+            (None, Some(val)) => self.sparse_registers.insert(reg, val),
+            (None, None) => self.sparse_registers.remove(&reg),
+        }
+    }
 
-        let addr = match mode {
-            Mode::None | Mode::PostOffset(_) => self.get(rd) as usize,
-            Mode::Offset(r_ofst) => (self.get(rd) + self.get(&r_ofst)) as usize,
-            Mode::PreOffset(r_ofst) => {
-                let addr = self.get(rd) + self.get(&r_ofst);
-                self.save(rd, &addr);
-                addr as usize
-            }
-        };
+    fn store(&mut self, rd: &Reg, value: &u32, mode: &Mode) -> Result<(), VmError> {
+        let bytes = value.to_be_bytes();
+        let addr = self.address(rd, mode)?;
+        let range = self.word_range(addr)?;
 
-        for (i, byte) in bytes.iter().enumerate() {
-            let mem: &mut u8 = self
-                .memory
-                .get_mut(addr + i)
-                .expect("error handling needed in vm");
-            *mem = *byte;
+        if self.vm_flags.trace_memory {
+            debug!("store {bytes:02x?} at {addr:#x} ({mode:?})")
         }
-
-        match mode {
-            Mode::PostOffset(r_ofst) => {
-                let addr = self.get(rd) + self.get(&r_ofst);
-                self.save(rd, &addr);
+        for (i, byte) in range.zip(bytes) {
+            let previous = std::mem::replace(&mut self.memory[i], byte);
+            if let Some(snapshot) = self.snapshot() {
+                snapshot.memory.push((i, previous));
             }
-            _ => (),
         }
+
+        self.post_offset(rd, mode)
     }
 
     // consider a storeb variant
 
-    fn load(&mut self, rd: &Reg, mode: &Mode) -> u32 {
-        let mut bytes: [u8; 4] = [0; 4];
+    fn load(&mut self, rd: &Reg, mode: &Mode) -> Result<u32, VmError> {
+        let addr = self.address(rd, mode)?;
+        let range = self.word_range(addr)?;
+        let mut bytes = [0; WORD_SIZE];
+        bytes.copy_from_slice(&self.memory[range]);
+        if self.vm_flags.trace_memory {
+            debug!("load {bytes:02x?} from {addr:#x} ({mode:?})")
+        }
+
+        self.post_offset(rd, mode)?;
+        Ok(u32::from_be_bytes(bytes))
+    }
 
-        let addr = match mode {
-            Mode::None | Mode::PostOffset(_) => self.get(rd) as usize,
-            Mode::Offset(r_ofst) => (self.get(rd) + self.get(&r_ofst)) as usize,
+    /// The address a load or store accesses, writing it back to the address register first for a
+    /// pre-offset. Addresses wrap, as arithmetic does.
+    fn address(&mut self, rd: &Reg, mode: &Mode) -> Result<usize, VmError> {
+        Ok(match mode {
+            Mode::None | Mode::PostOffset(_) => self.get(rd)? as usize,
+            Mode::Offset(r_ofst) => self.get(rd)?.wrapping_add(self.get(r_ofst)?) as usize,
             Mode::PreOffset(r_ofst) => {
-                let addr = self.get(rd) + self.get(&r_ofst);
+                let addr = self.get(rd)?.wrapping_add(self.get(r_ofst)?);
                 self.save(rd, &addr);
                 addr as usize
             }
-        };
-
-        for i in 0..4 {
-            bytes[i] = self.memory[addr + i];
-        }
+        })
+    }
 
+    /// Write the offset address back to the address register after a post-offset access.
+    fn post_offset(&mut self, rd: &Reg, mode: &Mode) -> Result<(), VmError> {
         if let Mode::PostOffset(r_ofst) = mode {
-            let addr = self.get(rd) + self.get(&r_ofst);
+            let addr = self.get(rd)?.wrapping_add(self.get(r_ofst)?);
             self.save(rd, &addr);
         }
+        Ok(())
+    }
+
+    /// The range of bytes in the word at an address, erroring if any are outside of memory.
+    fn word_range(&self, addr: usize) -> Result<Range<usize>, VmError> {
+        match addr.checked_add(WORD_SIZE) {
+            Some(end) if end <= self.memory.len() => Ok(addr..end),
+            _ => Err(VmError::MemoryFault {
+                addr,
+                memory_size: self.memory.len(),
+                pc: self.pc,
+            }),
+        }
+    }
+
+    /// Read the word starting at a memory address.
+    pub fn read_word(&self, addr: usize) -> Result<u32, VmError> {
+        let bytes = addr
+            .checked_add(WORD_SIZE)
+            .and_then(|end| self.memory.get(addr..end))
+            .ok_or(VmError::OutOfBoundsAccess {
+                addr,
+                memory_size: self.memory.len(),
+            })?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
 
-        u32::from_be_bytes(bytes)
+    /// Read consecutive words starting at each word aligned offset in a range of memory addresses.
+    /// Reading stops at the first word that does not fit within memory.
+    pub fn read_words(&self, range: Range<usize>) -> Vec<u32> {
+        range
+            .step_by(WORD_SIZE)
+            .map_while(|addr| self.read_word(addr).ok())
+            .collect()
+    }
+
+    /// Render the program counter, flags, and every set register, described as by
+    /// [`Machine::describe_register`].
+    pub fn dump_state(&self) -> String {
+        let mut dump = format!("pc: {}\nflags: {:#010b}\n", self.pc, self.flags.0);
+        let mut sparse: Vec<(&u32, &u32)> = self.sparse_registers.iter().collect();
+        sparse.sort();
+        let registers = self
+            .registers
+            .iter()
+            .enumerate()
+            .filter_map(|(reg, val)| Some((reg as u32, (*val)?)))
+            .chain(sparse.into_iter().map(|(reg, val)| (*reg, *val)));
+        for (reg, val) in registers {
+            dump.push_str(&format!("{} = {val}\n", self.describe_register(Reg(reg))));
+        }
+        dump
+    }
+
+    /// Name a register for debugging, as `%0 (variable `x`)` if it holds a variable, or
+    /// `%1 (temporary)` if the compiler made it for part of an expression. Registers are only told
+    /// apart when the machine knows the program's variables, such as one made from a module.
+    pub fn describe_register(&self, reg: Reg) -> String {
+        match self.symbols.get(&reg) {
+            Some(name) => format!("{reg} (variable `{name}`)"),
+            None if self.symbols.is_empty() => reg.to_string(),
+            None => format!("{reg} (temporary)"),
+        }
+    }
+
+    /// Returns the source of the instruction that caused an error. Only machines made with
+    /// [`Machine::from_air_module`] know where their instructions came from, and instructions
+    /// without a source, such as those of hand-written AIR, have an empty span.
+    pub fn error_span(&self, error: &VmError) -> Option<Span> {
+        error
+            .pc()
+            .and_then(|pc| self.spans.get(pc))
+            .copied()
+            .filter(|span| span.span().0 < span.span().1)
+    }
+
+    /// Render a range of memory as hex, sixteen bytes to a line, each line prefixed with its address.
+    pub fn memory_hex(&self, range: Range<usize>) -> String {
+        let end = range.end.min(self.memory.len());
+        let start = range.start.min(end);
+
+        self.memory[start..end]
+            .chunks(16)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let bytes: Vec<String> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+                format!("{:#06x}: {}\n", start + i * 16, bytes.join(" "))
+            })
+            .collect()
     }
 
     /// Yield a value in a register from the program. This passes the value to the yield callback
-    fn yield_register(&mut self, reg: &Reg) {
-        let val: u32 = self.get(reg);
+    fn yield_register(&mut self, reg: &Reg, stream: Stream) -> Result<(), VmError> {
+        let val: u32 = self.get(reg)?;
         if self.log_is_normal() {
             debug!("yielding {val}")
         }
 
+        if self.yield_history_size > 0 {
+            if self.recent_yields.len() == 2 * self.yield_history_size {
+                self.recent_yields.drain(..self.yield_history_size);
+            }
+            self.recent_yields.push(val);
+        }
+
         self.yield_callback
-            .send(Message::Yield(val))
-            .expect("oh no!") // this requires better handling
+            .send(Message::Yield { stream, value: val })
+            .map_err(|_| VmError::OutputClosed)
     }
 
-    /// Branch to a label, panics if the label doesn't exist
-    fn branch(&mut self, label: &str) {
+    /// Branch to a label, erroring if the label doesn't exist
+    fn branch(&mut self, label: &str) -> Result<(), VmError> {
         match self.find_label(label) {
-            None => panic!("expected label {label} to exist in the program"),
+            None => Err(VmError::UnknownLabel {
+                label: label.to_owned(),
+                pc: self.pc,
+            }),
             Some(idx) => {
                 if self.log_is_verbose() {
                     debug!("branching to {label}, pc = {idx}")
                 }
-                self.pc = idx
+                self.pc = idx;
+                Ok(())
             }
         }
     }
 
     /// Find the index of the first label with the specified name if it exists
     fn find_label(&self, label: &str) -> Option<usize> {
-        self.instructions
-            .iter()
-            .enumerate()
-            .find(|(_, inst)| **inst == Instruction::LBL(label.to_string()))
-            .map(|(i, _)| i)
+        self.labels.get(label).copied()
     }
 
-    fn set_flags(&mut self, rx: &Reg, ry: &Reg) {
-        let x = self.get(rx);
-        let y = self.get(ry);
-
-        if x == y {
+    /// Set the flags from the ordering of a comparison's first operand relative to its second.
+    /// Flags from any earlier comparison are cleared, leaving only `Al` and the relations that hold.
+    fn set_flags(&mut self, ordering: Ordering) {
+        self.flags = Flags::empty();
+        if ordering.is_eq() {
             self.flags.set(Flag::Eq)
         }
-        if x != y {
+        if ordering.is_ne() {
             self.flags.set(Flag::Ne)
         }
-        if x < y {
+        if ordering.is_lt() {
             self.flags.set(Flag::Lt)
         }
-        if x <= y {
+        if ordering.is_le() {
             self.flags.set(Flag::Le)
         }
-        if x > y {
+        if ordering.is_gt() {
             self.flags.set(Flag::Gt)
         }
-        if x >= y {
+        if ordering.is_ge() {
             self.flags.set(Flag::Ge)
         }
 
@@ -280,6 +900,16 @@ impl Machine {
     }
 }
 
+/// Divide signed values, rounding the quotient as given. Dividing the minimum value by `-1` wraps.
+fn divide_signed(x: i32, y: i32, rounding: Rounding) -> i32 {
+    let quotient = x.wrapping_div(y);
+    match rounding {
+        Rounding::Truncate => quotient,
+        Rounding::Floor if x.wrapping_rem(y) != 0 && (x < 0) != (y < 0) => quotient - 1,
+        Rounding::Floor => quotient,
+    }
+}
+
 struct Flags(u16);
 
 impl Flags {
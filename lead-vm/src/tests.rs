@@ -1,8 +1,9 @@
 use super::*;
 use lead::air::air::{
-    Flag,
+    Flag, Inst,
     Instruction::{self, *},
 };
+use lead::lex::span::Span;
 use log::{info, warn};
 use ntest::timeout;
 use std::sync::mpsc::channel;
@@ -19,6 +20,14 @@ const R3: Reg = Reg(3);
 // const R4: Reg = Reg(4);
 // const R5: Reg = Reg(5);
 
+/// A value yielded to the program's output.
+fn out(value: u32) -> Message {
+    Message::Yield {
+        stream: Stream::Out,
+        value,
+    }
+}
+
 // fn init() {
 //     let _ = env_logger::builder().is_test(true).try_init();
 // }
@@ -33,11 +42,11 @@ fn it_works() {
 #[test]
 fn r#yield() {
     warn!("checking!");
-    let instructions = vec![CON(R0, 5), YLD(R0)];
+    let instructions = vec![CON(R0, 5), YLD(R0, Stream::Out)];
     let (sndr, recvr) = channel();
     let mut vm = Machine::new(instructions, sndr, VERY_VERBOSE);
     vm.run();
-    assert_eq!(Ok(Message::Yield(5)), recvr.recv())
+    assert_eq!(Ok(out(5)), recvr.recv())
 }
 
 #[test]
@@ -45,15 +54,38 @@ fn branch_simple() {
     let instructions = vec![
         BRA("label".to_owned()),
         CON(R0, 5),
-        YLD(R0),
+        YLD(R0, Stream::Out),
         LBL("label".to_owned()),
         CON(R0, 17),
-        YLD(R0),
+        YLD(R0, Stream::Out),
     ];
     let (sndr, recvr) = channel();
     let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
     vm.run();
-    assert_eq!(Ok(Message::Yield(17)), recvr.recv())
+    assert_eq!(Ok(out(17)), recvr.recv())
+}
+
+#[timeout(5000)]
+#[test]
+fn long_loop_branches_to_first_label() {
+    let instructions = vec![
+        CON(R0, 0),
+        CON(R1, 100_000),
+        LBL("loop".to_owned()),
+        INC(R0),
+        CMP(R0, R1, Some(Flag::Lt)),
+        BRAcc(Flag::Lt, "loop".to_owned()),
+        YLD(R0, Stream::Out),
+        HALT,
+        // a later label with the same name is never branched to.
+        LBL("loop".to_owned()),
+        CON(R0, 0),
+        YLD(R0, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+    assert_eq!(Ok(out(100_000)), recvr.recv());
+    assert_eq!(Ok(Message::Done), recvr.recv());
 }
 
 #[test]
@@ -64,7 +96,7 @@ fn store_and_load() {
         STR(R0, R1, Mode::None), // str r0, [r1]
         ADD(R1, R1, R1),         // nop
         LDR(R2, R1, Mode::None), // ldr r2, [r1]
-        YLD(R2),                 // yield r2
+        YLD(R2, Stream::Out),    // yield r2
     ];
 
     let (sndr, recvr) = channel();
@@ -72,7 +104,34 @@ fn store_and_load() {
     vm.run();
 
     assert_eq!(vm.memory[0..4], vec![0xde, 0xad, 0xbe, 0xef]);
-    assert_eq!(Ok(Message::Yield(0xdeadbeef)), recvr.recv())
+    assert_eq!(Ok(out(0xdeadbeef)), recvr.recv())
+}
+
+#[test]
+fn read_stored_words() {
+    let instructions = vec![
+        CON(R0, 0xdeadbeef),
+        CON(R1, 0x1234),
+        CON(R2, 4),
+        CON(R3, 0),
+        STR(R0, R3, Mode::PostOffset(R2)),
+        STR(R1, R3, Mode::PostOffset(R2)),
+    ];
+
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.run();
+
+    assert_eq!(vec![0xdeadbeef, 0x1234], vm.read_words(0..8));
+    assert_eq!(Ok(0x1234), vm.read_word(4));
+    assert_eq!(
+        Err(VmError::OutOfBoundsAccess {
+            addr: DEFAULT_MEMORY_SIZE - 2,
+            memory_size: DEFAULT_MEMORY_SIZE
+        }),
+        vm.read_word(DEFAULT_MEMORY_SIZE - 2)
+    );
+    assert_eq!("0x0000: de ad be ef 00 00 12 34\n", vm.memory_hex(0..8));
 }
 
 #[timeout(1000)]
@@ -85,18 +144,18 @@ fn loop_not_taken() {
         CMP(R0, R1, None),             //     cmp r0, r1
         CHK(Flag::Gt),                 //
         BRA("break".into()),           //     bgt break
-        YLD(R1),                       //     yld r1
+        YLD(R1, Stream::Out),          //     yld r1
         BRA("check-condition".into()), //     b check-condition
         LBL("break".into()),           // break:
         CON(R2, 0xbeef),               //     mov r2, #0xbeef
-        YLD(R2),                       //     yld r2
+        YLD(R2, Stream::Out),          //     yld r2
     ];
 
     let (sndr, recvr) = channel();
     let mut vm = Machine::new(instructions, sndr, VERY_VERBOSE);
     vm.run();
 
-    assert_eq!(Ok(Message::Yield(0xbeef)), recvr.recv())
+    assert_eq!(Ok(out(0xbeef)), recvr.recv())
 }
 
 #[test]
@@ -109,22 +168,943 @@ fn loop_and_break() {
         CMP(R0, R1, None),             //      cmp r0, r1
         CHK(Flag::Ge),                 //
         BRA("break".into()),           //      bge break
-        YLD(R0),                       //      yld r0
+        YLD(R0, Stream::Out),          //      yld r0
         CON(R2, 1),                    //      mov r2, #1
         ADD(R0, R0, R2),               //      add r0, r2
         BRA("check-condition".into()), //      b check-condition
         LBL("break".into()),           // break:
         CON(R3, 64),                   // mov r3, #64
-        YLD(R3),                       // yld r3
+        YLD(R3, Stream::Out),          // yld r3
     ];
 
     let (sndr, recvr) = channel();
     let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
     vm.run();
 
-    assert_eq!(Ok(Message::Yield(1)), recvr.recv());
-    assert_eq!(Ok(Message::Yield(2)), recvr.recv());
-    assert_eq!(Ok(Message::Yield(3)), recvr.recv());
-    assert_eq!(Ok(Message::Yield(4)), recvr.recv());
-    assert_eq!(Ok(Message::Yield(64)), recvr.recv())
+    assert_eq!(Ok(out(1)), recvr.recv());
+    assert_eq!(Ok(out(2)), recvr.recv());
+    assert_eq!(Ok(out(3)), recvr.recv());
+    assert_eq!(Ok(out(4)), recvr.recv());
+    assert_eq!(Ok(out(64)), recvr.recv())
+}
+
+#[test]
+fn signed_comparison() {
+    let instructions = vec![
+        CON(R0, -1i32 as u32),
+        CON(R1, 1),
+        SCMP(R0, R1, Some(Flag::Lt)),
+    ];
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::new(instructions.clone(), sndr.clone(), NO_FLAGS);
+    vm.run();
+    assert!(vm.flags.contains(Flag::Lt));
+    assert!(!vm.flags.contains(Flag::Gt));
+
+    let mut instructions = instructions;
+    instructions[2] = CMP(R0, R1, Some(Flag::Lt));
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.run();
+    assert!(vm.flags.contains(Flag::Gt));
+    assert!(!vm.flags.contains(Flag::Lt));
+}
+
+#[test]
+fn comparison_clears_earlier_flags() {
+    let instructions = vec![
+        CON(R0, 1),
+        CON(R1, 2),
+        CMP(R0, R1, Some(Flag::Lt)),
+        CMP(R1, R0, Some(Flag::Gt)),
+    ];
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.run();
+    assert!(vm.flags.contains(Flag::Gt));
+    assert!(vm.flags.contains(Flag::Al));
+    assert!(!vm.flags.contains(Flag::Lt));
+    assert!(!vm.flags.contains(Flag::Le));
+}
+
+#[test]
+fn comparison_accumulates_flags() {
+    // every relation that holds is set together, and none that do not.
+    let instructions = vec![CON(R0, 2), CON(R1, 1), CMP(R0, R1, Some(Flag::Gt))];
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.run();
+    for flag in [Flag::Al, Flag::Gt, Flag::Ge, Flag::Ne] {
+        assert!(vm.flags.contains(flag), "{flag} should be set");
+    }
+    for flag in [Flag::Eq, Flag::Lt, Flag::Le, Flag::Nv] {
+        assert!(!vm.flags.contains(flag), "{flag} should not be set");
+    }
+}
+
+#[test]
+fn conditional_branch() {
+    let instructions = vec![
+        CON(R0, 1),
+        CON(R1, 2),
+        CMP(R0, R1, Some(Flag::Lt)),
+        BRAcc(Flag::Ge, "skip".to_string()),
+        YLD(R0, Stream::Out),
+        LBL("skip".to_string()),
+        CMP(R1, R0, Some(Flag::Gt)),
+        BRAcc(Flag::Gt, "end".to_string()),
+        YLD(R1, Stream::Out),
+        LBL("end".to_string()),
+    ];
+    let (sndr, recvr) = channel();
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+    assert_eq!(Ok(out(1)), recvr.recv());
+    assert_eq!(Ok(Message::Done), recvr.recv());
+}
+
+/// Returns whether a `CHK` of `flag` after comparing `x` with `y` branches.
+fn branches(x: i32, y: i32, flag: Flag) -> bool {
+    let instructions = vec![
+        CON(R0, x as u32),
+        CON(R1, y as u32),
+        SCMP(R0, R1, Some(flag)),
+        CHK(flag),
+        BRA("taken".to_string()),
+        CON(R2, 0),
+        YLD(R2, Stream::Out),
+        HALT,
+        LBL("taken".to_string()),
+        CON(R2, 1),
+        YLD(R2, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+    recvr.recv() == Ok(out(1))
+}
+
+#[test]
+fn every_comparison_branches_both_ways() {
+    for flag in [Flag::Eq, Flag::Ne, Flag::Lt, Flag::Le, Flag::Gt, Flag::Ge] {
+        for (x, y) in [(1, 2), (2, 2), (3, 2), (-1, 2)] {
+            let holds = match flag {
+                Flag::Eq => x == y,
+                Flag::Ne => x != y,
+                Flag::Lt => x < y,
+                Flag::Le => x <= y,
+                Flag::Gt => x > y,
+                _ => x >= y,
+            };
+            assert_eq!(holds, branches(x, y, flag), "{x} {flag} {y}");
+            // the negated flag is checked for a condition failing, so it must branch otherwise.
+            assert_eq!(!holds, branches(x, y, flag.negate()), "not {x} {flag} {y}");
+        }
+    }
+}
+
+#[test]
+#[timeout(1000)]
+fn stop_from_another_thread() {
+    let instructions = vec![LBL("loop".to_string()), BRA("loop".to_string())];
+    let (sndr, recvr) = channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.stop_when(stop.clone());
+    let vm_thread = std::thread::spawn(move || vm.run());
+
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    stop.store(true, atomic::Ordering::Relaxed);
+    vm_thread.join().unwrap();
+    assert_eq!(Ok(Message::Done), recvr.recv());
+}
+
+#[test]
+fn run_air_module() {
+    let instructions = vec![
+        Inst::new(CON(R0, 5), Span::new((0, 1))),
+        Inst::new(BRA("end".to_string()), Span::new((0, 1))),
+        Inst::new(CON(R0, 6), Span::new((0, 1))),
+        Inst::new(LBL("end".to_string()), Span::new((0, 1))),
+        Inst::new(YLD(R0, Stream::Out), Span::new((0, 1))),
+    ];
+    let mut module = AirModule::from_instructions(Vec::new());
+    module.instructions = instructions;
+    module.variables.insert("total".to_string(), R0);
+    module.static_memory = 2 * DEFAULT_MEMORY_SIZE;
+
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::from_air_module(module, sndr, NO_FLAGS);
+    assert_eq!(2 * DEFAULT_MEMORY_SIZE, vm.memory.len());
+    assert_eq!(Some(3), vm.find_label("end"));
+    vm.run();
+    assert_eq!(Ok(out(5)), recvr.recv());
+    assert!(vm.dump_state().contains("(variable `total`) = 5"));
+}
+
+#[test]
+fn dump_tells_variables_from_temporaries() {
+    let src = "let x := 2;\nlet y := x * 3 + 1;\nyield y;";
+    let tokens = lead::lex::Lexer::new(src).run().unwrap();
+    let ast = lead::parse::LangParser::new(&tokens)
+        .parse_statement(Vec::new())
+        .unwrap();
+    let mut state = lead::air::GenerationState::new();
+    let instructions = lead::air::generate_program(&mut state, ast)
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+    let module = AirModule::new(instructions, &state);
+    let x = module.variables["x"];
+
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::from_air_module(module, sndr, NO_FLAGS);
+    vm.run();
+    let dump = vm.dump_state();
+    assert!(
+        dump.contains(&format!("{x} (variable `x`) = 2\n")),
+        "{dump}"
+    );
+    // operators group to the right, so `y` is `x * (3 + 1)`.
+    assert!(dump.contains("(variable `y`) = 8\n"), "{dump}");
+    assert!(dump.contains("(temporary) = 3\n"), "{dump}");
+    assert!(dump.contains("(temporary) = 4\n"), "{dump}");
+
+    // without a module, nothing is known about the registers.
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::new(vec![CON(R0, 1)], sndr, NO_FLAGS);
+    vm.run();
+    assert!(vm.dump_state().ends_with("\n%0 = 1\n"));
+}
+
+#[test]
+fn break_on_line() {
+    let src = "let x := 5;\nlet y := x * 2;\nyield y;\nyield x;";
+    let tokens = lead::lex::Lexer::new(src).run().unwrap();
+    let ast = lead::parse::LangParser::new(&tokens)
+        .parse_statement(Vec::new())
+        .unwrap();
+    let mut state = lead::air::GenerationState::new();
+    let instructions = lead::air::generate_program(&mut state, ast)
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+    let module = AirModule::new(instructions, &state);
+
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::from_air_module(module, sndr, NO_FLAGS);
+    assert!(vm.run_until_line(src, 3));
+    assert!(matches!(vm.instructions[vm.pc], YLD(_, Stream::Out)));
+    assert!(vm.dump_state().contains("(variable `y`) = 10"));
+    assert!(recvr.try_recv().is_err());
+    // already paused on the line, so this doesn't move.
+    assert!(vm.run_until_line(src, 3));
+    assert!(vm.step());
+    assert_eq!(Ok(out(10)), recvr.recv());
+    assert!(!vm.run_until_line(src, 3));
+    assert_eq!(Ok(out(5)), recvr.recv());
+}
+
+#[test]
+fn overflowing_index_traps() {
+    // `i * 4` wraps to 4, which would read `a[1]`.
+    let src = "let a := [1, 2, 3];\nlet i := 1073741825;\nyield a[i];";
+    let run = |bounds_checks: bool| {
+        let tokens = lead::lex::Lexer::new(src).run().unwrap();
+        let ast = lead::parse::LangParser::new(&tokens)
+            .parse_statement(Vec::new())
+            .unwrap();
+        let mut state = lead::air::GenerationState::new();
+        state.set_bounds_checks(bounds_checks);
+        let instructions = lead::air::generate_program(&mut state, ast)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        let (sndr, recvr) = channel();
+        Machine::from_air_module(AirModule::new(instructions, &state), sndr, NO_FLAGS).run();
+        recvr.recv()
+    };
+    assert_eq!(Ok(out(2)), run(false));
+    assert!(matches!(
+        run(true),
+        Ok(Message::Error(VmError::Trap { .. }))
+    ));
+}
+
+#[test]
+fn recent_yields() {
+    let instructions = (1..=5)
+        .flat_map(|n| [CON(R0, n), YLD(R0, Stream::Out)])
+        .collect();
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.record_yields(3);
+    assert!(vm.recent_yields().is_empty());
+    vm.run();
+    assert_eq!(&[3, 4, 5], vm.recent_yields());
+}
+
+#[test]
+fn step_back() {
+    let instructions = vec![
+        CON(R0, 4),
+        CON(R1, 8),
+        STR(R0, R1, Mode::None),
+        CMP(R0, R1, None),
+        CON(R0, 9),
+    ];
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.record_history(16);
+
+    assert!(vm.step());
+    let registers = vm.registers.clone();
+    let memory = vm.memory.clone();
+    let flags = vm.flags.0;
+    assert!(vm.step());
+    assert!(vm.step());
+    assert_eq!(Ok(4), vm.read_word(8));
+
+    assert!(vm.step_back());
+    assert!(vm.step_back());
+    assert_eq!(1, vm.pc);
+    assert_eq!(registers, vm.registers);
+    assert_eq!(memory, vm.memory);
+    assert_eq!(flags, vm.flags.0);
+}
+
+#[test]
+fn step_back_is_bounded() {
+    let instructions = vec![CON(R0, 1), CON(R0, 2), CON(R0, 3), CON(R0, 4)];
+    let (sndr, _recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.record_history(2);
+
+    while vm.step() {}
+    assert!(vm.step_back());
+    assert!(vm.step_back());
+    assert!(!vm.step_back());
+    assert_eq!(2, vm.pc);
+    assert_eq!(Ok(2), vm.get(&R0));
+}
+
+#[test]
+fn signed_division_rounding() {
+    let instructions = vec![
+        CON(R0, -7i32 as u32),
+        CON(R1, 2),
+        SDIV(R2, R0, R1, Rounding::Truncate),
+        SDIV(R3, R0, R1, Rounding::Floor),
+        YLD(R2, Stream::Out),
+        YLD(R3, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.run();
+
+    assert_eq!(Ok(out(-3i32 as u32)), recvr.recv());
+    assert_eq!(Ok(out(-4i32 as u32)), recvr.recv());
+}
+
+#[test]
+fn floor_division_of_exact_and_positive_quotients() {
+    assert_eq!(3, divide_signed(7, 2, Rounding::Floor));
+    assert_eq!(-4, divide_signed(7, -2, Rounding::Floor));
+    assert_eq!(3, divide_signed(-6, -2, Rounding::Floor));
+    assert_eq!(-3, divide_signed(-6, 2, Rounding::Floor));
+    assert_eq!(i32::MIN, divide_signed(i32::MIN, -1, Rounding::Floor));
+}
+
+#[test]
+fn increment_and_decrement() {
+    let instructions = vec![
+        CON(R0, 5),
+        INC(R0),
+        INC(R0),
+        YLD(R0, Stream::Out),
+        DEC(R0),
+        YLD(R0, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.run();
+
+    assert_eq!(Ok(out(7)), recvr.recv());
+    assert_eq!(Ok(out(6)), recvr.recv());
+}
+
+#[test]
+fn test_bits() {
+    let tested = |value: u32, mask: u32| {
+        let (sndr, _recvr) = channel();
+        let mut vm = Machine::new(vec![CON(R0, value), TST(R0, mask)], sndr, NO_FLAGS);
+        vm.run();
+        vm.flags
+    };
+    assert!(tested(0b1010, 0b0101).contains(Flag::Eq));
+    assert!(!tested(0b1010, 0b0101).contains(Flag::Ne));
+    assert!(tested(0b1010, 0b0010).contains(Flag::Ne));
+    assert!(!tested(0b1010, 0b0010).contains(Flag::Eq));
+    assert!(tested(u32::MAX, 0).contains(Flag::Eq));
+}
+
+#[test]
+fn clear_bits() {
+    let instructions = vec![
+        CON(R0, 0b1111_0110),
+        BIC(R1, R0, 0b0101_0100),
+        YLD(R1, Stream::Out),
+        YLD(R0, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+
+    assert_eq!(Ok(out(0b1010_0010)), recvr.recv());
+    assert_eq!(Ok(out(0b1111_0110)), recvr.recv());
+}
+
+#[test]
+fn and_or() {
+    let instructions = vec![
+        CON(R0, 0b1100),
+        CON(R1, 0b1010),
+        AND(R2, R0, R1),
+        ORR(R3, R0, R1),
+        YLD(R2, Stream::Out),
+        YLD(R3, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+
+    assert_eq!(Ok(out(0b1000)), recvr.recv());
+    assert_eq!(Ok(out(0b1110)), recvr.recv());
+}
+
+#[test]
+fn cycle_count() {
+    let instructions = vec![
+        CYC(R0),
+        CON(R1, 1),
+        ADD(R1, R1, R1),
+        ADD(R1, R1, R1),
+        CYC(R2),
+        YLD(R0, Stream::Out),
+        YLD(R2, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.run();
+
+    assert_eq!(Ok(out(0)), recvr.recv());
+    assert_eq!(Ok(out(4)), recvr.recv());
+    assert_eq!(7, vm.steps());
+}
+
+#[test]
+fn arguments() {
+    let instructions = vec![
+        CON(R0, 1),
+        ARG(R1, R0),
+        YLD(R1, Stream::Out),
+        CON(R0, 2),
+        ARG(R1, R0),
+        YLD(R1, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.set_arguments(vec![4, 9]);
+    vm.run();
+
+    assert_eq!(Ok(out(9)), recvr.recv());
+    assert_eq!(
+        Ok(Message::Error(VmError::MissingArgument {
+            index: 2,
+            count: 2,
+            pc: 4,
+        })),
+        recvr.recv()
+    );
+}
+
+#[test]
+fn heap_allocation() {
+    let instructions = vec![
+        CON(R0, 6),
+        ALLOC(R1, R0),
+        CON(R0, 4),
+        ALLOC(R2, R0),
+        YLD(R1, Stream::Out),
+        YLD(R2, Stream::Out),
+        CON(R0, DEFAULT_MEMORY_SIZE as u32),
+        ALLOC(R3, R0),
+    ];
+    let (sndr, recvr) = channel();
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+
+    // the first allocation is rounded up to two words, so the second starts after it.
+    assert_eq!(Ok(out(0)), recvr.recv());
+    assert_eq!(Ok(out(8)), recvr.recv());
+    assert_eq!(
+        Ok(Message::Error(VmError::HeapExhausted {
+            requested: DEFAULT_MEMORY_SIZE,
+            available: DEFAULT_MEMORY_SIZE - 12,
+            pc: 7,
+        })),
+        recvr.recv()
+    );
+}
+
+#[test]
+fn store_past_memory() {
+    let instructions = vec![
+        CON(R0, 7),
+        CON(R1, 64),
+        STR(R0, R1, Mode::None),
+        YLD(R0, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, VMFlags::new(64, 0));
+    vm.run();
+    assert_eq!(
+        Ok(Message::Error(VmError::MemoryFault {
+            addr: 64,
+            memory_size: 64,
+            pc: 2
+        })),
+        recvr.recv()
+    );
+    assert_eq!(Ok(Message::Done), recvr.recv());
+
+    // an address near the top of the address space can not overflow when offset.
+    let instructions = vec![CON(R0, 7), CON(R1, u32::MAX - 1), STR(R0, R1, Mode::None)];
+    let (sndr, recvr) = channel();
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+    assert!(matches!(
+        recvr.recv(),
+        Ok(Message::Error(VmError::MemoryFault { pc: 2, .. }))
+    ));
+}
+
+#[test]
+fn overflow_modes() {
+    let instructions = vec![
+        CON(R0, u32::MAX),
+        CON(R1, 2),
+        ADD(R2, R0, R1),
+        YLD(R2, Stream::Out),
+        MUL(R2, R0, R1),
+        YLD(R2, Stream::Out),
+        SUB(R2, R1, R0),
+        YLD(R2, Stream::Out),
+        INC(R0),
+        YLD(R0, Stream::Out),
+    ];
+    let run = |overflow_mode| {
+        let (sndr, recvr) = channel();
+        let vm_flags = VMFlags {
+            overflow_mode,
+            ..NO_FLAGS
+        };
+        Machine::new(instructions.clone(), sndr, vm_flags).run();
+        recvr.iter().collect::<Vec<Message>>()
+    };
+
+    assert_eq!(
+        vec![out(1), out(u32::MAX - 1), out(3), out(0), Message::Done],
+        run(OverflowMode::Wrapping)
+    );
+    assert_eq!(
+        vec![
+            out(u32::MAX),
+            out(u32::MAX),
+            out(0),
+            out(u32::MAX),
+            Message::Done
+        ],
+        run(OverflowMode::Saturating)
+    );
+    assert_eq!(
+        vec![
+            Message::Error(VmError::ArithmeticOverflow { pc: 2 }),
+            Message::Done
+        ],
+        run(OverflowMode::Trapping)
+    );
+}
+
+#[test]
+fn preloaded_memory() {
+    let instructions = vec![
+        CON(R0, 4),
+        LDR(R1, R0, Mode::None),
+        YLD(R1, Stream::Out),
+        CON(R0, 4),
+        ALLOC(R2, R0),
+        YLD(R2, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    Machine::with_memory(
+        instructions,
+        vec![0, 0, 0, 1, 0, 0, 0, 2, 3],
+        sndr,
+        NO_FLAGS,
+    )
+    .run();
+
+    // the heap starts at the first word after the preloaded bytes.
+    assert_eq!(Ok(out(2)), recvr.recv());
+    assert_eq!(Ok(out(12)), recvr.recv());
+}
+
+/// A program that counts up forever, yielding each number.
+fn count_forever() -> Vec<Instruction> {
+    vec![
+        CON(R0, 0),
+        CON(R1, 1),
+        LBL("loop".to_owned()),
+        ADD(R0, R0, R1),
+        YLD(R0, Stream::Out),
+        BRA("loop".to_owned()),
+    ]
+}
+
+#[test]
+fn sandboxed_run() {
+    let instructions = vec![CON(R0, 7), YLD(R0, Stream::Out), YLD(R0, Stream::Err)];
+    let machine = Machine::new(instructions, channel().0, NO_FLAGS);
+    assert_eq!(Ok(vec![7]), machine.run_sandboxed(Sandbox::default()));
+}
+
+#[test]
+fn sandbox_step_limit() {
+    let sandbox = Sandbox {
+        max_steps: 100,
+        max_yields: usize::MAX,
+        ..Sandbox::default()
+    };
+    let machine = Machine::new(count_forever(), channel().0, NO_FLAGS);
+    assert_eq!(
+        Err(VmError::StepLimitExceeded { limit: 100 }),
+        machine.run_sandboxed(sandbox)
+    );
+}
+
+#[test]
+fn sandbox_memory_limit() {
+    let sandbox = Sandbox {
+        max_memory: DEFAULT_MEMORY_SIZE - 1,
+        ..Sandbox::default()
+    };
+    let machine = Machine::new(vec![CON(R0, 1)], channel().0, NO_FLAGS);
+    assert_eq!(
+        Err(VmError::MemoryLimitExceeded {
+            size: DEFAULT_MEMORY_SIZE,
+            limit: DEFAULT_MEMORY_SIZE - 1,
+        }),
+        machine.run_sandboxed(sandbox)
+    );
+}
+
+#[test]
+fn sandbox_output_limit() {
+    let sandbox = Sandbox {
+        max_yields: 10,
+        ..Sandbox::default()
+    };
+    let machine = Machine::new(count_forever(), channel().0, NO_FLAGS);
+    assert_eq!(
+        Err(VmError::OutputLimitExceeded { limit: 10 }),
+        machine.run_sandboxed(sandbox)
+    );
+}
+
+#[test]
+#[timeout(5000)]
+fn sandbox_timeout() {
+    let sandbox = Sandbox {
+        max_steps: u64::MAX,
+        max_yields: usize::MAX,
+        timeout: Duration::from_millis(10),
+        ..Sandbox::default()
+    };
+    let machine = Machine::new(count_forever(), channel().0, NO_FLAGS);
+    assert_eq!(
+        Err(VmError::Timeout {
+            limit: Duration::from_millis(10)
+        }),
+        machine.run_sandboxed(sandbox)
+    );
+}
+
+#[test]
+fn signed_remainder() {
+    let instructions = vec![
+        CON(R0, 17),
+        CON(R1, 5),
+        MOD(R2, R0, R1),
+        YLD(R2, Stream::Out),
+        CON(R0, -17i32 as u32),
+        MOD(R2, R0, R1),
+        YLD(R2, Stream::Out),
+        CON(R1, 0),
+        MOD(R2, R0, R1),
+    ];
+    let (sndr, recvr) = channel();
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+
+    assert_eq!(Ok(out(2)), recvr.recv());
+    assert_eq!(Ok(out(-2i32 as u32)), recvr.recv());
+    assert_eq!(
+        Ok(Message::Error(VmError::DivisionByZero { pc: 8 })),
+        recvr.recv()
+    );
+}
+
+#[test]
+fn division_by_zero() {
+    let instructions = vec![
+        CON(R0, 1),
+        YLD(R0, Stream::Out),
+        CON(R1, 0),
+        DIV(R2, R0, R1),
+        YLD(R0, Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::new(instructions, sndr, NO_FLAGS);
+    vm.run();
+
+    assert_eq!(Ok(out(1)), recvr.recv());
+    assert_eq!(
+        Ok(Message::Error(VmError::DivisionByZero { pc: 3 })),
+        recvr.recv()
+    );
+    assert_eq!(Ok(Message::Done), recvr.recv());
+}
+
+#[test]
+fn huge_register_numbers() {
+    let huge = Reg(4_000_000_000);
+    let (sndr, recvr) = channel();
+    let mut vm = Machine::new(vec![CON(huge, 1), YLD(huge, Stream::Out)], sndr, NO_FLAGS);
+    vm.run();
+    assert_eq!(Ok(out(1)), recvr.recv());
+    assert_eq!(Ok(Message::Done), recvr.recv());
+    assert!(vm.dump_state().contains("%4000000000 = 1"));
+}
+
+#[test]
+fn unset_registers_error() {
+    // R1 lies within the register file grown to hold R3, and Reg(100) beyond it.
+    for unset in [R1, Reg(100)] {
+        let (sndr, recvr) = channel();
+        Machine::new(vec![CON(R3, 7), MOV(R0, unset)], sndr, NO_FLAGS).run();
+        assert_eq!(
+            Ok(Message::Error(VmError::UninitialisedRegister {
+                reg: unset,
+                pc: 1
+            })),
+            recvr.recv()
+        );
+        assert_eq!(Ok(Message::Done), recvr.recv());
+    }
+}
+
+#[test]
+fn errors_have_codes_and_help() {
+    use miette::Diagnostic;
+
+    let errors = [
+        VmError::OutOfBoundsAccess {
+            addr: 0x100,
+            memory_size: 0x100,
+        },
+        VmError::MemoryFault {
+            addr: 0x100,
+            memory_size: 0x100,
+            pc: 0,
+        },
+        VmError::DivisionByZero { pc: 0 },
+        VmError::ArithmeticOverflow { pc: 0 },
+        VmError::MissingArgument {
+            index: 0,
+            count: 0,
+            pc: 0,
+        },
+        VmError::Trap { pc: 0 },
+        VmError::HeapExhausted {
+            requested: 8,
+            available: 4,
+            pc: 0,
+        },
+        VmError::StepLimitExceeded { limit: 1 },
+        VmError::MemoryLimitExceeded { size: 2, limit: 1 },
+        VmError::OutputLimitExceeded { limit: 1 },
+        VmError::Timeout {
+            limit: Duration::from_millis(1),
+        },
+        VmError::UninitialisedRegister { reg: R0, pc: 0 },
+        VmError::UnknownLabel {
+            label: "missing".to_owned(),
+            pc: 0,
+        },
+    ];
+    for (n, error) in errors.iter().enumerate() {
+        let code = error.code().map(|code| code.to_string());
+        assert_eq!(Some(format!("R{:04}", n + 1)), code, "{error}");
+        assert!(error.help().is_some(), "{error} has no help");
+        assert!(lead::explain::explain(&code.unwrap()).is_some());
+    }
+    assert!(VmError::OutputClosed.code().is_some());
+}
+
+#[test]
+fn malformed_programs_error() {
+    let (sndr, recvr) = channel();
+    Machine::new(vec![MOV(R0, R1)], sndr, NO_FLAGS).run();
+    assert_eq!(
+        Ok(Message::Error(VmError::UninitialisedRegister {
+            reg: R1,
+            pc: 0
+        })),
+        recvr.recv()
+    );
+
+    let (sndr, recvr) = channel();
+    Machine::new(vec![BRA("missing".to_owned())], sndr, NO_FLAGS).run();
+    assert_eq!(
+        Ok(Message::Error(VmError::UnknownLabel {
+            label: "missing".to_owned(),
+            pc: 0
+        })),
+        recvr.recv()
+    );
+
+    let (sndr, recvr) = channel();
+    let instructions = vec![
+        CON(R0, DEFAULT_MEMORY_SIZE as u32 - 2),
+        LDR(R1, R0, Mode::None),
+    ];
+    Machine::new(instructions, sndr, NO_FLAGS).run();
+    assert_eq!(
+        Ok(Message::Error(VmError::MemoryFault {
+            addr: DEFAULT_MEMORY_SIZE - 2,
+            memory_size: DEFAULT_MEMORY_SIZE,
+            pc: 1
+        })),
+        recvr.recv()
+    );
+
+    // a check at the end of the program skips past it, which ends the program.
+    let (sndr, recvr) = channel();
+    Machine::new(vec![CHK(Flag::Nv)], sndr, NO_FLAGS).run();
+    assert_eq!(Ok(Message::Done), recvr.recv());
+}
+
+/// A linear congruential generator, so the generated programs are the same between runs.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+
+    fn reg(&mut self) -> Reg {
+        Reg(self.next() % 4)
+    }
+
+    fn word(&mut self) -> u32 {
+        // mostly small values, so addresses are sometimes in range, with the odd extreme one.
+        match self.next() % 8 {
+            0 => u32::MAX - self.next() % 4,
+            _ => self.next() % 24,
+        }
+    }
+
+    fn label(&mut self) -> String {
+        ["a", "b", "c"][self.next() as usize % 3].to_owned()
+    }
+
+    fn flag(&mut self) -> Flag {
+        let flags = [
+            Flag::Al,
+            Flag::Eq,
+            Flag::Ne,
+            Flag::Lt,
+            Flag::Le,
+            Flag::Gt,
+            Flag::Ge,
+            Flag::Nv,
+        ];
+        flags[self.next() as usize % flags.len()]
+    }
+
+    fn mode(&mut self) -> Mode {
+        match self.next() % 4 {
+            0 => Mode::None,
+            1 => Mode::Offset(self.reg()),
+            2 => Mode::PreOffset(self.reg()),
+            _ => Mode::PostOffset(self.reg()),
+        }
+    }
+
+    fn instruction(&mut self) -> Instruction {
+        let kinds = Instruction::kinds();
+        match kinds[self.next() as usize % kinds.len()] {
+            ADD(..) => ADD(self.reg(), self.reg(), self.reg()),
+            SUB(..) => SUB(self.reg(), self.reg(), self.reg()),
+            MUL(..) => MUL(self.reg(), self.reg(), self.reg()),
+            DIV(..) => DIV(self.reg(), self.reg(), self.reg()),
+            SDIV(..) => SDIV(self.reg(), self.reg(), self.reg(), Rounding::Floor),
+            MOD(..) => MOD(self.reg(), self.reg(), self.reg()),
+            INC(_) => INC(self.reg()),
+            DEC(_) => DEC(self.reg()),
+            CYC(_) => CYC(self.reg()),
+            ARG(..) => ARG(self.reg(), self.reg()),
+            ALLOC(..) => ALLOC(self.reg(), self.reg()),
+            CON(..) => CON(self.reg(), self.word()),
+            LEA(..) => LEA(self.reg(), self.word() as usize),
+            MOV(..) => MOV(self.reg(), self.reg()),
+            NOT(..) => NOT(self.reg(), self.reg()),
+            TST(..) => TST(self.reg(), self.word()),
+            BIC(..) => BIC(self.reg(), self.reg(), self.word()),
+            AND(..) => AND(self.reg(), self.reg(), self.reg()),
+            ORR(..) => ORR(self.reg(), self.reg(), self.reg()),
+            CMP(..) => CMP(self.reg(), self.reg(), None),
+            SCMP(..) => SCMP(self.reg(), self.reg(), None),
+            CHK(_) => CHK(self.flag()),
+            STR(..) => STR(self.reg(), self.reg(), self.mode()),
+            LDR(..) => LDR(self.reg(), self.reg(), self.mode()),
+            LBL(_) => LBL(self.label()),
+            BRA(_) => BRA(self.label()),
+            BRAcc(..) => BRAcc(self.flag(), self.label()),
+            YLD(..) => YLD(self.reg(), Stream::Out),
+            HALT => HALT,
+            TRAP => TRAP,
+        }
+    }
+}
+
+#[test]
+fn random_programs_do_not_panic() {
+    let mut rng = Lcg(0x1ead);
+    for program in 0..2000 {
+        let length = 1 + rng.next() as usize % 24;
+        let instructions: Vec<Instruction> = (0..length).map(|_| rng.instruction()).collect();
+        let (sndr, recvr) = channel();
+        // some programs yield to a receiver that has already gone away.
+        if program % 2 == 0 {
+            drop(recvr);
+        }
+        let mut vm = Machine::new(instructions, sndr, VMFlags::new(16, 0));
+        vm.set_arguments(vec![1, 2]);
+        for _ in 0..500 {
+            if !vm.step() {
+                break;
+            }
+        }
+    }
 }
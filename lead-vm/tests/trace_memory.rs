@@ -0,0 +1,59 @@
+//! Memory tracing is checked in its own test binary, since it installs a logger to capture the
+//! log, and only one logger can be installed per process.
+
+use lead::air::air::{Instruction::*, Mode, Reg, Stream};
+use lead_vm::{Machine, Message, VMFlags};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::{mpsc::channel, Mutex};
+
+struct Capture(Mutex<Vec<String>>);
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+#[test]
+fn store_then_load_is_logged() {
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let instructions = vec![
+        CON(Reg(0), 0xbeef),
+        CON(Reg(1), 16),
+        STR(Reg(0), Reg(1), Mode::None),
+        LDR(Reg(2), Reg(1), Mode::None),
+        YLD(Reg(2), Stream::Out),
+    ];
+    let (sndr, recvr) = channel();
+    let vm_flags = VMFlags {
+        trace_memory: true,
+        ..VMFlags::new(64, 0)
+    };
+    Machine::new(instructions, sndr, vm_flags).run();
+    assert_eq!(
+        Ok(Message::Yield {
+            stream: Stream::Out,
+            value: 0xbeef
+        }),
+        recvr.recv()
+    );
+
+    let log = CAPTURE.0.lock().unwrap();
+    let accesses: Vec<&String> = log
+        .iter()
+        .filter(|line| line.starts_with("store") || line.starts_with("load"))
+        .collect();
+    assert_eq!(2, accesses.len());
+    assert_eq!("store [00, 00, be, ef] at 0x10 (None)", accesses[0]);
+    assert_eq!("load [00, 00, be, ef] from 0x10 (None)", accesses[1]);
+}
@@ -1,5 +1,9 @@
-use lead::air::air::{Inst, Instruction};
+use lead::air::{
+    air::{Inst, Instruction},
+    render_air,
+};
 use lead::lex::token::Token;
+use lead::parse::ast::{AstDocument, Statement};
 use leadc::pipeline::Pipeline;
 use serde::Serialize;
 use sourcespan::SourceSpan;
@@ -27,12 +31,21 @@ pub fn build_from_string(string: String) -> String {
         },
     };
 
-    let mut buf: String = String::new();
+    render_air(&instructions)
+}
+
+/// Parse a program, returning its syntax tree as versioned JSON, or the error if it does not parse.
+#[wasm_bindgen]
+pub fn ast_from_string(string: String) -> String {
+    let statements: Vec<Statement> = match Pipeline::Text(string, None).lex() {
+        Err(err) => return err.to_string(),
+        Ok(pipeline) => match pipeline.parse() {
+            Err(err) => return err.to_string(),
+            Ok(pipeline) => pipeline.into(),
+        },
+    };
 
-    for instruction in instructions {
-        buf.push_str(&format!("{instruction}"));
-    }
-    buf
+    serde_json::to_string(&AstDocument::new(statements)).unwrap()
 }
 
 #[wasm_bindgen]
@@ -1,6 +1,7 @@
 use crate::lex::span::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Reg(pub u32);
 
 impl std::ops::Deref for Reg {
@@ -23,7 +24,7 @@ impl std::fmt::Display for Reg {
 }
 
 /// A wrapper type on `Instruction` containing additional span information.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Inst {
     pub instruction: Instruction,
     pub span: Span,
@@ -60,23 +61,55 @@ impl Spans for Inst {
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Instruction {
     ADD(Reg, Reg, Reg),
     SUB(Reg, Reg, Reg),
     MUL(Reg, Reg, Reg),
+    /// Unsigned division, truncating the quotient.
     DIV(Reg, Reg, Reg),
+    /// Signed division, treating registers as two's complement, and rounding the quotient as given.
+    SDIV(Reg, Reg, Reg, Rounding),
+    /// Signed remainder, treating registers as two's complement. The remainder takes the sign of
+    /// the dividend, matching the quotient of a truncating `SDIV`.
+    MOD(Reg, Reg, Reg),
+
+    /// Add one to a register, in place.
+    INC(Reg),
+    /// Subtract one from a register, in place.
+    DEC(Reg),
+    /// Load the number of instructions the machine has executed so far, wrapping, into a register.
+    CYC(Reg),
+    /// Load the program argument indexed by the second register, counting from 0.
+    ARG(Reg, Reg),
+    /// Reserve the number of bytes in the second register from the heap, loading the address of
+    /// the first byte into the first register.
+    ALLOC(Reg, Reg),
 
     /// Introduce a constant
     CON(Reg, u32),
+    /// Load the address of a static allocation. This runs like `CON`, but keeps addresses apart
+    /// from numbers so they could be relocated.
+    LEA(Reg, usize),
     /// Move a value from one register to another
     MOV(Reg, Reg),
 
     // not really sure what the point of this was
     NOT(Reg, Reg),
+    /// Set flags by comparing the bits of a register selected by a mask with zero, so `Eq` is set
+    /// exactly when none of the selected bits are set.
+    TST(Reg, u32),
+    /// Bit clear, storing a register with the bits set in a mask cleared.
+    BIC(Reg, Reg, u32),
+    /// Bitwise and of two registers.
+    AND(Reg, Reg, Reg),
+    /// Bitwise or of two registers.
+    ORR(Reg, Reg, Reg),
 
     /// Compare two registers, and set flags. Contains an optional info flag, designating what flag was intended to be set.
     CMP(Reg, Reg, Option<Flag>),
+    /// Compare two registers as two's complement signed numbers, and set flags. Contains an optional info flag like `CMP`.
+    SCMP(Reg, Reg, Option<Flag>),
     CHK(Flag),
 
     /// Store a register in memory, at a memory address given by rx, with a memmory addressing mode
@@ -87,11 +120,46 @@ pub enum Instruction {
     LBL(String),
     ///
     BRA(String),
-    /// Yield a register. This returns the value in the register, and continues executing.
-    YLD(Reg),
+    /// Branch to a label if a flag is set, otherwise continue with the next instruction.
+    BRAcc(Flag, String),
+    /// Yield a register to an output stream. This returns the value in the register, and continues executing.
+    YLD(Reg, Stream),
+    /// Stop executing the program.
+    HALT,
+    /// Stop the program with an error, for a check made at runtime that failed.
+    TRAP,
+}
+
+/// Where a yielded value is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Stream {
+    /// The program's output, printed to stdout.
+    Out,
+    /// Diagnostic output, printed to stderr.
+    Err,
+}
+
+impl Stream {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "out" => Some(Self::Out),
+            "err" => Some(Self::Err),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// How the quotient of a signed division is rounded when it is not a whole number. The two only
+/// differ when exactly one operand is negative: `-7 / 2` truncates to `-3`, and floors to `-4`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Rounding {
+    /// Round towards zero.
+    Truncate,
+    /// Round towards negative infinity.
+    Floor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Mode {
     None,
     Offset(Reg),
@@ -106,14 +174,194 @@ impl Instruction {
             Self::SUB(r, _, _) => *r,
             Self::MUL(r, _, _) => *r,
             Self::DIV(r, _, _) => *r,
-            Self::CON(r, _) => *r,
-            Self::NOT(r, _) => *r,
+            Self::SDIV(r, _, _, _) => *r,
+            Self::MOD(r, _, _) => *r,
+            Self::AND(r, _, _) | Self::ORR(r, _, _) => *r,
+            Self::INC(r) | Self::DEC(r) | Self::CYC(r) => *r,
+            Self::CON(r, _) | Self::LEA(r, _) | Self::ARG(r, _) | Self::ALLOC(r, _) => *r,
+            Self::NOT(r, _) | Self::BIC(r, _, _) => *r,
+            Self::LDR(r, _, _) => *r,
             _ => return None,
         })
     }
+
+    /// Returns every register the instruction reads from.
+    pub fn read_registers(&self) -> Vec<Reg> {
+        match self {
+            Self::ADD(_, rx, ry)
+            | Self::SUB(_, rx, ry)
+            | Self::MUL(_, rx, ry)
+            | Self::DIV(_, rx, ry)
+            | Self::SDIV(_, rx, ry, _)
+            | Self::MOD(_, rx, ry)
+            | Self::AND(_, rx, ry)
+            | Self::ORR(_, rx, ry) => {
+                vec![*rx, *ry]
+            }
+            Self::CMP(rx, ry, _) | Self::SCMP(rx, ry, _) => vec![*rx, *ry],
+            Self::MOV(_, rx)
+            | Self::NOT(_, rx)
+            | Self::BIC(_, rx, _)
+            | Self::ARG(_, rx)
+            | Self::ALLOC(_, rx) => vec![*rx],
+            Self::TST(rx, _) => vec![*rx],
+            Self::STR(rd, adr, mode) => [vec![*rd, *adr], mode.registers()].concat(),
+            Self::LDR(_, adr, mode) => [vec![*adr], mode.registers()].concat(),
+            Self::YLD(rx, _) | Self::INC(rx) | Self::DEC(rx) => vec![*rx],
+            Self::CON(_, _)
+            | Self::LEA(_, _)
+            | Self::CYC(_)
+            | Self::CHK(_)
+            | Self::LBL(_)
+            | Self::BRA(_)
+            | Self::BRAcc(_, _)
+            | Self::HALT
+            | Self::TRAP => Vec::new(),
+        }
+    }
+
+    /// Returns every register the instruction writes to, including address registers updated by
+    /// pre and post offset addressing.
+    pub fn written_registers(&self) -> Vec<Reg> {
+        match self {
+            Self::MOV(rd, _) => vec![*rd],
+            Self::LDR(rd, adr, mode) if mode.writes_back() => vec![*rd, *adr],
+            Self::STR(_, adr, mode) if mode.writes_back() => vec![*adr],
+            instruction => instruction.output_register().into_iter().collect(),
+        }
+    }
+}
+
+impl Instruction {
+    /// One instruction of each kind, in the order they are declared, with placeholder operands.
+    pub fn kinds() -> [Instruction; 30] {
+        let (rd, rx, ry) = (Reg(0), Reg(1), Reg(2));
+        let label = || "label".to_owned();
+        [
+            Self::ADD(rd, rx, ry),
+            Self::SUB(rd, rx, ry),
+            Self::MUL(rd, rx, ry),
+            Self::DIV(rd, rx, ry),
+            Self::SDIV(rd, rx, ry, Rounding::Truncate),
+            Self::MOD(rd, rx, ry),
+            Self::INC(rd),
+            Self::DEC(rd),
+            Self::CYC(rd),
+            Self::ARG(rd, rx),
+            Self::ALLOC(rd, rx),
+            Self::CON(rd, 1),
+            Self::LEA(rd, 0),
+            Self::MOV(rd, rx),
+            Self::NOT(rd, rx),
+            Self::TST(rx, 1),
+            Self::BIC(rd, rx, 1),
+            Self::AND(rd, rx, ry),
+            Self::ORR(rd, rx, ry),
+            Self::CMP(rx, ry, None),
+            Self::SCMP(rx, ry, None),
+            Self::CHK(Flag::Eq),
+            Self::STR(rx, ry, Mode::None),
+            Self::LDR(rd, ry, Mode::None),
+            Self::LBL(label()),
+            Self::BRA(label()),
+            Self::BRAcc(Flag::Eq, label()),
+            Self::YLD(rx, Stream::Out),
+            Self::HALT,
+            Self::TRAP,
+        ]
+    }
+
+    /// The name of the instruction's kind, as it is declared.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ADD(_, _, _) => "ADD",
+            Self::SUB(_, _, _) => "SUB",
+            Self::MUL(_, _, _) => "MUL",
+            Self::DIV(_, _, _) => "DIV",
+            Self::SDIV(_, _, _, _) => "SDIV",
+            Self::MOD(_, _, _) => "MOD",
+            Self::INC(_) => "INC",
+            Self::DEC(_) => "DEC",
+            Self::CYC(_) => "CYC",
+            Self::ARG(_, _) => "ARG",
+            Self::ALLOC(_, _) => "ALLOC",
+            Self::CON(_, _) => "CON",
+            Self::LEA(_, _) => "LEA",
+            Self::MOV(_, _) => "MOV",
+            Self::NOT(_, _) => "NOT",
+            Self::TST(_, _) => "TST",
+            Self::BIC(_, _, _) => "BIC",
+            Self::AND(_, _, _) => "AND",
+            Self::ORR(_, _, _) => "ORR",
+            Self::CMP(_, _, _) => "CMP",
+            Self::SCMP(_, _, _) => "SCMP",
+            Self::CHK(_) => "CHK",
+            Self::STR(_, _, _) => "STR",
+            Self::LDR(_, _, _) => "LDR",
+            Self::LBL(_) => "LBL",
+            Self::BRA(_) => "BRA",
+            Self::BRAcc(_, _) => "BRAcc",
+            Self::YLD(_, _) => "YLD",
+            Self::HALT => "HALT",
+            Self::TRAP => "TRAP",
+        }
+    }
+
+    /// A one line description of what instructions of this kind do.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::ADD(_, _, _) => "add two registers",
+            Self::SUB(_, _, _) => "subtract the second register from the first",
+            Self::MUL(_, _, _) => "multiply two registers",
+            Self::DIV(_, _, _) => "divide two registers as unsigned numbers, truncating",
+            Self::SDIV(_, _, _, _) => {
+                "divide two registers as signed numbers, truncating or flooring (SDIVF)"
+            }
+            Self::MOD(_, _, _) => "the remainder of dividing two registers as signed numbers",
+            Self::INC(_) => "add one to a register in place",
+            Self::DEC(_) => "subtract one from a register in place",
+            Self::CYC(_) => "load the number of instructions executed so far into a register",
+            Self::ARG(_, _) => "load the program argument indexed by a register",
+            Self::ALLOC(_, _) => "reserve bytes from the heap, loading the address of the first",
+            Self::CON(_, _) => "load a constant into a register",
+            Self::LEA(_, _) => "load the address of a static allocation into a register",
+            Self::MOV(_, _) => "copy one register to another",
+            Self::NOT(_, _) => "invert the bits of a register",
+            Self::TST(_, _) => "set the flags by comparing the masked bits of a register with zero",
+            Self::BIC(_, _, _) => "clear the bits of a register that are set in a mask",
+            Self::AND(_, _, _) => "and the bits of two registers",
+            Self::ORR(_, _, _) => "or the bits of two registers",
+            Self::CMP(_, _, _) => "compare two registers as unsigned numbers, setting the flags",
+            Self::SCMP(_, _, _) => "compare two registers as signed numbers, setting the flags",
+            Self::CHK(_) => "skip the next instruction unless a flag is set",
+            Self::STR(_, _, _) => "store a register in memory at an address",
+            Self::LDR(_, _, _) => "load a register from memory at an address",
+            Self::LBL(_) => "mark a place in the program that can be branched to",
+            Self::BRA(_) => "branch to a label",
+            Self::BRAcc(_, _) => "branch to a label if a flag is set",
+            Self::YLD(_, _) => "yield a register to stdout, or to stderr with `err`",
+            Self::HALT => "stop the program",
+            Self::TRAP => "stop the program with an error",
+        }
+    }
+}
+
+impl Mode {
+    /// Returns the offset register of the addressing mode, if it has one.
+    pub fn registers(&self) -> Vec<Reg> {
+        match self {
+            Mode::None => Vec::new(),
+            Mode::Offset(r) | Mode::PreOffset(r) | Mode::PostOffset(r) => vec![*r],
+        }
+    }
+
+    /// Returns weather the addressing mode writes the computed address back to the address register.
+    pub fn writes_back(&self) -> bool {
+        matches!(self, Mode::PreOffset(_) | Mode::PostOffset(_))
+    }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Flag {
     /// Always
     Al,
@@ -135,6 +383,8 @@ pub enum Flag {
 
 impl Flag {
     /// Negate a flag, returning a flag such that CHK FLAG successedes exactly when the origonal flag fails.
+    /// A comparison sets every relation that holds between its operands, so after a comparison
+    /// exactly one of a flag and its negation is set.
     pub fn negate(self) -> Self {
         match self {
             Flag::Al => Flag::Nv,
@@ -156,17 +406,39 @@ impl std::fmt::Display for Instruction {
             Instruction::SUB(rd, rx, ry) => writeln!(f, "SUB {rd}, {rx}, {ry}"),
             Instruction::MUL(rd, rx, ry) => writeln!(f, "MUL {rd}, {rx}, {ry}"),
             Instruction::DIV(rd, rx, ry) => writeln!(f, "DIV {rd}, {rx}, {ry}"),
+            Instruction::SDIV(rd, rx, ry, rounding) => match rounding {
+                Rounding::Truncate => writeln!(f, "SDIV {rd}, {rx}, {ry}"),
+                Rounding::Floor => writeln!(f, "SDIVF {rd}, {rx}, {ry}"),
+            },
+            Instruction::MOD(rd, rx, ry) => writeln!(f, "MOD {rd}, {rx}, {ry}"),
             Instruction::NOT(rd, rx) => writeln!(f, "NOT {rd}, {rx}"),
+            Instruction::TST(rx, mask) => writeln!(f, "TST {rx}, ={mask:#x}"),
+            Instruction::BIC(rd, rx, mask) => writeln!(f, "BIC {rd}, {rx}, ={mask:#x}"),
+            Instruction::AND(rd, rx, ry) => writeln!(f, "AND {rd}, {rx}, {ry}"),
+            Instruction::ORR(rd, rx, ry) => writeln!(f, "ORR {rd}, {rx}, {ry}"),
+            Instruction::INC(rd) => writeln!(f, "INC {rd}"),
+            Instruction::DEC(rd) => writeln!(f, "DEC {rd}"),
+            Instruction::CYC(rd) => writeln!(f, "CYC {rd}"),
+            Instruction::ARG(rd, rx) => writeln!(f, "ARG {rd}, {rx}"),
+            Instruction::ALLOC(rd, rx) => writeln!(f, "ALLOC {rd}, {rx}"),
             Instruction::CMP(rx, ry, _) => writeln!(f, "CMP {rx}, {ry}"),
+            Instruction::SCMP(rx, ry, _) => writeln!(f, "SCMP {rx}, {ry}"),
 
             Instruction::CON(rd, constant) => writeln!(f, "CONST {rd}, ={constant:#x}"),
+            Instruction::LEA(rd, address) => writeln!(f, "LEA {rd}, [{address:#x}]"),
             Instruction::MOV(rd, rx) => writeln!(f, "MOV {rd}, {rx}"),
 
             Instruction::LBL(label) => writeln!(f, "{label}:"),
             Instruction::BRA(label) => writeln!(f, "BRA {label}"),
+            Instruction::BRAcc(flag, label) => writeln!(f, "BRA {label} if {flag}"),
 
             Instruction::CHK(flag) => writeln!(f, "CHK {flag}",),
-            Instruction::YLD(rx) => writeln!(f, "YLD {rx}"),
+            Instruction::YLD(rx, stream) => match stream {
+                Stream::Out => writeln!(f, "YLD {rx}"),
+                Stream::Err => writeln!(f, "YLD {rx}, err"),
+            },
+            Instruction::HALT => writeln!(f, "HALT"),
+            Instruction::TRAP => writeln!(f, "TRAP"),
 
             Instruction::STR(rd, adr, mode) => match mode {
                 Mode::None => writeln!(f, "STR {rd}, [{adr}]"),
@@ -0,0 +1,440 @@
+use thiserror::Error;
+
+use super::{
+    air::{Flag, Instruction, Mode, Reg, Rounding, Stream},
+    AirModule,
+};
+
+/// The bytes every binary AIR program starts with, followed by the format version.
+pub const MAGIC: &[u8; 3] = b"LAB";
+pub const VERSION: u8 = 2;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("not a binary AIR program, as it does not start with `LAB`")]
+    NotBinary,
+    #[error("binary AIR version {0} is not supported, expected version {VERSION}")]
+    UnsupportedVersion(u8),
+    #[error("the program ended partway through an instruction")]
+    Truncated,
+    #[error("invalid {what} `{tag:#04x}` at byte {offset}")]
+    InvalidTag {
+        what: &'static str,
+        tag: u8,
+        offset: usize,
+    },
+    #[error("a label is not valid utf8")]
+    InvalidLabel,
+}
+
+/// Returns true if the bytes look like a binary AIR program.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encode a module as binary AIR. After the header comes the static memory of the module, its data
+/// prefixed by its length, and the number of instructions, then each instruction as an opcode
+/// followed by its operands. Integers
+/// are little endian, and labels are prefixed by their length. Spans and variable names are not
+/// kept.
+pub fn encode(module: &AirModule) -> Vec<u8> {
+    let mut bytes: Vec<u8> = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.extend((module.static_memory as u64).to_le_bytes());
+    bytes.extend((module.data.len() as u64).to_le_bytes());
+    bytes.extend(&module.data);
+    bytes.extend((module.instructions.len() as u32).to_le_bytes());
+    for inst in &module.instructions {
+        encode_instruction(&mut bytes, &inst.instruction);
+    }
+    bytes
+}
+
+/// Decode a module from binary AIR.
+pub fn decode(bytes: &[u8]) -> Result<AirModule, DecodeError> {
+    if !is_binary(bytes) {
+        return Err(DecodeError::NotBinary);
+    }
+    let mut reader = Reader {
+        bytes,
+        offset: MAGIC.len(),
+    };
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let static_memory = reader.u64()? as usize;
+    let data_length = reader.u64()? as usize;
+    let data = reader.take(data_length)?.to_vec();
+    let count = reader.u32()?;
+    let mut instructions: Vec<Instruction> = Vec::new();
+    for _ in 0..count {
+        instructions.push(reader.instruction()?);
+    }
+    let mut module = AirModule::from_instructions(instructions);
+    module.static_memory = static_memory;
+    module.data = data;
+    Ok(module)
+}
+
+fn encode_instruction(bytes: &mut Vec<u8>, instruction: &Instruction) {
+    let reg = |bytes: &mut Vec<u8>, reg: &Reg| bytes.extend(reg.0.to_le_bytes());
+    let label = |bytes: &mut Vec<u8>, label: &str| {
+        bytes.extend((label.len() as u32).to_le_bytes());
+        bytes.extend(label.as_bytes());
+    };
+    match instruction {
+        Instruction::ADD(rd, rs1, rs2)
+        | Instruction::SUB(rd, rs1, rs2)
+        | Instruction::MUL(rd, rs1, rs2)
+        | Instruction::DIV(rd, rs1, rs2)
+        | Instruction::MOD(rd, rs1, rs2)
+        | Instruction::AND(rd, rs1, rs2)
+        | Instruction::ORR(rd, rs1, rs2) => {
+            bytes.push(opcode(instruction));
+            [rd, rs1, rs2].into_iter().for_each(|r| reg(bytes, r));
+        }
+        Instruction::SDIV(rd, rs1, rs2, rounding) => {
+            bytes.push(opcode(instruction));
+            [rd, rs1, rs2].into_iter().for_each(|r| reg(bytes, r));
+            bytes.push(*rounding as u8);
+        }
+        Instruction::INC(rd) | Instruction::DEC(rd) | Instruction::CYC(rd) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rd);
+        }
+        Instruction::CON(rd, value) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rd);
+            bytes.extend(value.to_le_bytes());
+        }
+        Instruction::LEA(rd, address) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rd);
+            bytes.extend((*address as u64).to_le_bytes());
+        }
+        Instruction::MOV(rd, rs)
+        | Instruction::NOT(rd, rs)
+        | Instruction::ARG(rd, rs)
+        | Instruction::ALLOC(rd, rs) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rd);
+            reg(bytes, rs);
+        }
+        Instruction::CMP(rs1, rs2, hint) | Instruction::SCMP(rs1, rs2, hint) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rs1);
+            reg(bytes, rs2);
+            bytes.push(hint.map_or(NO_FLAG, |flag| flag as u8));
+        }
+        Instruction::CHK(flag) => {
+            bytes.push(opcode(instruction));
+            bytes.push(*flag as u8);
+        }
+        Instruction::STR(rs, ra, mode) | Instruction::LDR(rs, ra, mode) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rs);
+            reg(bytes, ra);
+            match mode {
+                Mode::None => bytes.push(0),
+                Mode::Offset(offset) => {
+                    bytes.push(1);
+                    reg(bytes, offset);
+                }
+                Mode::PreOffset(offset) => {
+                    bytes.push(2);
+                    reg(bytes, offset);
+                }
+                Mode::PostOffset(offset) => {
+                    bytes.push(3);
+                    reg(bytes, offset);
+                }
+            }
+        }
+        Instruction::LBL(name) | Instruction::BRA(name) => {
+            bytes.push(opcode(instruction));
+            label(bytes, name);
+        }
+        Instruction::BRAcc(flag, name) => {
+            bytes.push(opcode(instruction));
+            bytes.push(*flag as u8);
+            label(bytes, name);
+        }
+        Instruction::YLD(rs, stream) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rs);
+            bytes.push(*stream as u8);
+        }
+        Instruction::HALT | Instruction::TRAP => bytes.push(opcode(instruction)),
+        Instruction::TST(rs, mask) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rs);
+            bytes.extend(mask.to_le_bytes());
+        }
+        Instruction::BIC(rd, rs, mask) => {
+            bytes.push(opcode(instruction));
+            reg(bytes, rd);
+            reg(bytes, rs);
+            bytes.extend(mask.to_le_bytes());
+        }
+    }
+}
+
+/// Stands in for the flag of a comparison without a flag hint.
+const NO_FLAG: u8 = 0xff;
+
+fn opcode(instruction: &Instruction) -> u8 {
+    match instruction {
+        Instruction::ADD(_, _, _) => 0x00,
+        Instruction::SUB(_, _, _) => 0x01,
+        Instruction::MUL(_, _, _) => 0x02,
+        Instruction::DIV(_, _, _) => 0x03,
+        Instruction::SDIV(_, _, _, _) => 0x04,
+        Instruction::INC(_) => 0x05,
+        Instruction::DEC(_) => 0x06,
+        Instruction::CON(_, _) => 0x07,
+        Instruction::LEA(_, _) => 0x08,
+        Instruction::MOV(_, _) => 0x09,
+        Instruction::NOT(_, _) => 0x0a,
+        Instruction::CMP(_, _, _) => 0x0b,
+        Instruction::SCMP(_, _, _) => 0x0c,
+        Instruction::CHK(_) => 0x0d,
+        Instruction::STR(_, _, _) => 0x0e,
+        Instruction::LDR(_, _, _) => 0x0f,
+        Instruction::LBL(_) => 0x10,
+        Instruction::BRA(_) => 0x11,
+        Instruction::BRAcc(_, _) => 0x12,
+        Instruction::YLD(_, _) => 0x13,
+        Instruction::HALT => 0x14,
+        Instruction::TST(_, _) => 0x15,
+        Instruction::BIC(_, _, _) => 0x16,
+        Instruction::AND(_, _, _) => 0x17,
+        Instruction::ORR(_, _, _) => 0x18,
+        Instruction::CYC(_) => 0x19,
+        Instruction::ARG(_, _) => 0x1a,
+        Instruction::TRAP => 0x1b,
+        Instruction::ALLOC(_, _) => 0x1c,
+        Instruction::MOD(_, _, _) => 0x1d,
+    }
+}
+
+const FLAGS: [Flag; 8] = [
+    Flag::Al,
+    Flag::Eq,
+    Flag::Ne,
+    Flag::Lt,
+    Flag::Le,
+    Flag::Gt,
+    Flag::Ge,
+    Flag::Nv,
+];
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        let taken = self
+            .bytes
+            .get(self.offset..self.offset.saturating_add(count))
+            .ok_or(DecodeError::Truncated)?;
+        self.offset += count;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn reg(&mut self) -> Result<Reg, DecodeError> {
+        Ok(Reg(self.u32()?))
+    }
+
+    /// Read a tag byte, failing if it is not below `count`.
+    fn tag(&mut self, what: &'static str, count: usize) -> Result<u8, DecodeError> {
+        let offset = self.offset;
+        let tag = self.u8()?;
+        match (tag as usize) < count {
+            true => Ok(tag),
+            false => Err(DecodeError::InvalidTag { what, tag, offset }),
+        }
+    }
+
+    fn flag(&mut self) -> Result<Flag, DecodeError> {
+        Ok(FLAGS[self.tag("flag", FLAGS.len())? as usize])
+    }
+
+    fn hint(&mut self) -> Result<Option<Flag>, DecodeError> {
+        match self.bytes.get(self.offset) {
+            Some(&NO_FLAG) => {
+                self.offset += 1;
+                Ok(None)
+            }
+            _ => self.flag().map(Some),
+        }
+    }
+
+    fn mode(&mut self) -> Result<Mode, DecodeError> {
+        Ok(match self.tag("addressing mode", 4)? {
+            0 => Mode::None,
+            1 => Mode::Offset(self.reg()?),
+            2 => Mode::PreOffset(self.reg()?),
+            _ => Mode::PostOffset(self.reg()?),
+        })
+    }
+
+    fn label(&mut self) -> Result<String, DecodeError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| DecodeError::InvalidLabel)
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, DecodeError> {
+        Ok(match self.tag("opcode", 0x1e)? {
+            0x00 => Instruction::ADD(self.reg()?, self.reg()?, self.reg()?),
+            0x01 => Instruction::SUB(self.reg()?, self.reg()?, self.reg()?),
+            0x02 => Instruction::MUL(self.reg()?, self.reg()?, self.reg()?),
+            0x03 => Instruction::DIV(self.reg()?, self.reg()?, self.reg()?),
+            0x04 => Instruction::SDIV(
+                self.reg()?,
+                self.reg()?,
+                self.reg()?,
+                match self.tag("rounding", 2)? {
+                    0 => Rounding::Truncate,
+                    _ => Rounding::Floor,
+                },
+            ),
+            0x05 => Instruction::INC(self.reg()?),
+            0x06 => Instruction::DEC(self.reg()?),
+            0x07 => Instruction::CON(self.reg()?, self.u32()?),
+            0x08 => Instruction::LEA(self.reg()?, self.u64()? as usize),
+            0x09 => Instruction::MOV(self.reg()?, self.reg()?),
+            0x0a => Instruction::NOT(self.reg()?, self.reg()?),
+            0x0b => Instruction::CMP(self.reg()?, self.reg()?, self.hint()?),
+            0x0c => Instruction::SCMP(self.reg()?, self.reg()?, self.hint()?),
+            0x0d => Instruction::CHK(self.flag()?),
+            0x0e => Instruction::STR(self.reg()?, self.reg()?, self.mode()?),
+            0x0f => Instruction::LDR(self.reg()?, self.reg()?, self.mode()?),
+            0x10 => Instruction::LBL(self.label()?),
+            0x11 => Instruction::BRA(self.label()?),
+            0x12 => Instruction::BRAcc(self.flag()?, self.label()?),
+            0x13 => Instruction::YLD(
+                self.reg()?,
+                match self.tag("stream", 2)? {
+                    0 => Stream::Out,
+                    _ => Stream::Err,
+                },
+            ),
+            0x14 => Instruction::HALT,
+            0x15 => Instruction::TST(self.reg()?, self.u32()?),
+            0x16 => Instruction::BIC(self.reg()?, self.reg()?, self.u32()?),
+            0x17 => Instruction::AND(self.reg()?, self.reg()?, self.reg()?),
+            0x18 => Instruction::ORR(self.reg()?, self.reg()?, self.reg()?),
+            0x19 => Instruction::CYC(self.reg()?),
+            0x1a => Instruction::ARG(self.reg()?, self.reg()?),
+            0x1b => Instruction::TRAP,
+            0x1c => Instruction::ALLOC(self.reg()?, self.reg()?),
+            _ => Instruction::MOD(self.reg()?, self.reg()?, self.reg()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_instruction_round_trips() {
+        let instructions = vec![
+            Instruction::ADD(Reg(0), Reg(1), Reg(2)),
+            Instruction::SUB(Reg(0), Reg(1), Reg(2)),
+            Instruction::MUL(Reg(0), Reg(1), Reg(2)),
+            Instruction::DIV(Reg(0), Reg(1), Reg(2)),
+            Instruction::SDIV(Reg(0), Reg(1), Reg(2), Rounding::Floor),
+            Instruction::MOD(Reg(0), Reg(1), Reg(2)),
+            Instruction::INC(Reg(3)),
+            Instruction::DEC(Reg(3)),
+            Instruction::CON(Reg(4), u32::MAX),
+            Instruction::LEA(Reg(4), 0x40),
+            Instruction::MOV(Reg(5), Reg(6)),
+            Instruction::NOT(Reg(5), Reg(6)),
+            Instruction::CMP(Reg(1), Reg(2), None),
+            Instruction::SCMP(Reg(1), Reg(2), Some(Flag::Le)),
+            Instruction::CHK(Flag::Nv),
+            Instruction::STR(Reg(1), Reg(2), Mode::None),
+            Instruction::LDR(Reg(1), Reg(2), Mode::PostOffset(Reg(7))),
+            Instruction::LBL("loop".to_owned()),
+            Instruction::BRA("loop".to_owned()),
+            Instruction::BRAcc(Flag::Ge, "end".to_owned()),
+            Instruction::YLD(Reg(8), Stream::Err),
+            Instruction::HALT,
+            Instruction::TST(Reg(9), 0b1010),
+            Instruction::BIC(Reg(9), Reg(10), 0xff00),
+            Instruction::AND(Reg(0), Reg(1), Reg(2)),
+            Instruction::ORR(Reg(0), Reg(1), Reg(2)),
+            Instruction::CYC(Reg(11)),
+            Instruction::ARG(Reg(12), Reg(13)),
+            Instruction::TRAP,
+            Instruction::ALLOC(Reg(14), Reg(15)),
+        ];
+        let mut module = AirModule::from_instructions(instructions.clone());
+        module.static_memory = 12;
+        module.data = vec![0, 0, 0, 7];
+
+        let decoded = decode(&encode(&module)).unwrap();
+        assert_eq!(12, decoded.static_memory);
+        assert_eq!(vec![0, 0, 0, 7], decoded.data);
+        let decoded: Vec<Instruction> = decoded
+            .instructions
+            .into_iter()
+            .map(|inst| inst.instruction)
+            .collect();
+        assert!(instructions == decoded);
+    }
+
+    #[test]
+    fn every_kind_has_an_opcode() {
+        let mut opcodes: Vec<u8> = Instruction::kinds().iter().map(opcode).collect();
+        opcodes.sort_unstable();
+        assert_eq!((0..0x1e).collect::<Vec<u8>>(), opcodes);
+    }
+
+    #[test]
+    fn invalid_programs() {
+        let bytes = encode(&AirModule::from_instructions(vec![Instruction::CHK(
+            Flag::Eq,
+        )]));
+        assert_eq!(Err(DecodeError::NotBinary), decode(b"yield 1;").map(|_| ()));
+        assert_eq!(
+            Err(DecodeError::Truncated),
+            decode(&bytes[..bytes.len() - 1]).map(|_| ())
+        );
+
+        let mut bad_flag = bytes.clone();
+        *bad_flag.last_mut().unwrap() = 9;
+        assert_eq!(
+            Err(DecodeError::InvalidTag {
+                what: "flag",
+                tag: 9,
+                offset: bytes.len() - 1
+            }),
+            decode(&bad_flag).map(|_| ())
+        );
+
+        let mut bad_version = bytes;
+        bad_version[3] = 0;
+        assert_eq!(
+            Err(DecodeError::UnsupportedVersion(0)),
+            decode(&bad_version).map(|_| ())
+        );
+    }
+}
@@ -1,16 +1,23 @@
 use super::air::{Flag, Inst, Instruction, Reg};
+use crate::{
+    error::LangError,
+    lex::span::{Span, Spans},
+};
 
 /// A Block of the program, represented by AIR instructions
 #[derive(Clone)]
 pub struct Block {
     instructions: Vec<Inst>,
     output_register: Option<Reg>,
+    /// the source covered by the instructions, from the start of the first to the end of the last.
+    span: Option<Span>,
 }
 
 impl Block {
     pub fn new(instruction: Inst) -> Self {
         let output_register = instruction.output_register();
         Self {
+            span: Some(instruction.span()),
             instructions: vec![instruction],
             output_register,
         }
@@ -20,14 +27,40 @@ impl Block {
         Self {
             instructions: Vec::new(),
             output_register: None,
+            span: None,
         }
     }
 
     pub fn append_inst(&mut self, instruction: Inst) {
         self.output_register = instruction.output_register().or(self.output_register);
+        self.join_span(instruction.span());
         self.instructions.push(instruction);
     }
 
+    /// Append the instructions of another block. Its output register replaces this block's if it
+    /// has one, so appending an empty block leaves the output register unchanged.
+    pub fn append_block(&mut self, block: Block) {
+        self.output_register = block.output_register.or(self.output_register);
+        if let Some(span) = block.span {
+            self.join_span(span);
+        }
+        self.instructions.extend(block.instructions);
+    }
+
+    /// Widen the span of the block to cover `span`. Only the bounds change, so the id of the
+    /// block's span is that of its first instruction.
+    pub fn join_span(&mut self, span: Span) {
+        match &mut self.span {
+            Some(block_span) => block_span.join(span),
+            None => self.span = Some(span),
+        }
+    }
+
+    /// Returns the source covered by the block's instructions, or `None` if it has none.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
     pub fn from_instructions(instructions: Vec<Inst>) -> Self {
         let output_register: Option<Reg> = instructions
             .iter()
@@ -35,10 +68,18 @@ impl Block {
             .map(|inst| inst.output_register())
             .find(|r| r.is_some())
             .map(|x| x.unwrap());
+        let span = instructions
+            .iter()
+            .map(Inst::span)
+            .reduce(|mut span, other| {
+                span.join(other);
+                span
+            });
 
         Self {
             instructions,
             output_register,
+            span,
         }
     }
 
@@ -46,8 +87,13 @@ impl Block {
         self.output_register
     }
 
-    pub fn output_register_unchecked(&self) -> Reg {
-        self.output_register.expect("unchecked output register")
+    /// Returns the output register, or a `NullValueExpression` error if the block does not produce
+    /// a value. The error is labelled with the source the block covers, or with `span` if the block
+    /// has no instructions.
+    pub fn value_register(&self, span: impl Spans) -> Result<Reg, LangError> {
+        self.output_register.ok_or(LangError::NullValueExpression {
+            span: self.span().unwrap_or(span.span()),
+        })
     }
 
     pub fn set_output_register(&mut self, reg: Option<Reg>) {
@@ -61,7 +107,8 @@ impl Block {
                 return acc;
             } else {
                 match inst.instruction {
-                    Instruction::CMP(_, _, Some(flag_hint)) => return Some(flag_hint),
+                    Instruction::CMP(_, _, Some(flag_hint))
+                    | Instruction::SCMP(_, _, Some(flag_hint)) => return Some(flag_hint),
                     _ => None,
                 }
             }
@@ -86,13 +133,10 @@ impl Block {
 
 impl std::iter::Extend<Inst> for Block {
     fn extend<T: IntoIterator<Item = Inst>>(&mut self, iter: T) {
-        self.instructions.extend(iter);
-        self.output_register = self
-            .instructions
-            .iter()
-            .rev()
-            .find(|inst| inst.output_register().is_some())
-            .map(|x| x.output_register().unwrap());
+        // only the new instructions can change the output register, so an empty extend keeps it.
+        for inst in iter {
+            self.append_inst(inst);
+        }
     }
 }
 
@@ -118,3 +162,57 @@ impl std::fmt::Display for Inst {
         write!(f, "{}", self.instruction)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::air::air::Stream;
+    use crate::lex::span::Span;
+
+    fn inst(instruction: Instruction) -> Inst {
+        Inst::new(instruction, Span::new((0, 1)))
+    }
+
+    #[test]
+    fn empty_extend_keeps_output_register() {
+        let mut block = Block::empty();
+        block.set_output_register(Some(Reg(3)));
+        block.extend(Vec::new());
+        assert!(block.output_register() == Some(Reg(3)));
+
+        let mut block = Block::new(inst(Instruction::CON(Reg(0), 1)));
+        block.extend([inst(Instruction::YLD(Reg(0), Stream::Out))]);
+        assert!(block.output_register() == Some(Reg(0)));
+        block.extend([inst(Instruction::CON(Reg(1), 2))]);
+        assert!(block.output_register() == Some(Reg(1)));
+    }
+
+    #[test]
+    fn appending_joins_spans() {
+        let mut block = Block::empty();
+        assert!(block.span().is_none());
+        block.append_inst(Inst::new(Instruction::CON(Reg(0), 1), Span::new((4, 5))));
+        block.append_block(Block::new(Inst::new(
+            Instruction::CON(Reg(1), 2),
+            Span::new((0, 1)),
+        )));
+        block.extend([Inst::new(
+            Instruction::ADD(Reg(2), Reg(0), Reg(1)),
+            Span::new((2, 3)),
+        )]);
+        assert_eq!(Some((0, 5)), block.span().map(|span| span.span()));
+    }
+
+    #[test]
+    fn empty_append_keeps_output_register() {
+        let mut block = Block::new(inst(Instruction::CON(Reg(0), 1)));
+        block.append_block(Block::empty());
+        assert!(block.output_register() == Some(Reg(0)));
+        assert_eq!(1, block.instructions().len());
+
+        let mut variable = Block::empty();
+        variable.set_output_register(Some(Reg(4)));
+        block.append_block(variable);
+        assert!(block.output_register() == Some(Reg(4)));
+    }
+}
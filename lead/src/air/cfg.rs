@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use super::air::Instruction;
+
+/// A straight line run of instructions, entered only at its first instruction and left only after
+/// its last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// the indices of the instructions in the block
+    pub range: Range<usize>,
+    /// the indices of the blocks control can pass to after this one
+    pub successors: Vec<usize>,
+}
+
+/// The basic blocks of a program, and the edges between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    blocks: Vec<BasicBlock>,
+}
+
+impl ControlFlowGraph {
+    /// Partition instructions into basic blocks. Blocks start at labels, and after branches, halts
+    /// and traps. A conditional branch may continue to the next block or branch to its label. A
+    /// `CHK` ends its block, and the instruction it guards is given a block of its own, since the
+    /// check may skip it.
+    pub fn from_instructions(instructions: &[Instruction]) -> Self {
+        let mut leaders: Vec<usize> = vec![0];
+        for (i, instruction) in instructions.iter().enumerate() {
+            match instruction {
+                Instruction::LBL(_) => leaders.push(i),
+                Instruction::BRA(_)
+                | Instruction::BRAcc(_, _)
+                | Instruction::HALT
+                | Instruction::TRAP => leaders.push(i + 1),
+                Instruction::CHK(_) => leaders.extend([i + 1, i + 2]),
+                _ => (),
+            }
+        }
+        leaders.retain(|i| *i < instructions.len());
+        leaders.sort_unstable();
+        leaders.dedup();
+
+        let block_of: HashMap<usize, usize> = leaders
+            .iter()
+            .enumerate()
+            .map(|(block, leader)| (*leader, block))
+            .collect();
+        let labels: HashMap<&str, usize> = instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, instruction)| match instruction {
+                Instruction::LBL(label) => Some((label.as_str(), block_of[&i])),
+                _ => None,
+            })
+            .collect();
+
+        let blocks = leaders
+            .iter()
+            .enumerate()
+            .map(|(block, start)| {
+                let end = leaders
+                    .get(block + 1)
+                    .copied()
+                    .unwrap_or(instructions.len());
+                let fallthrough = block_of.get(&end).copied();
+                let successors = match &instructions[end - 1] {
+                    Instruction::BRA(label) => {
+                        labels.get(label.as_str()).copied().into_iter().collect()
+                    }
+                    Instruction::BRAcc(_, label) => fallthrough
+                        .into_iter()
+                        .chain(labels.get(label.as_str()).copied())
+                        .collect(),
+                    Instruction::CHK(_) => {
+                        let skipped = block_of.get(&(end + 1)).copied();
+                        fallthrough.into_iter().chain(skipped).collect()
+                    }
+                    Instruction::HALT | Instruction::TRAP => Vec::new(),
+                    _ => fallthrough.into_iter().collect(),
+                };
+                BasicBlock {
+                    range: *start..end,
+                    successors,
+                }
+            })
+            .collect();
+
+        Self { blocks }
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// Render the graph in Graphviz's DOT format. Each block is a node labelled with its
+    /// instructions, and the edges out of a conditional branch or check are labelled with the
+    /// condition under which control takes them.
+    pub fn render_dot(&self, instructions: &[Instruction]) -> String {
+        let mut dot =
+            String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for (index, block) in self.blocks.iter().enumerate() {
+            let label: String = instructions[block.range.clone()]
+                .iter()
+                .map(|instruction| {
+                    instruction
+                        .to_string()
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"")
+                        .replace('\n', "\\l")
+                })
+                .collect();
+            writeln!(dot, "    b{index} [label=\"{label}\"];").unwrap();
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            let falls_through = block.range.end < instructions.len();
+            for (edge, successor) in block.successors.iter().enumerate() {
+                // a branch falls through when its condition fails, and a check when it holds.
+                let condition = match &instructions[block.range.end - 1] {
+                    Instruction::BRAcc(flag, _) if falls_through && edge == 0 => {
+                        Some(flag.negate())
+                    }
+                    Instruction::BRAcc(flag, _) => Some(*flag),
+                    Instruction::CHK(flag) if edge == 0 => Some(*flag),
+                    Instruction::CHK(flag) => Some(flag.negate()),
+                    _ => None,
+                };
+                match condition {
+                    Some(flag) => writeln!(dot, "    b{index} -> b{successor} [label=\"{flag}\"];"),
+                    None => writeln!(dot, "    b{index} -> b{successor};"),
+                }
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the index of the block containing an instruction, if it is in the program.
+    pub fn block_containing(&self, instruction: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| block.range.contains(&instruction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::air::air::{
+        Flag,
+        Instruction::{self, *},
+        Reg, Stream,
+    };
+
+    #[test]
+    fn loop_and_break() {
+        let instructions: Vec<Instruction> = vec![
+            CON(Reg(0), 1),
+            LBL("check-condition".into()),
+            CON(Reg(1), 5),
+            CMP(Reg(0), Reg(1), None),
+            CHK(Flag::Ge),
+            BRA("break".into()),
+            YLD(Reg(0), Stream::Out),
+            CON(Reg(2), 1),
+            ADD(Reg(0), Reg(0), Reg(2)),
+            BRA("check-condition".into()),
+            LBL("break".into()),
+            CON(Reg(3), 64),
+            YLD(Reg(3), Stream::Out),
+        ];
+        let cfg = ControlFlowGraph::from_instructions(&instructions);
+
+        let edges: Vec<(Range<usize>, Vec<usize>)> = cfg
+            .blocks()
+            .iter()
+            .map(|block| (block.range.clone(), block.successors.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (0..1, vec![1]),
+                (1..5, vec![2, 3]),
+                (5..6, vec![4]),
+                (6..10, vec![1]),
+                (10..13, vec![]),
+            ],
+            edges
+        );
+        assert_eq!(Some(3), cfg.block_containing(8));
+    }
+
+    #[test]
+    fn conditional_branch() {
+        let instructions: Vec<Instruction> = vec![
+            CMP(Reg(0), Reg(1), None),
+            BRAcc(Flag::Ge, "end".into()),
+            YLD(Reg(0), Stream::Out),
+            LBL("end".into()),
+        ];
+        let cfg = ControlFlowGraph::from_instructions(&instructions);
+        let successors: Vec<Vec<usize>> = cfg
+            .blocks()
+            .iter()
+            .map(|block| block.successors.clone())
+            .collect();
+        assert_eq!(vec![vec![1, 2], vec![2], vec![]], successors);
+    }
+
+    #[test]
+    fn dot_labels_conditions() {
+        let instructions: Vec<Instruction> = vec![
+            CMP(Reg(0), Reg(1), None),
+            BRAcc(Flag::Ge, "end".into()),
+            YLD(Reg(0), Stream::Out),
+            LBL("end".into()),
+        ];
+        let dot = ControlFlowGraph::from_instructions(&instructions).render_dot(&instructions);
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b1 [label=\"YLD %0\\l\"];\n"));
+        assert!(dot.contains("    b0 -> b1 [label=\"<\"];\n"));
+        assert!(dot.contains("    b0 -> b2 [label=\">=\"];\n"));
+        assert!(dot.contains("    b1 -> b2;\n"));
+    }
+
+    #[test]
+    fn empty_program() {
+        assert!(ControlFlowGraph::from_instructions(&[]).blocks().is_empty());
+    }
+}
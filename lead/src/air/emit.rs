@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use uuid::Uuid;
+
+use super::air::{Inst, Instruction, Reg};
+use crate::lex::span::Span;
+
+/// A backend for lowering. Lowering asks the emitter for the registers and labels it uses, and
+/// `emit_program` passes it each instruction of the lowered program in order.
+pub trait Emitter {
+    fn emit(&mut self, instruction: Instruction, span: Span);
+    /// A register that has not been used before.
+    fn fresh_reg(&mut self) -> Reg;
+    /// A label that has not been used before. Lowering may suffix it to name the labels of one
+    /// construct, such as `-check-condition` and `-break` for a loop.
+    fn fresh_label(&mut self) -> String;
+}
+
+static NUMBERED_LABELS: AtomicBool = AtomicBool::new(false);
+
+/// Name the labels of every `AirEmitter` created after this with a counter instead of a random
+/// uuid, so that compiling the same program gives the same AIR.
+pub fn number_labels() {
+    NUMBERED_LABELS.store(true, Ordering::Relaxed);
+}
+
+/// The default emitter, numbering registers from 0 and naming labels with random uuids, or with
+/// a counter from 0 after `number_labels`.
+pub struct AirEmitter {
+    next_reg: Reg,
+    /// the number of the next label, if labels are numbered.
+    next_label: Option<usize>,
+    /// the instructions emitted so far, in order.
+    pub instructions: Vec<Inst>,
+}
+
+impl AirEmitter {
+    pub fn new() -> Self {
+        Self {
+            next_reg: Reg(0),
+            next_label: NUMBERED_LABELS.load(Ordering::Relaxed).then_some(0),
+            instructions: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for AirEmitter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AirEmitter")
+            .field("next_reg", &self.next_reg)
+            .field("next_label", &self.next_label)
+            .field("instructions", &self.instructions.len())
+            .finish()
+    }
+}
+
+impl Default for AirEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Emitter for AirEmitter {
+    fn emit(&mut self, instruction: Instruction, span: Span) {
+        self.instructions.push(Inst::new(instruction, span));
+    }
+
+    fn fresh_reg(&mut self) -> Reg {
+        let reg = self.next_reg;
+        (*self.next_reg) += 1;
+        reg
+    }
+
+    fn fresh_label(&mut self) -> String {
+        match &mut self.next_label {
+            Some(n) => {
+                *n += 1;
+                format!("L{}", *n - 1)
+            }
+            None => Uuid::new_v4().as_hyphenated().to_string(),
+        }
+    }
+}
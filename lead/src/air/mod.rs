@@ -1,54 +1,143 @@
-use std::collections::HashMap;
-use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
 use crate::{
+    check::TypeChecker,
     error::LangError,
     lex::span::*,
     parse::ast::{
-        Application, Expression, Identifier, If, Let, Literal, Mutate, OperatorType, Statement,
-        While,
+        Application, Builtin, Data, Expression, For, Identifier, If, Iterable, Let, Literal,
+        Mutate, OperatorType, Statement, Type, While,
     },
 };
-use air::{Flag, Inst, Instruction, Mode, Reg};
+use air::{Flag, Inst, Instruction, Mode, Reg, Rounding, Stream};
 use block::Block;
+use emit::{AirEmitter, Emitter};
 
 pub mod air;
+pub mod binary;
 mod block;
+pub mod cfg;
+pub mod emit;
+pub mod optimise;
+pub mod text;
 
 /// Word size in bytes. This does not modify behavior program wide
 const WORD_SIZE: usize = 4;
 
 // temp pub struct
 #[derive(Debug)]
-pub struct GenerationState {
-    next_reg: Reg,
+pub struct GenerationState<E: Emitter = AirEmitter> {
+    /// where registers and labels come from, and where `emit_program` sends instructions.
+    emitter: E,
     variables: HashMap<String, Reg>,
     /// The variable pointers in a program.
-    pointers: HashMap<String, usize>,
-    /// the address of the next place in memory to store arrays and strings.
+    pointers: HashMap<String, Allocation>,
+    /// the address of the next place in memory to store arrays and strings. This data region grows
+    /// up from address 0.
     next_mem_addr: usize,
+    /// the bytes the data region holds before the program runs, up to the end of the last `data`
+    /// table. Words are big endian, as the machine stores them.
+    data: Vec<u8>,
+    /// the size of the machine's memory, or `usize::MAX` if it is not known while compiling.
+    memory_size: usize,
+    /// the lowest address of the stack region that spilled values are stored in, which grows down
+    /// from the top of memory.
+    stack_base: usize,
+    /// the break labels of the loops being lowered, innermost last.
+    loops: Vec<String>,
+    /// the types of the variables, to pick signed or unsigned instructions.
+    types: TypeChecker,
+    /// whether indices computed at runtime are checked before they are used.
+    bounds_checks: bool,
+    /// whether arithmetic wraps when it overflows, rather than saturating or trapping.
+    wrapping_arithmetic: bool,
 }
 
 impl GenerationState {
+    /// A state for a machine whose memory is sized to fit the program once it is compiled.
     pub fn new() -> Self {
+        Self::with_memory_size(usize::MAX)
+    }
+
+    /// A state for a machine with `memory_size` bytes of memory, so that the data and stack
+    /// regions can be checked against each other.
+    pub fn with_memory_size(memory_size: usize) -> Self {
+        GenerationState::with_emitter(AirEmitter::new(), memory_size)
+    }
+
+    /// Take the instructions emitted by `emit_program`, leaving none behind.
+    pub fn take_instructions(&mut self) -> Vec<Inst> {
+        std::mem::take(&mut self.emitter.instructions)
+    }
+}
+
+impl<E: Emitter> GenerationState<E> {
+    /// A state lowering for another backend, which allocates the registers and labels and is
+    /// passed the lowered instructions.
+    pub fn with_emitter(emitter: E, memory_size: usize) -> Self {
         Self {
-            next_reg: Reg(0),
+            emitter,
             variables: HashMap::new(),
             pointers: HashMap::new(),
             next_mem_addr: 0,
+            data: Vec::new(),
+            memory_size,
+            stack_base: memory_size,
+            loops: Vec::new(),
+            types: TypeChecker::new(),
+            bounds_checks: false,
+            wrapping_arithmetic: true,
         }
     }
 
+    /// Check indices computed at runtime before they are used, trapping on an index outside the
+    /// array instead of reading past either end of it.
+    pub fn set_bounds_checks(&mut self, enabled: bool) {
+        self.bounds_checks = enabled;
+    }
+
+    /// Lower for a machine whose arithmetic wraps when it overflows. Otherwise, constants whose
+    /// computation overflows an unsigned word are left for the machine to saturate or trap on.
+    pub fn set_wrapping_arithmetic(&mut self, enabled: bool) {
+        self.wrapping_arithmetic = enabled;
+    }
+
     fn next_register(&mut self) -> Reg {
-        let reg = self.next_reg;
-        (*self.next_reg) += 1;
-        reg
+        self.emitter.fresh_reg()
     }
 
-    fn next_mem_addr(&mut self) -> usize {
+    fn fresh_label(&mut self) -> String {
+        self.emitter.fresh_label()
+    }
+
+    /// Reserve `words` words of the data region, returning the address of the first.
+    fn allocate(&mut self, words: usize, span: Span) -> Result<usize, LangError> {
         let addr = self.next_mem_addr;
-        self.next_mem_addr += 4; // the current word size in bytes
-        addr
+        self.next_mem_addr += words * WORD_SIZE;
+        self.check_regions(span)?;
+        Ok(addr)
+    }
+
+    /// Reserve `words` words of the stack region for values spilled from registers, returning the
+    /// address of the first. The stack is placed at the top of memory, so this should only be used
+    /// once the memory size is known.
+    pub fn allocate_spill(&mut self, words: usize, span: Span) -> Result<usize, LangError> {
+        self.stack_base = self.stack_base.saturating_sub(words * WORD_SIZE);
+        self.check_regions(span)?;
+        Ok(self.stack_base)
+    }
+
+    /// Returns an error if the data region has grown into the stack region.
+    fn check_regions(&self, span: Span) -> Result<(), LangError> {
+        match self.next_mem_addr <= self.stack_base && self.stack_base <= self.memory_size {
+            true => Ok(()),
+            false => Err(LangError::MemoryOverlap {
+                span,
+                data_end: self.next_mem_addr,
+                stack_base: self.stack_base,
+            }),
+        }
     }
 
     /// initialise a variable in the program. Returns the register it was allocated to
@@ -57,7 +146,7 @@ impl GenerationState {
     }
 
     /// initialise a pointer to a variable in the program.
-    fn initialise_pointer(&mut self, variable: String, pointer: usize) {
+    fn initialise_pointer(&mut self, variable: String, pointer: Allocation) {
         self.pointers.insert(variable, pointer);
     }
 
@@ -80,7 +169,28 @@ impl GenerationState {
         }
     }
 
-    fn deref_pointer(&self, variable: &String, span: impl Spans) -> Result<usize, LangError> {
+    /// lower statements in a scope of their own, so the variables they declare are forgotten
+    /// after. Variables from outside keep their registers, so changes to them are still seen.
+    fn lower_scope(&mut self, statements: &[Statement]) -> Result<Block, LangError> {
+        let (variables, pointers, types) = (
+            self.variables.clone(),
+            self.pointers.clone(),
+            self.types.clone(),
+        );
+        let lowered = self.lower_statements(statements);
+        (self.variables, self.pointers, self.types) = (variables, pointers, types);
+        lowered
+    }
+
+    fn lower_statements(&mut self, statements: &[Statement]) -> Result<Block, LangError> {
+        let mut block = Block::empty();
+        for statement in statements {
+            block.extend(statement.lower(self)?);
+        }
+        Ok(block)
+    }
+
+    fn deref_pointer(&self, variable: &String, span: impl Spans) -> Result<Allocation, LangError> {
         match self.pointers.get(variable) {
             Some(pointer) => Ok(*pointer),
             None => Err(LangError::UninitialisedPointer {
@@ -91,24 +201,196 @@ impl GenerationState {
     }
 }
 
+/// An array statically allocated in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Allocation {
+    /// The address of the first element.
+    pub address: usize,
+    /// The number of elements.
+    pub length: usize,
+}
+
+/// Returns the index of the first label with each name in a sequence of instructions.
+pub fn label_table<'a>(
+    instructions: impl Iterator<Item = &'a Instruction>,
+) -> HashMap<String, usize> {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    for (i, instruction) in instructions.enumerate() {
+        if let Instruction::LBL(label) = instruction {
+            labels.entry(label.clone()).or_insert(i);
+        }
+    }
+    labels
+}
+
+/// Render instructions as AIR text, one instruction per line.
+pub fn render_air(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(|instruction| instruction.to_string())
+        .collect()
+}
+
+/// Render instructions as AIR text like [`render_air`], following each register holding a named
+/// variable with the variable's name, as in `%3/*x*/`.
+pub fn render_annotated_air(instructions: &[Instruction], names: &HashMap<Reg, String>) -> String {
+    let rendered = render_air(instructions);
+    let mut annotated = String::with_capacity(rendered.len());
+    let mut chars = rendered.chars().peekable();
+    while let Some(c) = chars.next() {
+        annotated.push(c);
+        if c != '%' {
+            continue;
+        }
+        let mut number = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            number.push(digit);
+        }
+        annotated.push_str(&number);
+        if let Some(name) = number.parse().ok().and_then(|reg| names.get(&Reg(reg))) {
+            annotated.push_str(&format!("/*{name}*/"));
+        }
+    }
+    annotated
+}
+
+/// Follow each line of rendered AIR with a `; ` comment holding the source text of the matching
+/// instruction. Only the first line of the text is kept, followed by `...` if there is more.
+pub fn comment_with_source(rendered: &str, instructions: &[Inst], src: &str) -> String {
+    let mut commented = String::with_capacity(2 * rendered.len());
+    for (line, inst) in rendered.lines().zip(instructions) {
+        let (start, end) = inst.span().span();
+        let text = src.get(start..end).unwrap_or_default().trim();
+        let mut lines = text.lines();
+        let first = lines.next().unwrap_or_default().trim_end();
+        match lines.next() {
+            Some(_) => commented.push_str(&format!("{line} ; {first} ...\n")),
+            None if first.is_empty() => commented.push_str(&format!("{line}\n")),
+            None => commented.push_str(&format!("{line} ; {first}\n")),
+        }
+    }
+    commented
+}
+
+/// A compiled program, along with what is known about it at compile time.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AirModule {
+    pub instructions: Vec<Inst>,
+    /// The registers allocated to each variable.
+    pub variables: HashMap<String, Reg>,
+    /// The memory allocated to each array.
+    pub pointers: HashMap<String, Allocation>,
+    /// The number of bytes of memory statically allocated by the program.
+    pub static_memory: usize,
+    /// The bytes memory starts with when the program is loaded, from address 0, holding its `data`
+    /// tables.
+    pub data: Vec<u8>,
+}
+
+impl AirModule {
+    pub fn new(instructions: Vec<Inst>, state: &GenerationState<impl Emitter>) -> Self {
+        Self {
+            instructions,
+            variables: state.variables.clone(),
+            pointers: state.pointers.clone(),
+            static_memory: state.next_mem_addr,
+            data: state.data.clone(),
+        }
+    }
+
+    /// Create a module from instructions alone, such as hand-written AIR. Nothing is known
+    /// about the source of the instructions, so they are given empty spans.
+    pub fn from_instructions(instructions: Vec<Instruction>) -> Self {
+        Self {
+            instructions: instructions
+                .into_iter()
+                .map(|instruction| Inst::new(instruction, Span::new((0, 0))))
+                .collect(),
+            variables: HashMap::new(),
+            pointers: HashMap::new(),
+            static_memory: 0,
+            data: Vec::new(),
+        }
+    }
+
+    /// Returns the index of the first label with each name in the program.
+    pub fn labels(&self) -> HashMap<String, usize> {
+        label_table(self.instructions.iter().map(Inst::instruction_borrow))
+    }
+
+    /// Returns the source spans of every instruction that contributed to the value of a register:
+    /// those writing to it, and in turn those writing to the registers they read, ordered by where
+    /// they start in the source.
+    pub fn spans_for_register(&self, reg: Reg) -> Vec<Span> {
+        let mut pending: Vec<Reg> = vec![reg];
+        let mut visited: HashSet<Reg> = HashSet::new();
+        let mut spans: Vec<Span> = Vec::new();
+        while let Some(reg) = pending.pop() {
+            if !visited.insert(reg) {
+                continue;
+            }
+            for inst in &self.instructions {
+                if inst.instruction.written_registers().contains(&reg) {
+                    spans.push(inst.span);
+                    pending.extend(inst.instruction.read_registers());
+                }
+            }
+        }
+        spans.sort_by_key(Span::span);
+        spans.dedup();
+        spans
+    }
+
+    /// Returns the number of labels in the program.
+    pub fn label_count(&self) -> usize {
+        self.instructions
+            .iter()
+            .filter(|inst| matches!(inst.instruction, Instruction::LBL(_)))
+            .count()
+    }
+}
+
 /// Generate a nested unoptimised program.
 pub fn generate_program(
-    state: &mut GenerationState,
+    state: &mut GenerationState<impl Emitter>,
     statements: Vec<Statement>,
 ) -> Result<Vec<Block>, LangError> {
     let mut segments = Vec::new();
+    extend_program(state, &mut segments, statements)?;
+    Ok(segments)
+}
+
+/// Lower statements onto the end of a program already generated with `state`. Only the new
+/// statements are lowered, so a program built up a statement at a time, as in a REPL, costs the
+/// same as lowering it all at once.
+pub fn extend_program(
+    state: &mut GenerationState<impl Emitter>,
+    program: &mut Vec<Block>,
+    statements: Vec<Statement>,
+) -> Result<(), LangError> {
     for statement in statements {
-        segments.push(statement.lower(state)?)
+        program.push(statement.lower(state)?)
     }
-    Ok(segments)
+    Ok(())
+}
+
+/// Lower a program, passing each of its instructions in order to the state's emitter.
+pub fn emit_program(
+    state: &mut GenerationState<impl Emitter>,
+    statements: Vec<Statement>,
+) -> Result<(), LangError> {
+    for inst in generate_program(state, statements)?.into_iter().flatten() {
+        state.emitter.emit(inst.instruction, inst.span);
+    }
+    Ok(())
 }
 
 pub trait Lowerable {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError>;
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError>;
 }
 
 impl Lowerable for Literal {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
         let reg = state.next_register();
         Ok(match self {
             Literal::Char { val, span } => {
@@ -125,29 +407,28 @@ impl Lowerable for Literal {
 }
 
 impl Lowerable for Application {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
         match self {
             Application::Unary { op, expr, span } => {
                 let mut block: Block = expr.lower(state)?;
+                let block_output_register = block.value_register(expr.as_ref())?;
                 match op {
                     OperatorType::Not => {
                         block.append_inst(Inst::new(
-                            Instruction::NOT(
-                                state.next_register(),
-                                block.output_register_unchecked(),
-                            ),
+                            Instruction::NOT(state.next_register(), block_output_register),
                             *span,
                         ));
                     }
                     OperatorType::Minus => {
                         let rx: Reg = state.next_register();
-                        let block_output_register = block.output_register_unchecked();
                         block.append_inst(Inst::new(Instruction::CON(rx, 0), *span));
                         block.append_inst(Inst::new(
                             Instruction::SUB(state.next_register(), rx, block_output_register),
                             *span,
                         ));
                     }
+                    // unary plus leaves its operand unchanged, in the operand's register.
+                    OperatorType::Plus => (),
                     _ => unreachable!(),
                 }
                 Ok(block)
@@ -158,29 +439,104 @@ impl Lowerable for Application {
                 right,
                 span,
             } => {
+                if let OperatorType::And | OperatorType::Or = op {
+                    let (mut block, rx) = lower_boolean(left, state)?;
+                    let (ry_block, ry) = lower_boolean(right, state)?;
+                    block.extend(ry_block);
+                    let rd = state.next_register();
+                    block.append_inst(Inst::new(
+                        match op {
+                            OperatorType::And => Instruction::AND(rd, rx, ry),
+                            _ => Instruction::ORR(rd, rx, ry),
+                        },
+                        *span,
+                    ));
+                    return Ok(block);
+                }
+
+                // `0 < x < 10` parses as `0 < (x < 10)`, comparing a number with a comparison.
+                if op.is_comparison() {
+                    for operand in [left, right] {
+                        if let Expression::App {
+                            app:
+                                Application::Binary {
+                                    op: chained,
+                                    left: inner_left,
+                                    right: inner_right,
+                                    span: _,
+                                },
+                        } = operand.as_ref()
+                        {
+                            if chained.is_comparison() {
+                                let outer =
+                                    Span::new((left.span().span().1, right.span().span().0));
+                                let inner = Span::new((
+                                    inner_left.span().span().1,
+                                    inner_right.span().span().0,
+                                ));
+                                let (first, second) = match outer.span() < inner.span() {
+                                    true => (outer, inner),
+                                    false => (inner, outer),
+                                };
+                                return Err(LangError::ChainedComparison { first, second });
+                            }
+                        }
+                    }
+                }
+
+                if let (
+                    OperatorType::Equal | OperatorType::NotEqual,
+                    Expression::Identifier(left),
+                    Expression::Identifier(right),
+                ) = (op, left.as_ref(), right.as_ref())
+                {
+                    if let (Some(a), Some(b)) = (
+                        state.pointers.get(left.name()).copied(),
+                        state.pointers.get(right.name()).copied(),
+                    ) {
+                        return lower_array_equality(*op, (left, a), (right, b), *span, state);
+                    }
+                }
+
                 let mut rx_block: Block = left.lower(state)?;
-                let rx: Reg = rx_block.output_register_unchecked();
+                let rx: Reg = rx_block.value_register(left.as_ref())?;
                 let ry_block: Block = right.lower(state)?;
-                let ry: Reg = ry_block.output_register_unchecked();
+                let ry: Reg = ry_block.value_register(right.as_ref())?;
 
                 rx_block.extend(ry_block);
 
+                // numbers are signed, so they are compared as signed.
+                let compare = match state.types.infer(left)? {
+                    Type::Num => Instruction::SCMP,
+                    _ => Instruction::CMP,
+                };
+
                 rx_block.append_inst(Inst::new(
                     match op {
                         OperatorType::Plus => Instruction::ADD(state.next_register(), rx, ry),
                         OperatorType::Minus => Instruction::SUB(state.next_register(), rx, ry),
                         OperatorType::Multiply => Instruction::MUL(state.next_register(), rx, ry),
-                        OperatorType::Divide => Instruction::DIV(state.next_register(), rx, ry),
-                        OperatorType::LessThan => Instruction::CMP(rx, ry, Some(Flag::Lt)),
-                        OperatorType::LessThanEq => Instruction::CMP(rx, ry, Some(Flag::Le)),
-                        OperatorType::GreaterThan => Instruction::CMP(rx, ry, Some(Flag::Gt)),
-                        OperatorType::GreaterThanEq => Instruction::CMP(rx, ry, Some(Flag::Ge)),
-                        OperatorType::NotEqual => Instruction::CMP(rx, ry, Some(Flag::Ne)),
-                        OperatorType::Equal => Instruction::CMP(rx, ry, Some(Flag::Eq)),
+                        OperatorType::Divide => {
+                            Instruction::SDIV(state.next_register(), rx, ry, Rounding::Truncate)
+                        }
+                        OperatorType::Modulo => Instruction::MOD(state.next_register(), rx, ry),
+                        OperatorType::LessThan => compare(rx, ry, Some(Flag::Lt)),
+                        OperatorType::LessThanEq => compare(rx, ry, Some(Flag::Le)),
+                        OperatorType::GreaterThan => compare(rx, ry, Some(Flag::Gt)),
+                        OperatorType::GreaterThanEq => compare(rx, ry, Some(Flag::Ge)),
+                        OperatorType::NotEqual => compare(rx, ry, Some(Flag::Ne)),
+                        OperatorType::Equal => compare(rx, ry, Some(Flag::Eq)),
                         _ => unreachable!("OperatorType::Not is a unary operator "),
                     },
                     *span,
                 ));
+                if matches!(
+                    rx_block.instructions().last().map(Inst::instruction_borrow),
+                    Some(Instruction::CMP(_, _, _) | Instruction::SCMP(_, _, _))
+                ) {
+                    // a comparison only sets flags, so the operands are not its value.
+                    rx_block.set_output_register(None);
+                }
                 Ok(rx_block)
             }
         }
@@ -188,12 +544,96 @@ impl Lowerable for Application {
 }
 
 impl Lowerable for Expression {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
         match self {
             Expression::Literal { lit } => lit.lower(state),
             Expression::App { app } => app.lower(state),
-            Expression::Group { expr, span: _ } => expr.lower(state),
+            // the parentheses are part of the grouped expression's source.
+            Expression::Group { expr, span } => {
+                let mut block = expr.lower(state)?;
+                block.join_span(*span);
+                Ok(block)
+            }
             Expression::Identifier(identifier) => identifier.lower(state),
+            Expression::Call {
+                function,
+                arguments,
+                span,
+            } => match Builtin::resolve(function, arguments, *span)? {
+                Builtin::Swap => {
+                    let mut registers: Vec<Reg> = Vec::with_capacity(2);
+                    for argument in arguments {
+                        let Expression::Identifier(variable) = argument else {
+                            return Err(LangError::ExpectedVariable {
+                                span: argument.span(),
+                            });
+                        };
+                        registers.push(*state.variable_register(variable.borrow_name(), variable)?);
+                    }
+                    let (ra, rb) = (registers[0], registers[1]);
+
+                    // exchange the values through a scratch register, so both variables keep
+                    // their registers.
+                    let scratch = state.next_register();
+                    let mut block = Block::new(Inst::new(Instruction::MOV(scratch, ra), *span));
+                    block.append_inst(Inst::new(Instruction::MOV(ra, rb), *span));
+                    block.append_inst(Inst::new(Instruction::MOV(rb, scratch), *span));
+                    Ok(block)
+                }
+                Builtin::DivFloor => {
+                    let mut block: Block = arguments[0].lower(state)?;
+                    let rx: Reg = block.value_register(&arguments[0])?;
+                    let ry_block: Block = arguments[1].lower(state)?;
+                    let ry: Reg = ry_block.value_register(&arguments[1])?;
+                    block.extend(ry_block);
+                    block.append_inst(Inst::new(
+                        Instruction::SDIV(state.next_register(), rx, ry, Rounding::Floor),
+                        *span,
+                    ));
+                    Ok(block)
+                }
+                Builtin::Cycles => Ok(Block::new(Inst::new(
+                    Instruction::CYC(state.next_register()),
+                    *span,
+                ))),
+                Builtin::Arg => {
+                    let mut block: Block = arguments[0].lower(state)?;
+                    let index: Reg = block.value_register(&arguments[0])?;
+                    block.append_inst(Inst::new(
+                        Instruction::ARG(state.next_register(), index),
+                        *span,
+                    ));
+                    Ok(block)
+                }
+                Builtin::Alloc => {
+                    let mut block: Block = arguments[0].lower(state)?;
+                    let size: Reg = block.value_register(&arguments[0])?;
+                    block.append_inst(Inst::new(
+                        Instruction::ALLOC(state.next_register(), size),
+                        *span,
+                    ));
+                    Ok(block)
+                }
+                builtin @ (Builtin::SizeOf | Builtin::AddressOf) => {
+                    let Expression::Identifier(array) = &arguments[0] else {
+                        return Err(LangError::ExpectedVariable {
+                            span: arguments[0].span(),
+                        });
+                    };
+                    state.types.array_element(array)?;
+                    let pointer = state.deref_pointer(array.borrow_name(), array)?;
+                    let rd = state.next_register();
+                    Ok(Block::new(Inst::new(
+                        match builtin {
+                            Builtin::SizeOf => {
+                                Instruction::CON(rd, (pointer.length * WORD_SIZE) as u32)
+                            }
+                            _ => Instruction::LEA(rd, pointer.address),
+                        },
+                        *span,
+                    )))
+                }
+            },
             Expression::Array {
                 elements: array_elements,
                 span,
@@ -202,7 +642,7 @@ impl Lowerable for Expression {
                 let reg_index = state.next_register();
                 let offset = state.next_register();
                 array_initialisation.append_inst(Inst::new(
-                    Instruction::CON(reg_index, state.next_mem_addr() as u32),
+                    Instruction::LEA(reg_index, state.allocate(array_elements.len(), *span)?),
                     *span,
                 ));
                 array_initialisation
@@ -210,37 +650,129 @@ impl Lowerable for Expression {
 
                 for element in array_elements {
                     let element_expr: Block = element.lower(state)?;
+                    let element_register = element_expr.value_register(element.as_ref())?;
                     array_initialisation.extend(element_expr);
                     array_initialisation.append_inst(Inst::new(
-                        Instruction::STR(
-                            array_initialisation.output_register().unwrap(),
-                            reg_index,
-                            Mode::PostOffset(offset),
-                        ),
+                        Instruction::STR(element_register, reg_index, Mode::PostOffset(offset)),
                         element.span(),
                     ))
                 }
 
                 // should store the base address of the array in the register of the variable.
                 // this might mean moving array intialisation to a statement.
+                // until then the array has no value, rather than that of its last element.
+                array_initialisation.set_output_register(None);
 
                 Ok(array_initialisation)
             }
+            Expression::Repeat {
+                element,
+                count,
+                span,
+            } => {
+                let count = constant_length(count)?;
+                let mut block = Block::empty();
+                let r_base_addr = state.next_register();
+                let r_offset = state.next_register();
+                block.append_inst(Inst::new(
+                    Instruction::LEA(r_base_addr, state.allocate(count, *span)?),
+                    *span,
+                ));
+                block.append_inst(Inst::new(
+                    Instruction::CON(r_offset, WORD_SIZE as u32),
+                    *span,
+                ));
+
+                let element_block: Block = element.lower(state)?;
+                let r_element = element_block.value_register(element.as_ref())?;
+                block.extend(element_block);
+
+                // store the element `count` times, counting up to `count` from zero.
+                let r_stored = state.next_register();
+                let r_count = state.next_register();
+                let r_one = state.next_register();
+                block.append_inst(Inst::new(Instruction::CON(r_stored, 0), *span));
+                block.append_inst(Inst::new(Instruction::CON(r_count, count as u32), *span));
+                block.append_inst(Inst::new(Instruction::CON(r_one, 1), *span));
+
+                let label_uuid = state.fresh_label();
+                let check_label = format!("{label_uuid}-check-condition");
+                let break_label = format!("{label_uuid}-break");
+                for instruction in [
+                    Instruction::LBL(check_label.clone()),
+                    Instruction::CMP(r_stored, r_count, Some(Flag::Ge)),
+                    Instruction::BRAcc(Flag::Ge, break_label.clone()),
+                    Instruction::STR(r_element, r_base_addr, Mode::PostOffset(r_offset)),
+                    Instruction::ADD(r_stored, r_stored, r_one),
+                    Instruction::BRA(check_label),
+                    Instruction::LBL(break_label),
+                ] {
+                    block.append_inst(Inst::new(instruction, *span));
+                }
+
+                // like other arrays, a repeated array has no value.
+                block.set_output_register(None);
+                Ok(block)
+            }
             Expression::Index {
                 variable,
                 index: index_expr,
                 span,
             } => {
-                let base_addr = state.deref_pointer(variable.borrow_name(), span)?;
+                let array = state.deref_pointer(variable.borrow_name(), span)?;
+
+                // constant indices are bounds checked, and negative ones counted from the end, at
+                // compile time.
+                if let Some(index) = constant_index(index_expr) {
+                    let position = match index {
+                        index if index < 0 => index + array.length as i64,
+                        index => index,
+                    };
+                    if position < 0 || position >= array.length as i64 {
+                        return Err(LangError::IndexOutOfBounds {
+                            span: index_expr.span(),
+                            index,
+                            length: array.length,
+                        });
+                    }
+
+                    let r_addr = state.next_register();
+                    let mut block = Block::new(Inst::new(
+                        Instruction::LEA(r_addr, array.address + position as usize * WORD_SIZE),
+                        *span,
+                    ));
+                    block.append_inst(Inst::new(
+                        Instruction::LDR(state.next_register(), r_addr, Mode::None),
+                        *span,
+                    ));
+                    return Ok(block);
+                }
+
                 let r_base_addr = state.next_register();
                 let mut block: Block = Block::new(Inst::new(
-                    Instruction::CON(r_base_addr, base_addr as u32),
+                    Instruction::LEA(r_base_addr, array.address),
                     *span,
                 ));
 
-                block.extend(index_expr.lower(state)?);
-
-                let r_index_output = block.output_register_unchecked();
+                let index_block: Block = index_expr.lower(state)?;
+                let r_index_output = index_block.value_register(index_expr.as_ref())?;
+                block.extend(index_block);
+                if state.bounds_checks {
+                    // compared as unsigned, a negative index is larger than any length, so one
+                    // comparison rules out both ends of the array. An index within it also keeps
+                    // the multiply and the addition below from overflowing.
+                    let r_length = state.next_register();
+                    let in_range = format!("{}-in-range", state.fresh_label());
+                    for instruction in [
+                        Instruction::CON(r_length, array.length as u32),
+                        Instruction::CMP(r_index_output, r_length, None),
+                        Instruction::BRAcc(Flag::Lt, in_range.clone()),
+                        Instruction::TRAP,
+                        Instruction::LBL(in_range),
+                    ] {
+                        block.append_inst(Inst::new(instruction, index_expr.span()));
+                    }
+                }
                 let r_word_size = state.next_register();
                 block.append_inst(Inst::new(
                     Instruction::CON(r_word_size, WORD_SIZE as u32),
@@ -259,58 +791,422 @@ impl Lowerable for Expression {
                     *span,
                 ));
 
-                // this shouldnt be needed
                 Ok(block)
             }
         }
     }
 }
 
+impl Mutate {
+    /// Returns the instruction stepping the variable by one, if the mutation only does that.
+    fn step(&self) -> Option<fn(Reg) -> Instruction> {
+        let Expression::App {
+            app:
+                Application::Binary {
+                    op,
+                    left,
+                    right,
+                    span: _,
+                },
+        } = &self.value
+        else {
+            return None;
+        };
+        let is_variable = |expr: &Expression| matches!(expr, Expression::Identifier(id) if *id.name() == *self.variable);
+        let is_one = |expr: &Expression| constant_index(expr) == Some(1);
+
+        match op {
+            OperatorType::Plus
+                if (is_variable(left) && is_one(right)) || (is_one(left) && is_variable(right)) =>
+            {
+                Some(Instruction::INC)
+            }
+            OperatorType::Minus if is_variable(left) && is_one(right) => Some(Instruction::DEC),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the value of an index known at compile time. Arithmetic on constants is folded, as long
+/// as every step fits in a number.
+fn constant_index(index: &Expression) -> Option<i64> {
+    match index {
+        Expression::Literal {
+            lit: Literal::Number { val, span: _ },
+        } => Some(*val as i64),
+        Expression::Group { expr, span: _ } => constant_index(expr),
+        Expression::App {
+            app:
+                Application::Unary {
+                    op: OperatorType::Minus,
+                    expr,
+                    span: _,
+                },
+        } => constant_index(expr).map(|index| -index),
+        Expression::App {
+            app:
+                Application::Unary {
+                    op: OperatorType::Plus,
+                    expr,
+                    span: _,
+                },
+        } => constant_index(expr),
+        Expression::App {
+            app:
+                Application::Binary {
+                    op,
+                    left,
+                    right,
+                    span: _,
+                },
+        } => {
+            let left = i32::try_from(constant_index(left)?).ok()?;
+            let right = i32::try_from(constant_index(right)?).ok()?;
+            match op {
+                OperatorType::Plus => left.checked_add(right),
+                OperatorType::Minus => left.checked_sub(right),
+                OperatorType::Multiply => left.checked_mul(right),
+                OperatorType::Divide => left.checked_div(right),
+                OperatorType::Modulo => left.checked_rem(right),
+                _ => None,
+            }
+            .map(i64::from)
+        }
+        _ => None,
+    }
+}
+
+/// Lower the condition of an `if` or `while`, returning its block and the flag a `CHK` tests for
+/// the condition holding. A comparison leaves its own flag hint, and a boolean value is compared
+/// with zero, so the flag is always set by the last instruction of the block.
+fn lower_condition(
+    condition: &Expression,
+    state: &mut GenerationState<impl Emitter>,
+) -> Result<(Block, Flag), LangError> {
+    let mut block: Block = condition.lower(state)?;
+    if block.output_register().is_none() {
+        if let Some(flag) = block.latest_flag_hint() {
+            return Ok((block, flag));
+        }
+    }
+
+    let value = block.value_register(condition)?;
+    let zero = state.next_register();
+    block.append_inst(Inst::new(Instruction::CON(zero, 0), condition.span()));
+    block.append_inst(Inst::new(
+        Instruction::CMP(value, zero, Some(Flag::Ne)),
+        condition.span(),
+    ));
+    block.set_output_register(None);
+    Ok((block, Flag::Ne))
+}
+
+/// Lower a condition to a register holding 1 if it holds, and 0 otherwise, so that it can be
+/// combined with `&` and `|`.
+fn lower_boolean(
+    condition: &Expression,
+    state: &mut GenerationState<impl Emitter>,
+) -> Result<(Block, Reg), LangError> {
+    let (mut block, flag) = lower_condition(condition, state)?;
+    let reg = state.next_register();
+    let label = format!("{}-materialised", state.fresh_label());
+    for instruction in [
+        Instruction::CON(reg, 0),
+        Instruction::BRAcc(flag.negate(), label.clone()),
+        Instruction::CON(reg, 1),
+        Instruction::LBL(label),
+    ] {
+        block.append_inst(Inst::new(instruction, condition.span()));
+    }
+    block.set_output_register(Some(reg));
+    Ok((block, reg))
+}
+
+/// Lower `==` or `!=` between two arrays, comparing them element by element. The flags are left
+/// as `Eq` if every element is equal, and as `Ne` after the first that is not.
+fn lower_array_equality(
+    op: OperatorType,
+    (left, a): (&Identifier, Allocation),
+    (right, b): (&Identifier, Allocation),
+    span: Span,
+    state: &mut GenerationState<impl Emitter>,
+) -> Result<Block, LangError> {
+    if a.length != b.length {
+        return Err(LangError::ArrayLengthMismatch {
+            left: left.span(),
+            right: right.span(),
+            left_length: a.length,
+            right_length: b.length,
+        });
+    }
+    let flag = match op {
+        OperatorType::Equal => Flag::Eq,
+        _ => Flag::Ne,
+    };
+    let (ra, rb) = (state.next_register(), state.next_register());
+    let (r_index, r_length, r_word) = (
+        state.next_register(),
+        state.next_register(),
+        state.next_register(),
+    );
+    let (rx, ry) = (state.next_register(), state.next_register());
+    let label_uuid = state.fresh_label();
+    let check_label = format!("{label_uuid}-check-elements");
+    let done_label = format!("{label_uuid}-compared");
+
+    let mut block = Block::empty();
+    for instruction in [
+        Instruction::LEA(ra, a.address),
+        Instruction::LEA(rb, b.address),
+        Instruction::CON(r_index, 0),
+        Instruction::CON(r_length, a.length as u32),
+        Instruction::CON(r_word, WORD_SIZE as u32),
+        Instruction::LBL(check_label.clone()),
+        // once every element has been compared, this leaves `Eq` set.
+        Instruction::CMP(r_index, r_length, Some(Flag::Ge)),
+        Instruction::BRAcc(Flag::Ge, done_label.clone()),
+        Instruction::LDR(rx, ra, Mode::PostOffset(r_word)),
+        Instruction::LDR(ry, rb, Mode::PostOffset(r_word)),
+        Instruction::CMP(rx, ry, Some(flag)),
+        Instruction::BRAcc(Flag::Ne, done_label.clone()),
+        Instruction::INC(r_index),
+        Instruction::BRA(check_label),
+        Instruction::LBL(done_label),
+    ] {
+        block.append_inst(Inst::new(instruction, span));
+    }
+    // the comparison only sets flags, so the loop registers are not its value.
+    block.set_output_register(None);
+    Ok(block)
+}
+
+/// Returns the value of a condition known at compile time.
+fn constant_condition(condition: &Expression) -> Option<bool> {
+    match condition {
+        Expression::Literal {
+            lit: Literal::Boolean { val, span: _ },
+        } => Some(*val),
+        Expression::Group { expr, span: _ } => constant_condition(expr),
+        Expression::App {
+            app:
+                Application::Unary {
+                    op: OperatorType::Not,
+                    expr,
+                    span: _,
+                },
+        } => constant_condition(expr).map(|val| !val),
+        Expression::App {
+            app:
+                Application::Binary {
+                    op,
+                    left,
+                    right,
+                    span: _,
+                },
+        } if matches!(op, OperatorType::And | OperatorType::Or) => {
+            let (left, right) = (constant_condition(left)?, constant_condition(right)?);
+            Some(match op {
+                OperatorType::And => left && right,
+                _ => left || right,
+            })
+        }
+        Expression::App {
+            app:
+                Application::Binary {
+                    op,
+                    left,
+                    right,
+                    span: _,
+                },
+        } => {
+            let (left, right) = (constant_index(left)?, constant_index(right)?);
+            match op {
+                OperatorType::LessThan => Some(left < right),
+                OperatorType::LessThanEq => Some(left <= right),
+                OperatorType::GreaterThan => Some(left > right),
+                OperatorType::GreaterThanEq => Some(left >= right),
+                OperatorType::NotEqual => Some(left != right),
+                OperatorType::Equal => Some(left == right),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns whether every step of computing a constant is a number that isn't negative, so the
+/// machine computes it on unsigned words without overflowing.
+fn folds_unsigned(expr: &Expression) -> bool {
+    let operands_fold = match expr {
+        Expression::Group { expr, span: _ }
+        | Expression::App {
+            app: Application::Unary { expr, .. },
+        } => folds_unsigned(expr),
+        Expression::App {
+            app: Application::Binary { left, right, .. },
+        } => folds_unsigned(left) && folds_unsigned(right),
+        _ => true,
+    };
+    operands_fold && constant_index(expr).is_some_and(|value| value >= 0)
+}
+
+/// Lower a value to be yielded. A number known at compile time is computed by the compiler, so only
+/// its result is loaded. Unless arithmetic wraps, this is only done when computing it on the
+/// machine could not overflow.
+fn lower_yielded(
+    expr: &Expression,
+    state: &mut GenerationState<impl Emitter>,
+) -> Result<Block, LangError> {
+    let constant = match state.wrapping_arithmetic || folds_unsigned(expr) {
+        true => constant_index(expr),
+        false => None,
+    };
+    match constant.and_then(|value| i32::try_from(value).ok()) {
+        Some(value) => Ok(Block::new(Inst::new(
+            Instruction::CON(state.next_register(), value as u32),
+            expr.span(),
+        ))),
+        None => expr.lower(state),
+    }
+}
+
+/// Returns the length of a repeated array, which must be a positive constant.
+fn constant_length(count: &Expression) -> Result<usize, LangError> {
+    constant_index(count)
+        .and_then(|count| usize::try_from(count).ok())
+        .filter(|count| *count > 0)
+        .ok_or(LangError::InvalidArrayLength { span: count.span() })
+}
+
+/// Returns the word a constant element of a `data` table is stored as.
+fn constant_word(element: &Expression) -> Result<u32, LangError> {
+    match element {
+        Expression::Literal {
+            lit: Literal::Char { val, span: _ },
+        } => Ok(*val as u32),
+        Expression::Literal {
+            lit: Literal::Boolean { val, span: _ },
+        } => Ok(*val as u32),
+        _ => constant_index(element)
+            .and_then(|val| i32::try_from(val).ok())
+            .map(|val| val as u32)
+            .ok_or(LangError::NonConstantData {
+                span: element.span(),
+            }),
+    }
+}
+
+impl Lowerable for Data {
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
+        let words: Vec<u32> = match &self.table {
+            Expression::Repeat {
+                element,
+                count,
+                span: _,
+            } => vec![constant_word(element)?; constant_length(count)?],
+            Expression::Array { elements, span: _ } => elements
+                .iter()
+                .map(|element| constant_word(element))
+                .collect::<Result<_, _>>()?,
+            table => return Err(LangError::NonConstantData { span: table.span() }),
+        };
+
+        // the table is in memory before the program runs, so it needs no instructions.
+        let address = state.allocate(words.len(), self.span())?;
+        state.data.resize(address, 0);
+        state
+            .data
+            .extend(words.iter().flat_map(|word| word.to_be_bytes()));
+        state.initialise_pointer(
+            self.name.name().to_owned(),
+            Allocation {
+                address,
+                length: words.len(),
+            },
+        );
+        let ty = state.types.infer(&self.table)?;
+        state.types.declare(self.name.name(), ty);
+        Ok(Block::empty())
+    }
+}
+
 impl Lowerable for Statement {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
         match self {
             Statement::Expr(expr) => expr.lower(state),
             Statement::Let(r#let) => r#let.lower(state),
             Statement::Mutate(mutate) => mutate.lower(state),
             Statement::If(r#if) => r#if.lower(state),
             Statement::While(r#while) => r#while.lower(state),
-            Statement::Yield(expr) => {
-                let mut expr_block = expr.lower(state)?;
-                match expr_block.output_register() {
-                    None => (),
-                    Some(reg) => {
-                        // todo, change this to use the yield instructions span.
-                        expr_block.append_inst(Inst::new(Instruction::YLD(reg), expr.span()))
-                    }
-                };
+            Statement::For(r#for) => r#for.lower(state),
+            Statement::Data(data) => data.lower(state),
+            Statement::Yield(expr, stream) => {
+                let mut expr_block = lower_yielded(expr, state)?;
+                let reg = expr_block.value_register(expr)?;
+                // todo, change this to use the yield instructions span.
+                expr_block.append_inst(Inst::new(Instruction::YLD(reg, *stream), expr.span()));
                 Ok(expr_block)
             }
+            Statement::Return(expr) => {
+                let mut expr_block = lower_yielded(expr, state)?;
+                let reg = expr_block.value_register(expr)?;
+                expr_block.append_inst(Inst::new(Instruction::YLD(reg, Stream::Out), expr.span()));
+                expr_block.append_inst(Inst::new(Instruction::HALT, expr.span()));
+                Ok(expr_block)
+            }
+            Statement::Break(span) => match state.loops.last() {
+                Some(label) => Ok(Block::new(Inst::new(
+                    Instruction::BRA(label.clone()),
+                    *span,
+                ))),
+                None => Err(LangError::BreakOutsideLoop { span: *span }),
+            },
         }
     }
 }
 
 impl Lowerable for If {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
-        // the condition should contain an AST `CMP` instruction, which will contain a flag hint.
-        let mut if_block: Block = self.condition.lower(state)?;
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
+        let otherwise: &[Statement] = self.otherwise.as_deref().unwrap_or_default();
+
+        // a constant condition needs no check. a body that never runs is still lowered, so its
+        // errors are reported, but its instructions are left out.
+        if let Some(condition) = constant_condition(&self.condition) {
+            let inner_block = state.lower_scope(&self.iff)?;
+            let else_block = state.lower_scope(otherwise)?;
+            return Ok(match condition {
+                true => inner_block,
+                false => else_block,
+            });
+        }
 
-        // we check the latest flag hint, and if it exists, generate a `CHK` instruction for it
-        // if no flag hint exists, then the we generate a `CHK Nv` (check never) instruction
+        let (mut if_block, flag) = lower_condition(&self.condition, state)?;
+
+        let label_uuid = state.fresh_label();
+        let end_label = format!("{label_uuid}-end-if");
+        let else_label = format!("{label_uuid}-else");
+
+        // branch past the body, to the `else` if there is one, when the condition fails.
         if_block.append_inst(Inst::new(
-            Instruction::CHK(if_block.latest_flag_hint().unwrap_or(Flag::Nv)),
+            Instruction::BRAcc(
+                flag.negate(),
+                match self.otherwise {
+                    Some(_) => else_label.clone(),
+                    None => end_label.clone(),
+                },
+            ),
             self.condition.span(),
         ));
 
-        let mut if_label = Uuid::new_v4().as_hyphenated().to_string();
-        if_label.push_str("-if");
-
-        if_block.append_inst(Inst::new(Instruction::BRA(if_label.clone()), self.span()));
-
-        let mut inner_block: Block = Block::new(Inst::new(Instruction::LBL(if_label), self.span()));
+        let mut inner_block = state.lower_scope(&self.iff)?;
 
-        for statement in self.iff.iter() {
-            inner_block.extend(statement.lower(state)?);
+        // the body jumps over the `else`, so both rejoin at the end.
+        if self.otherwise.is_some() {
+            inner_block.append_inst(Inst::new(Instruction::BRA(end_label.clone()), self.span()));
+            inner_block.append_inst(Inst::new(Instruction::LBL(else_label), self.span()));
+            inner_block.extend(state.lower_scope(otherwise)?);
         }
+        inner_block.append_inst(Inst::new(Instruction::LBL(end_label), self.span()));
 
         if_block.extend(inner_block);
 
@@ -319,43 +1215,44 @@ impl Lowerable for If {
 }
 
 impl Lowerable for While {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
-        let label_uuid = Uuid::new_v4().as_hyphenated().to_string();
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
+        let label_uuid = state.fresh_label();
         let check_condition_label = format!("{}-check-condition", label_uuid.clone());
-
-        let mut while_block = Block::new(Inst::new(
-            Instruction::LBL(check_condition_label.clone()),
-            self.condition.span(),
-        ));
-
-        while_block.extend(self.condition.lower(state)?);
-
         // let loop_label = format!("{}-loop", label_uuid.clone()); // no need for a loop label
         let break_label = format!("{}-break", label_uuid);
+        let constant = constant_condition(&self.condition);
 
-        while_block.append_inst(Inst::new(
-            Instruction::CHK(
-                while_block
-                    .latest_flag_hint()
-                    .map_or(Flag::Nv, |flag| flag.negate()),
-            ),
-            self.condition.span(),
-        ));
-
-        // append a branch to break if the condition is unsuccessful.
+        // a body that never runs is still lowered, so its errors are reported, but its
+        // instructions are left out.
+        if constant == Some(false) {
+            state.loops.push(break_label);
+            state.lower_scope(&self.body)?;
+            state.loops.pop();
+            return Ok(Block::empty());
+        }
 
-        while_block.append_inst(Inst::new(
-            Instruction::BRA(break_label.clone()),
+        let mut while_block = Block::new(Inst::new(
+            Instruction::LBL(check_condition_label.clone()),
             self.condition.span(),
         ));
 
-        // the block inside the {}.
-        let mut inner_block: Block = Block::empty();
+        // a condition that is always true needs no check, so the loop only ends with a `break`.
+        if constant.is_none() {
+            let (condition_block, flag) = lower_condition(&self.condition, state)?;
+            while_block.extend(condition_block);
 
-        for statement in self.body.iter() {
-            inner_block.extend(statement.lower(state)?);
+            // append a branch to break if the condition is unsuccessful.
+            while_block.append_inst(Inst::new(
+                Instruction::BRAcc(flag.negate(), break_label.clone()),
+                self.condition.span(),
+            ));
         }
 
+        // the block inside the {}, whose variables are forgotten after the loop.
+        state.loops.push(break_label.clone());
+        let mut inner_block: Block = state.lower_scope(&self.body)?;
+        state.loops.pop();
+
         // add a jump back to the condition check
         inner_block.append_inst(Inst::new(
             Instruction::BRA(check_condition_label),
@@ -376,29 +1273,192 @@ impl Lowerable for While {
     }
 }
 
+impl Lowerable for For {
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
+        let span = self.span();
+        let mut for_block = Block::empty();
+        let label_uuid = state.fresh_label();
+        let check_label = format!("{label_uuid}-check-condition");
+        let break_label = format!("{label_uuid}-break");
+        let r_index = state.next_register();
+        let r_item = state.next_register();
+
+        let item_ty = match &self.iterable {
+            Iterable::Array(array) => {
+                let pointer = state.deref_pointer(array.borrow_name(), array)?;
+                let r_addr = state.next_register();
+                let r_offset = state.next_register();
+                let r_length = state.next_register();
+                for instruction in [
+                    Instruction::LEA(r_addr, pointer.address),
+                    Instruction::CON(r_offset, WORD_SIZE as u32),
+                    Instruction::CON(r_index, 0),
+                    Instruction::CON(r_length, pointer.length as u32),
+                    // load each element into the item's register before running the body.
+                    Instruction::LBL(check_label.clone()),
+                    Instruction::CMP(r_index, r_length, Some(Flag::Ge)),
+                    Instruction::BRAcc(Flag::Ge, break_label.clone()),
+                    Instruction::LDR(r_item, r_addr, Mode::PostOffset(r_offset)),
+                ] {
+                    for_block.append_inst(Inst::new(instruction, span));
+                }
+
+                let Type::Array(item_ty) =
+                    state.types.infer(&Expression::Identifier(array.clone()))?
+                else {
+                    return Err(LangError::UninitialisedPointer {
+                        span: array.span(),
+                        name: array.name().to_owned(),
+                    });
+                };
+                *item_ty
+            }
+            Iterable::Range { start, end, .. } => {
+                // both ends are evaluated once, before the first iteration, and copied so that
+                // the body can't change them by assigning to a variable they were read from.
+                let start_block: Block = start.lower(state)?;
+                let r_start = start_block.value_register(start)?;
+                for_block.append_block(start_block);
+                let end_block: Block = end.lower(state)?;
+                let r_end_value = end_block.value_register(end)?;
+                for_block.append_block(end_block);
+                let r_end = state.next_register();
+                for_block.append_inst(Inst::new(Instruction::MOV(r_end, r_end_value), end.span()));
+
+                // numbers are signed, so they are compared as signed.
+                let item_ty = state.types.infer(start)?;
+                let compare = match item_ty {
+                    Type::Num => Instruction::SCMP,
+                    _ => Instruction::CMP,
+                };
+                // the item is a copy of the counter, so the body can change it without changing
+                // how many times the loop runs.
+                for instruction in [
+                    Instruction::MOV(r_index, r_start),
+                    Instruction::LBL(check_label.clone()),
+                    compare(r_index, r_end, Some(Flag::Ge)),
+                    Instruction::BRAcc(Flag::Ge, break_label.clone()),
+                    Instruction::MOV(r_item, r_index),
+                ] {
+                    for_block.append_inst(Inst::new(instruction, span));
+                }
+                item_ty
+            }
+            Iterable::Counter { start, .. } => {
+                let start_block: Block = start.lower(state)?;
+                let r_start = start_block.value_register(start.as_ref())?;
+                for_block.append_block(start_block);
+                for instruction in [
+                    Instruction::MOV(r_item, r_start),
+                    Instruction::LBL(check_label.clone()),
+                ] {
+                    for_block.append_inst(Inst::new(instruction, span));
+                }
+                state.types.infer(start)?
+            }
+        };
+
+        // the item is only in scope within the loop, so restore whatever it shadowed afterwards.
+        let shadowed_ty = state.types.declare(self.item.name(), item_ty);
+        let shadowed = state.variables.insert(self.item.name().to_owned(), r_item);
+        if let Iterable::Counter { condition, .. } = &self.iterable {
+            let (condition_block, flag) = lower_condition(condition, state)?;
+            for_block.extend(condition_block);
+            for_block.append_inst(Inst::new(
+                Instruction::BRAcc(flag.negate(), break_label.clone()),
+                condition.span(),
+            ));
+        }
+        state.loops.push(break_label.clone());
+        for_block.extend(state.lower_scope(&self.body)?);
+        state.loops.pop();
+        match &self.iterable {
+            Iterable::Counter { step, .. } => for_block.extend(step.lower(state)?),
+            _ => for_block.append_inst(Inst::new(Instruction::INC(r_index), span)),
+        }
+        match shadowed {
+            Some(reg) => state.variables.insert(self.item.name().to_owned(), reg),
+            None => state.variables.remove(self.item.name()),
+        };
+        state.types.restore(self.item.name(), shadowed_ty);
+
+        for instruction in [Instruction::BRA(check_label), Instruction::LBL(break_label)] {
+            for_block.append_inst(Inst::new(instruction, span));
+        }
+
+        // a loop has no value.
+        for_block.set_output_register(None);
+        Ok(for_block)
+    }
+}
+
+/// Move the value of an assignment into each of the variables chained before it, from right to left.
+fn assign_chained(
+    block: &mut Block,
+    chained: &[Identifier],
+    value: &Expression,
+    state: &mut GenerationState<impl Emitter>,
+) -> Result<(), LangError> {
+    if chained.is_empty() {
+        return Ok(());
+    }
+    let reg = block.value_register(value)?;
+    for variable in chained.iter().rev() {
+        let variable_register = *state.variable_register(variable.borrow_name(), variable)?;
+        block.append_inst(Inst::new(
+            Instruction::MOV(variable_register, reg),
+            variable.span(),
+        ));
+    }
+    Ok(())
+}
+
 impl Lowerable for Let {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
-        Ok(match self.value {
-            Expression::Array {
-                elements: _,
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
+        let ty = state.types.infer(&self.value)?;
+        let mut block = self.lower_value(state)?;
+        state.types.declare(&self.variable, ty);
+        assign_chained(&mut block, &self.chained, &self.value, state)?;
+        Ok(block)
+    }
+}
+
+impl Let {
+    /// Lower the value of the declaration, initialising the variable to hold it.
+    fn lower_value(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
+        Ok(match &self.value {
+            Expression::Array { elements, span: _ } => {
+                let base_mem_addr = state.next_mem_addr; // the array is allocated from here when lowered
+                state.initialise_pointer(
+                    self.variable.clone(),
+                    Allocation {
+                        address: base_mem_addr,
+                        length: elements.len(),
+                    },
+                );
+                self.value.lower(state)?
+            }
+            Expression::Repeat {
+                element: _,
+                count,
                 span: _,
             } => {
-                let base_mem_addr = state.next_mem_addr; // this doesnt increment the address unlike the method call
-                state.initialise_pointer(self.variable.clone(), base_mem_addr);
+                let base_mem_addr = state.next_mem_addr;
+                state.initialise_pointer(
+                    self.variable.clone(),
+                    Allocation {
+                        address: base_mem_addr,
+                        length: constant_length(count)?,
+                    },
+                );
                 self.value.lower(state)?
             }
             _ => {
                 let block: Block = self.value.lower(state)?;
 
-                match block.output_register() {
-                    // this doesn't require an instruction
-                    Some(reg) => state.initialise_variable(self.variable.clone(), reg),
-                    None => {
-                        return Err(LangError::NullValueExpression {
-                            span: self.value.span(),
-                        })
-                    }
-                }
+                // this doesn't require an instruction
+                let reg = block.value_register(&self.value)?;
+                state.initialise_variable(self.variable.clone(), reg);
                 block
             }
         })
@@ -406,31 +1466,33 @@ impl Lowerable for Let {
 }
 
 impl Lowerable for Mutate {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
+        // `x := x + 1` and `x := x - 1` change the variable's register in place.
+        if let Some(instruction) = self.step().filter(|_| self.chained.is_empty()) {
+            let variable_register = *state.variable_register(&self.variable, self.span())?;
+            return Ok(Block::new(Inst::new(
+                instruction(variable_register),
+                self.span(),
+            )));
+        }
+
         let mut block: Block = self.value.lower(state)?;
+        assign_chained(&mut block, &self.chained, &self.value, state)?;
 
         let variable_register = state.variable_register(&self.variable, self.span())?;
 
-        match block.output_register() {
-            Some(reg) => {
-                block.append_inst(Inst::new(
-                    Instruction::MOV(variable_register.to_owned(), reg),
-                    self.span(),
-                ));
-            }
-            None => {
-                return Err(LangError::NullValueExpression {
-                    span: self.value.span(),
-                })
-            }
-        }
+        let reg = block.value_register(&self.value)?;
+        block.append_inst(Inst::new(
+            Instruction::MOV(variable_register.to_owned(), reg),
+            self.span(),
+        ));
 
         Ok(block)
     }
 }
 
 impl Lowerable for Identifier {
-    fn lower(&self, state: &mut GenerationState) -> Result<Block, LangError> {
+    fn lower(&self, state: &mut GenerationState<impl Emitter>) -> Result<Block, LangError> {
         // set the output register to be the variable register, and give back a block with no instructions
         let variable_register = state.variable_register(self.borrow_name(), self)?;
         let mut block = Block::empty();
@@ -438,3 +1500,479 @@ impl Lowerable for Identifier {
         Ok(block)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex::Lexer, parse::LangParser, try_compile};
+
+    #[test]
+    fn binary_block_covers_operands() {
+        let src = "let a := 1;\nlet b := 2;\nyield (a * 3) + (b - 1);";
+        let tokens = Lexer::new(src).run().unwrap();
+        let mut ast = LangParser::new(&tokens)
+            .parse_statement(Vec::new())
+            .unwrap();
+        let Some(Statement::Yield(expr, _)) = ast.pop() else {
+            panic!("expected a yield");
+        };
+        let mut state = GenerationState::new();
+        generate_program(&mut state, ast).unwrap();
+
+        let block = expr.lower(&mut state).unwrap();
+        let span = block.span().unwrap();
+        let (a, b) = (src.find("a *").unwrap(), src.find("b -").unwrap());
+        assert!(span.is_superset(&Span::new((a, a + 1))));
+        assert!(span.is_superset(&Span::new((b, b + 1))));
+        assert_eq!(expr.span().span(), span.span());
+    }
+
+    #[test]
+    fn extending_lowers_only_new_statements() {
+        let parse = |src: &str| {
+            let tokens = Lexer::new(src).run().unwrap();
+            LangParser::new(&tokens)
+                .parse_statement(Vec::new())
+                .unwrap()
+        };
+        let mut state = GenerationState::new();
+        let mut program = generate_program(&mut state, parse("let total := 0;")).unwrap();
+
+        // each line lowers to the same instructions however long the session has been.
+        for line in 0..200 {
+            let before = program.len();
+            extend_program(&mut state, &mut program, parse("total := total + 1;")).unwrap();
+            assert_eq!(before + 1, program.len());
+            assert_eq!(
+                program[1].clone().into_iter().count(),
+                program[line + 1].clone().into_iter().count()
+            );
+        }
+
+        let whole = generate_program(
+            &mut GenerationState::new(),
+            parse(&format!(
+                "let total := 0;\n{}",
+                "total := total + 1;\n".repeat(200)
+            )),
+        )
+        .unwrap();
+        let incremental: Vec<Instruction> = program
+            .into_iter()
+            .flatten()
+            .map(Inst::instruction)
+            .collect();
+        let whole: Vec<Instruction> = whole.into_iter().flatten().map(Inst::instruction).collect();
+        assert!(incremental == whole);
+    }
+
+    #[test]
+    fn arrays_do_not_overlap() {
+        let instructions = try_compile("let a := [1, 2, 3];\nlet b := [4];").unwrap();
+        assert!(instructions.contains(&Instruction::LEA(Reg(5), 12)));
+    }
+
+    #[test]
+    fn array_layout_of_non_array() {
+        // lowering is checked on its own, without the type checker running first.
+        let tokens = Lexer::new("let x := 1;\nyield addressof(x);")
+            .run()
+            .unwrap();
+        let ast = LangParser::new(&tokens)
+            .parse_statement(Vec::new())
+            .unwrap();
+        assert!(matches!(
+            generate_program(&mut GenerationState::new(), ast),
+            Err(LangError::ExpectedArray { .. })
+        ));
+    }
+
+    /// Records the instructions emitted to it, numbering registers from 100.
+    struct RecordingEmitter {
+        emitted: Vec<Instruction>,
+        next_reg: u32,
+    }
+
+    impl Emitter for RecordingEmitter {
+        fn emit(&mut self, instruction: Instruction, _span: Span) {
+            self.emitted.push(instruction);
+        }
+
+        fn fresh_reg(&mut self) -> Reg {
+            self.next_reg += 1;
+            Reg(self.next_reg - 1)
+        }
+
+        fn fresh_label(&mut self) -> String {
+            format!("label{}", self.next_reg)
+        }
+    }
+
+    #[test]
+    fn custom_emitter() {
+        let emitter = RecordingEmitter {
+            emitted: Vec::new(),
+            next_reg: 100,
+        };
+        let mut state = GenerationState::with_emitter(emitter, usize::MAX);
+        let tokens = Lexer::new("yield 1 + 2;").run().unwrap();
+        let ast = LangParser::new(&tokens)
+            .parse_statement(Vec::new())
+            .unwrap();
+        emit_program(&mut state, ast).unwrap();
+        assert!(
+            vec![
+                Instruction::CON(Reg(100), 3),
+                Instruction::YLD(Reg(100), Stream::Out)
+            ] == state.emitter.emitted
+        );
+    }
+
+    #[test]
+    fn arrays_cannot_overlap_spilled_values() {
+        let lower = |state: &mut GenerationState, src: &str| {
+            let tokens = Lexer::new(src).run().unwrap();
+            let ast = LangParser::new(&tokens)
+                .parse_statement(Vec::new())
+                .unwrap();
+            generate_program(state, ast).map(|_| ())
+        };
+        let mut state = GenerationState::with_memory_size(64);
+        for _ in 0..8 {
+            state.allocate_spill(1, Span::new((0, 0))).unwrap();
+        }
+        assert!(lower(&mut state, "let xs := [0; 8];").is_ok());
+        assert!(matches!(
+            lower(&mut state, "let ys := [1, 2];"),
+            Err(LangError::MemoryOverlap {
+                data_end: 40,
+                stack_base: 32,
+                ..
+            })
+        ));
+        assert!(matches!(
+            state.allocate_spill(100, Span::new((0, 0))),
+            Err(LangError::MemoryOverlap { .. })
+        ));
+    }
+
+    #[test]
+    fn constant_index_out_of_bounds() {
+        for index in ["3", "-4", "-(4)"] {
+            let src = format!("let a := [1, 2, 3];\nyield a[{index}];");
+            assert!(
+                matches!(
+                    try_compile(&src),
+                    Err(LangError::IndexOutOfBounds { length: 3, .. })
+                ),
+                "`a[{index}]` should be out of bounds"
+            );
+        }
+    }
+
+    #[test]
+    fn loop_variables_are_forgotten_after_the_loop() {
+        for src in [
+            "let i := 5;\nwhile i < 3 {\nlet z := 4;\ni := i + 1;\n}\nyield z;",
+            "while false {\nlet z := 4;\n}\nyield z;",
+            "for i in 0..3 {\nlet z := 4;\n}\nyield z;",
+        ] {
+            assert!(
+                matches!(
+                    try_compile(src),
+                    Err(LangError::UninitialisedVariable { .. })
+                ),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn constant_conditions_are_pruned() {
+        let branches = |src: &str| {
+            try_compile(src)
+                .unwrap()
+                .into_iter()
+                .filter(|inst| {
+                    matches!(
+                        inst,
+                        Instruction::CMP(_, _, _)
+                            | Instruction::SCMP(_, _, _)
+                            | Instruction::CHK(_)
+                            | Instruction::BRA(_)
+                            | Instruction::BRAcc(_, _)
+                    )
+                })
+                .count()
+        };
+        assert!(try_compile("if false { yield 1; }").unwrap().is_empty());
+        assert!(try_compile("while (1 > 2) { yield 1; }")
+            .unwrap()
+            .is_empty());
+        assert_eq!(0, branches("if !false { yield 1; }"));
+        assert!(try_compile("if true { yield 1; }")
+            .unwrap()
+            .iter()
+            .any(|inst| matches!(inst, Instruction::YLD(_, _))));
+        // an infinite loop only branches back to its start, and out of it with `break`.
+        assert_eq!(2, branches("while true { yield 1; break; }"));
+        assert!(try_compile("if false { let x := 1; }\nyield x;").is_err());
+    }
+
+    #[test]
+    fn numbers_are_compared_as_signed() {
+        let signed = |src: &str| {
+            try_compile(src)
+                .unwrap()
+                .iter()
+                .rev()
+                .find_map(|inst| match inst {
+                    Instruction::SCMP(_, _, _) => Some(true),
+                    Instruction::CMP(_, _, _) => Some(false),
+                    _ => None,
+                })
+        };
+        assert_eq!(
+            Some(true),
+            signed("let a := 1;\nwhile a < 3 {\na := a + 1;\n}")
+        );
+        assert_eq!(Some(false), signed("let c := 'c';\nwhile c == 'a' { }"));
+        assert_eq!(
+            Some(true),
+            signed("let xs := [1, 2];\nfor x in xs {\nwhile x < 0 { }\n}")
+        );
+    }
+
+    #[test]
+    fn constant_yields_are_folded() {
+        assert!(
+            try_compile("yield 2 + 3;\nyield 4 * (5 - 1);\nyield -(7 / 2);").unwrap()
+                == vec![
+                    Instruction::CON(Reg(0), 5),
+                    Instruction::YLD(Reg(0), Stream::Out),
+                    Instruction::CON(Reg(1), 16),
+                    Instruction::YLD(Reg(1), Stream::Out),
+                    Instruction::CON(Reg(2), -3i32 as u32),
+                    Instruction::YLD(Reg(2), Stream::Out),
+                ]
+        );
+        // a division by zero is left for the VM to report, and variables are not constants.
+        for src in ["yield 1 / 0;", "let x := 1;\nyield x + 1;"] {
+            assert!(
+                try_compile(src)
+                    .unwrap()
+                    .iter()
+                    .any(|inst| matches!(inst, Instruction::SDIV(..) | Instruction::ADD(..))),
+                "`{src}` should not be folded"
+            );
+        }
+    }
+
+    #[test]
+    fn register_provenance() {
+        let src = "let a := 1;\nlet b := 2;\nlet c := a + b;\nlet d := 3;";
+        let tokens = Lexer::new(src).run().unwrap();
+        let ast = LangParser::new(&tokens)
+            .parse_statement(Vec::new())
+            .unwrap();
+        let mut state = GenerationState::new();
+        let instructions = generate_program(&mut state, ast)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        let module = AirModule::new(instructions, &state);
+
+        let at = |text: &str| {
+            let start = src.find(text).unwrap();
+            Span::new((start, start + text.len()))
+        };
+        assert_eq!(
+            vec![at("1"), at("2"), at("a + b")],
+            module.spans_for_register(module.variables["c"])
+        );
+    }
+
+    #[test]
+    fn boolean_literals() {
+        assert!(
+            try_compile("true;\nlet b := false;\nyield b;").unwrap()
+                == vec![
+                    Instruction::CON(Reg(0), 1),
+                    Instruction::CON(Reg(1), 0),
+                    Instruction::YLD(Reg(1), Stream::Out),
+                ]
+        );
+    }
+
+    #[test]
+    fn value_less_operands_are_errors() {
+        for (src, span) in [
+            ("let a := [1, 2 < 3];", (13, 18)),
+            ("yield -(1 < 2);", (7, 14)),
+            ("yield !(1 == 2);", (7, 15)),
+            ("let b := (1 < 2);", (9, 16)),
+        ] {
+            match try_compile(src) {
+                Err(LangError::NullValueExpression { span: found }) => {
+                    assert_eq!(Span::new(span), found, "`{src}`")
+                }
+                _ => panic!("`{src}` should have a value-less operand"),
+            }
+        }
+    }
+
+    #[test]
+    fn chained_comparison_suggests_and() {
+        match try_compile("let x := 5;\nif 0 < x < 10 {\nyield x;\n}") {
+            Err(LangError::ChainedComparison { first, second }) => {
+                assert_eq!(Span::new((16, 19)), first);
+                assert_eq!(Span::new((20, 23)), second);
+            }
+            _ => panic!("a chained comparison should be an error"),
+        }
+        // grouping a comparison makes the comparison of its result deliberate.
+        assert!(!matches!(
+            try_compile("let x := 5;\nif (x < 10) == true {\nyield x;\n}"),
+            Err(LangError::ChainedComparison { .. })
+        ));
+        assert!(try_compile("let x := 5;\nif (0 < x) & (x < 10) {\nyield x;\n}").is_ok());
+    }
+
+    #[test]
+    fn yield_without_value() {
+        for src in [
+            "let x := 1;\nyield x < 3;",
+            "yield 1 == 2;",
+            "yield [1, 2];",
+        ] {
+            assert!(
+                matches!(try_compile(src), Err(LangError::NullValueExpression { .. })),
+                "`{src}` has no value to yield"
+            );
+        }
+    }
+
+    #[test]
+    fn repeated_array_length() {
+        assert!(try_compile("let a := [7; 4];\nyield a[3];").is_ok());
+        assert!(matches!(
+            try_compile("let a := [7; 4];\nyield a[4];"),
+            Err(LangError::IndexOutOfBounds { length: 4, .. })
+        ));
+        assert!(matches!(
+            try_compile("let a := [7; 2 * (3 - 1)];\nyield a[4];"),
+            Err(LangError::IndexOutOfBounds { length: 4, .. })
+        ));
+        for count in ["-1", "0", "n", "n + 1", "2 - 3", "1 / 0"] {
+            let src = format!("let n := 2;\nlet a := [7; {count}];");
+            assert!(
+                matches!(try_compile(&src), Err(LangError::InvalidArrayLength { .. })),
+                "`{count}` is not a valid array length"
+            );
+        }
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        assert!(matches!(
+            try_compile("break;"),
+            Err(LangError::BreakOutsideLoop { .. })
+        ));
+        assert!(matches!(
+            try_compile("let x := 1;\nwhile x < 3 {\nx := x + 1;\n}\nbreak;"),
+            Err(LangError::BreakOutsideLoop { .. })
+        ));
+    }
+
+    #[test]
+    fn increment_and_decrement() {
+        let instructions =
+            try_compile("let x := 1;\nx := x + 1;\nx := 1 + x;\nx := x - 1;").unwrap();
+        assert!(
+            instructions
+                == vec![
+                    Instruction::CON(Reg(0), 1),
+                    Instruction::INC(Reg(0)),
+                    Instruction::INC(Reg(0)),
+                    Instruction::DEC(Reg(0)),
+                ]
+        );
+        let instructions =
+            try_compile("let x := 1;\nlet y := 2;\nx := y + 1;\nx := 1 - x;").unwrap();
+        assert!(!instructions
+            .iter()
+            .any(|inst| matches!(inst, Instruction::INC(_) | Instruction::DEC(_))));
+    }
+
+    #[test]
+    fn conditions_end_with_a_comparison() {
+        for src in [
+            "let a := 1;\nif a < 2 { }",
+            "let b := true;\nif b { }",
+            "let b := true;\nwhile !b { }",
+        ] {
+            let instructions = try_compile(src).unwrap();
+            let check = instructions
+                .iter()
+                .position(|inst| matches!(inst, Instruction::BRAcc(_, _)))
+                .unwrap();
+            assert!(
+                matches!(
+                    instructions[check - 1],
+                    Instruction::CMP(_, _, Some(_)) | Instruction::SCMP(_, _, Some(_))
+                ),
+                "`{src}` should be checked after a comparison"
+            );
+        }
+    }
+
+    #[test]
+    fn annotated_registers() {
+        let names = HashMap::from([(Reg(0), "x".to_string())]);
+        let instructions = [
+            Instruction::CON(Reg(0), 1),
+            Instruction::MOV(Reg(10), Reg(0)),
+        ];
+        assert_eq!(
+            "CONST %0/*x*/, =0x1\nMOV %10, %0/*x*/\n",
+            render_annotated_air(&instructions, &names)
+        );
+    }
+
+    #[test]
+    fn conditions_branch_with_bracc() {
+        let instructions = try_compile("let a := 1;\nif a < 2 {\nyield a;\n}").unwrap();
+        assert!(!instructions
+            .iter()
+            .any(|inst| matches!(inst, Instruction::CHK(_))));
+        // the operands, the comparison, a branch past the body, the body and the branch's label.
+        assert_eq!(6, instructions.len());
+        assert!(matches!(instructions[3], Instruction::BRAcc(Flag::Ge, _)));
+    }
+
+    #[test]
+    fn addresses_are_loaded_with_lea() {
+        let instructions = try_compile("let a := [7];\nlet b := [8, 9];\nyield b[1];").unwrap();
+        let addresses: Vec<usize> = instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                Instruction::LEA(_, address) => Some(*address),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![0, 4, 8], addresses);
+        for value in [7, 8, 9] {
+            assert!(instructions
+                .iter()
+                .any(|inst| matches!(inst, Instruction::CON(_, v) if *v == value)));
+        }
+    }
+
+    #[test]
+    fn negative_constant_index() {
+        let instructions = try_compile("let a := [1, 2, 3];\nyield a[-1];").unwrap();
+        assert!(instructions.contains(&Instruction::LEA(Reg(5), 8)));
+        assert!(instructions.contains(&Instruction::LDR(Reg(6), Reg(5), Mode::None)));
+    }
+}
@@ -0,0 +1,304 @@
+use super::air::{Inst, Instruction, Reg};
+use std::collections::{HashMap, HashSet};
+
+/// An operation with its register to write to cleared, so that it holds only what the value is
+/// computed from, such as the rounding of a division, and the registers it reads.
+type Computation = (Instruction, Vec<Reg>);
+
+/// Replace recomputations of a value within a basic block with a `MOV` from the register holding
+/// the earlier result. Availability is forgotten at every label, branch and check, so no value is
+/// reused across a change in control flow.
+pub fn eliminate_common_subexpressions(instructions: Vec<Inst>) -> Vec<Inst> {
+    let mut available: HashMap<Computation, Reg> = HashMap::new();
+    let mut optimised: Vec<Inst> = Vec::with_capacity(instructions.len());
+
+    for mut inst in instructions {
+        match inst.instruction {
+            Instruction::LBL(_)
+            | Instruction::BRA(_)
+            | Instruction::BRAcc(_, _)
+            | Instruction::CHK(_) => available.clear(),
+            _ => (),
+        }
+
+        let computation = pure_computation(&inst.instruction);
+        let written = inst.instruction.written_registers();
+
+        if let Some(key) = &computation {
+            if let Some(reg) = available.get(key) {
+                inst.instruction = Instruction::MOV(written[0], *reg);
+            }
+        }
+
+        // any value computed from, or held in, a register that has just changed is stale.
+        available.retain(|(_, operands), reg| {
+            !written.contains(reg) && !operands.iter().any(|r| written.contains(r))
+        });
+
+        if let Some((ty, operands)) = computation {
+            if !operands.iter().any(|r| written.contains(r)) {
+                available.insert((ty, operands), written[0]);
+            }
+        }
+
+        optimised.push(inst);
+    }
+    optimised
+}
+
+/// Move computations that give the same value on every iteration of a loop to just before it, so
+/// they run once. A loop runs from a label to the last branch back to it, and its computations are
+/// only moved if nothing outside the loop branches to the label. A computation is invariant when
+/// none of its operands are written within the loop, and its result register is written nowhere
+/// else in the program. When `arithmetic_traps`, as when overflow stops the program, arithmetic is
+/// left in the loop, since it could stop the program on an iteration that would never have run.
+pub fn hoist_loop_invariants(mut instructions: Vec<Inst>, arithmetic_traps: bool) -> Vec<Inst> {
+    // hoisting one computation can make those that read it invariant, so repeat until none move.
+    loop {
+        let hoisted = loop_invariants(&instructions, arithmetic_traps);
+        if hoisted.is_empty() {
+            return instructions;
+        }
+
+        let mut moved: HashMap<usize, Vec<Inst>> = HashMap::new();
+        let mut optimised: Vec<Option<Inst>> = instructions.into_iter().map(Some).collect();
+        for (index, start) in hoisted {
+            let inst = optimised[index]
+                .take()
+                .expect("an instruction is only hoisted once");
+            moved.entry(start).or_default().push(inst);
+        }
+        instructions = Vec::with_capacity(optimised.len());
+        for (index, inst) in optimised.into_iter().enumerate() {
+            if let Some(preheader) = moved.remove(&index) {
+                instructions.extend(preheader);
+            }
+            instructions.extend(inst);
+        }
+    }
+}
+
+/// Returns the index of each invariant computation, in order, and the index of the label of the
+/// outermost loop it can be moved out of.
+fn loop_invariants(instructions: &[Inst], arithmetic_traps: bool) -> Vec<(usize, usize)> {
+    let mut writes: HashMap<Reg, usize> = HashMap::new();
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut branches: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, inst) in instructions.iter().enumerate() {
+        for reg in inst.instruction.written_registers() {
+            *writes.entry(reg).or_default() += 1;
+        }
+        match &inst.instruction {
+            Instruction::LBL(label) => {
+                labels.insert(label, index);
+            }
+            Instruction::BRA(label) | Instruction::BRAcc(_, label) => {
+                branches.entry(label).or_default().push(index)
+            }
+            _ => (),
+        }
+    }
+
+    // loops ordered by their labels, so an outer loop comes before the loops within it.
+    let mut loops: Vec<(usize, usize)> = labels
+        .iter()
+        .filter_map(|(label, &start)| {
+            let sources = branches.get(label)?;
+            let end = *sources.iter().max()?;
+            let entered_outside = sources.iter().any(|&source| source < start);
+            let after_check =
+                start > 0 && matches!(instructions[start - 1].instruction, Instruction::CHK(_));
+            (end > start && !entered_outside && !after_check).then_some((start, end))
+        })
+        .collect();
+    loops.sort();
+
+    let mut hoisted: HashMap<usize, usize> = HashMap::new();
+    for (start, end) in loops {
+        let written: HashSet<Reg> = instructions[start..=end]
+            .iter()
+            .flat_map(|inst| inst.instruction.written_registers())
+            .collect();
+        for index in start + 1..end {
+            let instruction = &instructions[index].instruction;
+            let invariant = match instruction {
+                Instruction::CON(_, _) => true,
+                // a division could stop the program on an iteration that would never have run it.
+                Instruction::DIV(_, _, _)
+                | Instruction::SDIV(_, _, _, _)
+                | Instruction::MOD(_, _, _) => false,
+                Instruction::ADD(_, _, _)
+                | Instruction::SUB(_, _, _)
+                | Instruction::MUL(_, _, _)
+                    if arithmetic_traps =>
+                {
+                    false
+                }
+                _ => pure_computation(instruction).is_some(),
+            };
+            let checked = matches!(instructions[index - 1].instruction, Instruction::CHK(_));
+            if invariant
+                && !checked
+                && !hoisted.contains_key(&index)
+                && instruction
+                    .written_registers()
+                    .iter()
+                    .all(|reg| writes[reg] == 1)
+                && !instruction
+                    .read_registers()
+                    .iter()
+                    .any(|reg| written.contains(reg))
+            {
+                hoisted.insert(index, start);
+            }
+        }
+    }
+
+    let mut hoisted: Vec<(usize, usize)> = hoisted.into_iter().collect();
+    hoisted.sort();
+    hoisted
+}
+
+/// Returns the computation performed by an instruction if it only depends on its operand registers.
+fn pure_computation(instruction: &Instruction) -> Option<Computation> {
+    let mut operation = instruction.clone();
+    match &mut operation {
+        Instruction::ADD(rd, _, _)
+        | Instruction::SUB(rd, _, _)
+        | Instruction::MUL(rd, _, _)
+        | Instruction::DIV(rd, _, _)
+        | Instruction::SDIV(rd, _, _, _)
+        | Instruction::MOD(rd, _, _)
+        | Instruction::AND(rd, _, _)
+        | Instruction::ORR(rd, _, _)
+        | Instruction::NOT(rd, _) => *rd = Reg(0),
+        _ => return None,
+    }
+    Some((operation, instruction.read_registers()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        air::{generate_program, GenerationState},
+        lex::Lexer,
+        parse::LangParser,
+    };
+
+    fn build(src: &str) -> Vec<Instruction> {
+        let tokens = Lexer::new(src).run().unwrap();
+        let ast = LangParser::new(&tokens)
+            .parse_statement(Vec::new())
+            .unwrap();
+        let air: Vec<Inst> = generate_program(&mut GenerationState::new(), ast)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        eliminate_common_subexpressions(air)
+            .into_iter()
+            .map(Inst::instruction)
+            .collect()
+    }
+
+    fn build_and_hoist(src: &str) -> Vec<Instruction> {
+        let tokens = Lexer::new(src).run().unwrap();
+        let ast = LangParser::new(&tokens)
+            .parse_statement(Vec::new())
+            .unwrap();
+        let air: Vec<Inst> = generate_program(&mut GenerationState::new(), ast)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        hoist_loop_invariants(eliminate_common_subexpressions(air), false)
+            .into_iter()
+            .map(Inst::instruction)
+            .collect()
+    }
+
+    #[test]
+    fn repeated_sum_is_computed_once() {
+        let instructions = build("let a := 1;\nlet b := 2;\nyield (a + b) + (a + b);");
+        let sums_of_a_and_b = instructions
+            .iter()
+            .filter(|inst| **inst == Instruction::ADD(Reg(2), Reg(0), Reg(1)))
+            .count();
+        assert_eq!(1, sums_of_a_and_b);
+        assert!(instructions.contains(&Instruction::MOV(Reg(3), Reg(2))));
+        assert!(instructions.contains(&Instruction::ADD(Reg(4), Reg(2), Reg(3))));
+    }
+
+    #[test]
+    fn divisions_with_different_rounding_are_kept_apart() {
+        let instructions = build("let a := -7;\nlet b := 2;\nyield a / b;\nyield div_floor(a, b);");
+        let divisions = instructions
+            .iter()
+            .filter(|inst| matches!(inst, Instruction::SDIV(_, _, _, _)))
+            .count();
+        assert_eq!(2, divisions);
+    }
+
+    #[test]
+    fn mutated_operand_is_recomputed() {
+        let instructions = build("let a := 1;\nlet b := 2;\nyield a + b;\na := 5;\nyield a + b;");
+        let sums = instructions
+            .iter()
+            .filter(|inst| matches!(inst, Instruction::ADD(_, _, _)))
+            .count();
+        assert_eq!(2, sums);
+    }
+
+    #[test]
+    fn invariant_constant_is_computed_before_loop() {
+        let instructions: Vec<Instruction> =
+            build_and_hoist("let i := 0;\nlet n := 3;\nwhile i < n * 10 {\ni := i + 1;\n}");
+        let label = instructions
+            .iter()
+            .position(|inst| matches!(inst, Instruction::LBL(_)))
+            .unwrap();
+        let ten = instructions
+            .iter()
+            .position(|inst| matches!(inst, Instruction::CON(_, 10)))
+            .unwrap();
+        let product = instructions
+            .iter()
+            .position(|inst| matches!(inst, Instruction::MUL(_, _, _)))
+            .unwrap();
+        assert!(ten < label);
+        assert!(product < label);
+        let constants = instructions
+            .iter()
+            .filter(|inst| matches!(inst, Instruction::CON(_, 10)))
+            .count();
+        assert_eq!(1, constants);
+    }
+
+    #[test]
+    fn loop_dependent_computation_stays_in_loop() {
+        let instructions: Vec<Instruction> =
+            build_and_hoist("let i := 0;\nwhile i < i * 2 + 1 {\ni := i + 1;\n}");
+        let label = instructions
+            .iter()
+            .position(|inst| matches!(inst, Instruction::LBL(_)))
+            .unwrap();
+        let product = instructions
+            .iter()
+            .position(|inst| matches!(inst, Instruction::MUL(_, _, _)))
+            .unwrap();
+        assert!(product > label);
+    }
+
+    #[test]
+    fn values_are_not_reused_across_labels() {
+        let instructions = build(
+            "let a := 1;\nlet b := 2;\nyield a + b;\nwhile a < b {\nyield a + b;\na := b;\n}",
+        );
+        let sums = instructions
+            .iter()
+            .filter(|inst| matches!(inst, Instruction::ADD(_, _, _)))
+            .count();
+        assert_eq!(2, sums);
+    }
+}
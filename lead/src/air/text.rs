@@ -0,0 +1,443 @@
+use miette::{Diagnostic, Report};
+use thiserror::Error;
+
+use super::air::{Flag, Instruction, Mode, Reg, Rounding, Stream};
+use crate::lex::span::Span;
+
+/// An error in hand-written AIR, spanning the offending text.
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+pub enum AirParseError {
+    #[error("unknown instruction `{mnemonic}`")]
+    #[diagnostic(help("`leadc version --instructions` lists every instruction"))]
+    UnknownMnemonic {
+        #[label("not an instruction")]
+        span: Span,
+        mnemonic: String,
+    },
+    #[error("`{mnemonic}` takes {expected}, found {found}")]
+    OperandCount {
+        #[label("this has {found} operands")]
+        span: Span,
+        mnemonic: String,
+        expected: &'static str,
+        found: usize,
+    },
+    #[error("expected {expected}, found `{found}`")]
+    ExpectedOperand {
+        #[label("expected {expected}")]
+        span: Span,
+        expected: &'static str,
+        found: String,
+    },
+}
+
+impl AirParseError {
+    pub fn with_src(self, src: String) -> Report {
+        <AirParseError as Into<Report>>::into(self).with_source_code(src)
+    }
+}
+
+/// A piece of text in the source, with its position.
+#[derive(Clone, Copy)]
+struct Word<'s> {
+    text: &'s str,
+    start: usize,
+}
+
+impl<'s> Word<'s> {
+    fn span(&self) -> Span {
+        Span::new((self.start, self.start + self.text.len()))
+    }
+
+    /// Trim whitespace from both ends, keeping the position of the remaining text.
+    fn trim(self) -> Self {
+        let trimmed = self.text.trim_start();
+        Word {
+            start: self.start + self.text.len() - trimmed.len(),
+            text: trimmed.trim_end(),
+        }
+    }
+
+    /// Split at the first occurrence of a pattern, dropping it.
+    fn split_once(self, pattern: &str) -> Option<(Self, Self)> {
+        let at = self.text.find(pattern)?;
+        Some((
+            Word {
+                text: &self.text[..at],
+                start: self.start,
+            },
+            Word {
+                text: &self.text[at + pattern.len()..],
+                start: self.start + at + pattern.len(),
+            },
+        ))
+    }
+
+    fn expected(&self, expected: &'static str) -> AirParseError {
+        AirParseError::ExpectedOperand {
+            span: self.span(),
+            expected,
+            found: self.text.to_owned(),
+        }
+    }
+}
+
+/// Parse AIR text, in the form printed by `render_air`, with one instruction per line. Each
+/// instruction's operands are checked against what its mnemonic takes before it is constructed.
+/// Blank lines are skipped, a `;` starts a comment running to the end of the line, and a register
+/// may be followed by a `/*name*/` annotation.
+pub fn parse_air(src: &str) -> Result<Vec<Instruction>, AirParseError> {
+    let mut instructions: Vec<Instruction> = Vec::new();
+    let mut start = 0;
+    for line in src.split_inclusive('\n') {
+        let code = line.split(';').next().unwrap_or_default();
+        let word = Word { text: code, start }.trim();
+        start += line.len();
+        if !word.text.is_empty() {
+            instructions.push(parse_instruction(word)?);
+        }
+    }
+    Ok(instructions)
+}
+
+fn parse_instruction(line: Word) -> Result<Instruction, AirParseError> {
+    if let Some(label) = line.text.strip_suffix(':') {
+        return Ok(Instruction::LBL(label.to_owned()));
+    }
+    let (mnemonic, rest) = match line.split_once(" ") {
+        Some((mnemonic, rest)) => (mnemonic, rest.trim()),
+        None => (
+            line,
+            Word {
+                text: "",
+                start: line.start + line.text.len(),
+            },
+        ),
+    };
+    let operands = split_operands(rest);
+    let count = |expected: &'static str, counts: &[usize]| match counts.contains(&operands.len()) {
+        true => Ok(()),
+        false => Err(AirParseError::OperandCount {
+            span: line.span(),
+            mnemonic: mnemonic.text.to_owned(),
+            expected,
+            found: operands.len(),
+        }),
+    };
+    let op = |i: usize| operands[i];
+
+    Ok(match mnemonic.text {
+        "ADD" | "SUB" | "MUL" | "DIV" | "SDIV" | "SDIVF" | "MOD" | "AND" | "ORR" => {
+            count("3 registers", &[3])?;
+            let (rd, rx, ry) = (reg(op(0))?, reg(op(1))?, reg(op(2))?);
+            match mnemonic.text {
+                "ADD" => Instruction::ADD(rd, rx, ry),
+                "SUB" => Instruction::SUB(rd, rx, ry),
+                "MUL" => Instruction::MUL(rd, rx, ry),
+                "DIV" => Instruction::DIV(rd, rx, ry),
+                "SDIV" => Instruction::SDIV(rd, rx, ry, Rounding::Truncate),
+                "MOD" => Instruction::MOD(rd, rx, ry),
+                "AND" => Instruction::AND(rd, rx, ry),
+                "ORR" => Instruction::ORR(rd, rx, ry),
+                _ => Instruction::SDIV(rd, rx, ry, Rounding::Floor),
+            }
+        }
+        "INC" | "DEC" | "CYC" => {
+            count("1 register", &[1])?;
+            match mnemonic.text {
+                "INC" => Instruction::INC(reg(op(0))?),
+                "DEC" => Instruction::DEC(reg(op(0))?),
+                _ => Instruction::CYC(reg(op(0))?),
+            }
+        }
+        "MOV" | "NOT" | "ARG" | "ALLOC" | "CMP" | "SCMP" => {
+            count("2 registers", &[2])?;
+            let (rx, ry) = (reg(op(0))?, reg(op(1))?);
+            match mnemonic.text {
+                "MOV" => Instruction::MOV(rx, ry),
+                "NOT" => Instruction::NOT(rx, ry),
+                "ARG" => Instruction::ARG(rx, ry),
+                "ALLOC" => Instruction::ALLOC(rx, ry),
+                "CMP" => Instruction::CMP(rx, ry, None),
+                _ => Instruction::SCMP(rx, ry, None),
+            }
+        }
+        "CONST" => {
+            count("a register and an immediate", &[2])?;
+            Instruction::CON(reg(op(0))?, immediate(op(1))?)
+        }
+        "TST" => {
+            count("a register and an immediate", &[2])?;
+            Instruction::TST(reg(op(0))?, immediate(op(1))?)
+        }
+        "BIC" => {
+            count("2 registers and an immediate", &[3])?;
+            Instruction::BIC(reg(op(0))?, reg(op(1))?, immediate(op(2))?)
+        }
+        "LEA" => {
+            count("a register and an address", &[2])?;
+            let address = op(1)
+                .text
+                .strip_prefix('[')
+                .and_then(|address| address.strip_suffix(']'))
+                .and_then(number)
+                .ok_or(op(1).expected("an address, as in `[0x10]`"))?;
+            Instruction::LEA(reg(op(0))?, address as usize)
+        }
+        "STR" | "LDR" => {
+            count("a register and an addressing mode", &[2, 3])?;
+            let rd = reg(op(0))?;
+            let (address, mode) = addressing(op(1), operands.get(2).copied())?;
+            match mnemonic.text {
+                "STR" => Instruction::STR(rd, address, mode),
+                _ => Instruction::LDR(rd, address, mode),
+            }
+        }
+        "CHK" => {
+            count("a flag", &[1])?;
+            Instruction::CHK(flag(op(0))?)
+        }
+        "BRA" => {
+            count("a label", &[1])?;
+            match op(0).split_once(" if ") {
+                Some((target, condition)) => {
+                    Instruction::BRAcc(flag(condition.trim())?, label(target.trim())?)
+                }
+                None => Instruction::BRA(label(op(0))?),
+            }
+        }
+        "YLD" => {
+            count("a register, and optionally a stream", &[1, 2])?;
+            let stream = match operands.get(1) {
+                None => Stream::Out,
+                Some(name) => {
+                    Stream::from_name(name.text).ok_or(name.expected("a stream, `out` or `err`"))?
+                }
+            };
+            Instruction::YLD(reg(op(0))?, stream)
+        }
+        "HALT" | "TRAP" => {
+            count("no operands", &[0])?;
+            match mnemonic.text {
+                "HALT" => Instruction::HALT,
+                _ => Instruction::TRAP,
+            }
+        }
+        _ => {
+            return Err(AirParseError::UnknownMnemonic {
+                span: mnemonic.span(),
+                mnemonic: mnemonic.text.to_owned(),
+            })
+        }
+    })
+}
+
+/// Split operands at each comma outside of square brackets.
+fn split_operands(rest: Word) -> Vec<Word> {
+    if rest.text.is_empty() {
+        return Vec::new();
+    }
+    let mut operands: Vec<Word> = Vec::new();
+    let (mut depth, mut from) = (0, 0);
+    for (i, c) in rest.text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(
+                    Word {
+                        text: &rest.text[from..i],
+                        start: rest.start + from,
+                    }
+                    .trim(),
+                );
+                from = i + 1;
+            }
+            _ => (),
+        }
+    }
+    operands.push(
+        Word {
+            text: &rest.text[from..],
+            start: rest.start + from,
+        }
+        .trim(),
+    );
+    operands
+}
+
+fn reg(word: Word) -> Result<Reg, AirParseError> {
+    // drop any annotation naming the variable in the register.
+    let text = match word.text.find("/*") {
+        Some(at) if word.text.ends_with("*/") => &word.text[..at],
+        _ => word.text,
+    };
+    text.strip_prefix('%')
+        .and_then(|n| n.parse().ok())
+        .map(Reg)
+        .ok_or(word.expected("a register, as in `%0`"))
+}
+
+/// Parse a number, in hexadecimal if it starts with `0x`.
+fn number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn immediate(word: Word) -> Result<u32, AirParseError> {
+    word.text
+        .strip_prefix('=')
+        .and_then(number)
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or(word.expected("an immediate, as in `=0x1`"))
+}
+
+fn label(word: Word) -> Result<String, AirParseError> {
+    match !word.text.is_empty() && !word.text.contains(char::is_whitespace) {
+        true if !word.text.starts_with('%') => Ok(word.text.to_owned()),
+        _ => Err(word.expected("a label")),
+    }
+}
+
+fn flag(word: Word) -> Result<Flag, AirParseError> {
+    Ok(match word.text {
+        "1" => Flag::Al,
+        "0" => Flag::Nv,
+        "==" => Flag::Eq,
+        "!=" => Flag::Ne,
+        ">" => Flag::Gt,
+        ">=" => Flag::Ge,
+        "<" => Flag::Lt,
+        "<=" => Flag::Le,
+        _ => return Err(word.expected("a flag, such as `==` or `<`")),
+    })
+}
+
+/// Parse an addressing mode, `[%a]`, `[%a, %o]`, `[%a, %o]!` or `[%a], %o`, returning the address
+/// register and the mode.
+fn addressing(address: Word, post_offset: Option<Word>) -> Result<(Reg, Mode), AirParseError> {
+    let malformed = || address.expected("an addressing mode, as in `[%1]` or `[%1, %2]`");
+    let (inner, pre) = match address.text.strip_suffix('!') {
+        Some(inner) => (inner, true),
+        None => (address.text, false),
+    };
+    let inner = inner
+        .strip_prefix('[')
+        .and_then(|inner| inner.strip_suffix(']'))
+        .ok_or_else(malformed)?;
+    let inner = Word {
+        text: inner,
+        start: address.start + 1,
+    };
+    let registers = split_operands(inner);
+    match (registers.as_slice(), pre, post_offset) {
+        ([base], false, None) => Ok((reg(*base)?, Mode::None)),
+        ([base], false, Some(offset)) => Ok((reg(*base)?, Mode::PostOffset(reg(offset)?))),
+        ([base, offset], false, None) => Ok((reg(*base)?, Mode::Offset(reg(*offset)?))),
+        ([base, offset], true, None) => Ok((reg(*base)?, Mode::PreOffset(reg(*offset)?))),
+        _ => Err(malformed()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{air::render_air, try_compile};
+
+    #[test]
+    fn rendered_air_parses() {
+        let instructions = vec![
+            Instruction::SDIV(Reg(0), Reg(1), Reg(2), Rounding::Floor),
+            Instruction::CON(Reg(4), 0x2a),
+            Instruction::LEA(Reg(4), 0x40),
+            Instruction::STR(Reg(1), Reg(2), Mode::PreOffset(Reg(3))),
+            Instruction::LDR(Reg(1), Reg(2), Mode::PostOffset(Reg(3))),
+            Instruction::LBL("end-if".to_owned()),
+            Instruction::BRAcc(Flag::Ge, "end-if".to_owned()),
+            Instruction::YLD(Reg(8), Stream::Err),
+            Instruction::HALT,
+        ];
+        assert!(instructions == parse_air(&render_air(&instructions)).unwrap());
+
+        let compiled = try_compile(
+            "let xs := [1, 2];\nfor x in xs {\nif x > 1 {\nyield x;\n}\n}\nyield div_floor(3, 2);",
+        )
+        .unwrap();
+        let parsed = parse_air(&render_air(&compiled)).unwrap();
+        assert_eq!(render_air(&compiled), render_air(&parsed));
+    }
+
+    #[test]
+    fn missing_register() {
+        match parse_air("CONST %0, =0x1\nADD %0, %1") {
+            Err(AirParseError::OperandCount {
+                span,
+                mnemonic,
+                expected,
+                found,
+            }) => {
+                assert_eq!(Span::new((15, 25)), span);
+                assert_eq!("ADD", mnemonic);
+                assert_eq!("3 registers", expected);
+                assert_eq!(2, found);
+            }
+            _ => panic!("expected an operand count error"),
+        }
+    }
+
+    #[test]
+    fn branch_to_a_register() {
+        match parse_air("BRA %1") {
+            Err(AirParseError::ExpectedOperand {
+                span,
+                expected,
+                found,
+            }) => {
+                assert_eq!(Span::new((4, 6)), span);
+                assert_eq!("a label", expected);
+                assert_eq!("%1", found);
+            }
+            _ => panic!("expected a label error"),
+        }
+    }
+
+    #[test]
+    fn malformed_addressing_mode() {
+        for (src, found) in [
+            ("STR %0, [%1, %2", "[%1, %2"),
+            ("STR %0, [%1, %2, %3]", "[%1, %2, %3]"),
+            ("STR %0, [%1, %2], %3", "[%1, %2]"),
+            ("LDR %0, %1", "%1"),
+        ] {
+            match parse_air(src) {
+                Err(AirParseError::ExpectedOperand {
+                    span,
+                    expected,
+                    found: text,
+                }) => {
+                    assert_eq!(Span::new((8, 8 + found.len())), span, "`{src}`");
+                    assert!(expected.starts_with("an addressing mode"));
+                    assert_eq!(found, text);
+                }
+                _ => panic!("`{src}` should have a malformed addressing mode"),
+            }
+        }
+        match parse_air("STR %0, [%x]") {
+            Err(AirParseError::ExpectedOperand { span, found, .. }) => {
+                assert_eq!(Span::new((9, 11)), span);
+                assert_eq!("%x", found);
+            }
+            _ => panic!("expected a register error"),
+        }
+    }
+
+    #[test]
+    fn unknown_mnemonic() {
+        assert!(matches!(
+            parse_air("HALT\nJMP end"),
+            Err(AirParseError::UnknownMnemonic { mnemonic, .. }) if mnemonic == "JMP"
+        ));
+    }
+}
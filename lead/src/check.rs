@@ -0,0 +1,562 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::LangError,
+    lex::span::{Span, Spans},
+    parse::ast::{
+        Application, Builtin, Expression, For, Identifier, Iterable, Let, Literal, OperatorType,
+        Statement, Type,
+    },
+};
+
+/// Infers the types of expressions in a program, and checks them against any annotations.
+#[derive(Debug, Default, Clone)]
+pub struct TypeChecker {
+    variables: HashMap<String, Type>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            variables: HashMap::new(),
+        }
+    }
+
+    pub fn check(&mut self, statements: &[Statement]) -> Result<(), LangError> {
+        for statement in statements {
+            self.check_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn check_statement(&mut self, statement: &Statement) -> Result<(), LangError> {
+        match statement {
+            Statement::Let(r#let) => self.check_let(r#let),
+            Statement::Mutate(mutate) => {
+                let ty = self.infer(&mutate.value)?;
+                self.check_chained(&mutate.chained, &mutate.value, &ty)
+            }
+            Statement::Expr(Expression::Call {
+                function,
+                arguments,
+                span,
+            }) => self.check_call(function, arguments, *span).map(|_| ()),
+            Statement::Expr(expr) | Statement::Yield(expr, _) | Statement::Return(expr) => {
+                self.infer(expr).map(|_| ())
+            }
+            Statement::Break(_) => Ok(()),
+            Statement::If(r#if) => {
+                self.infer(&r#if.condition)?;
+                self.check_scope(&r#if.iff)?;
+                self.check_scope(r#if.otherwise.as_deref().unwrap_or_default())
+            }
+            Statement::While(r#while) => {
+                self.infer(&r#while.condition)?;
+                self.check_scope(&r#while.body)
+            }
+            Statement::For(r#for) => self.check_for(r#for),
+            Statement::Data(data) => {
+                let ty = self.infer(&data.table)?;
+                self.declare(data.name.name(), ty);
+                Ok(())
+            }
+        }
+    }
+
+    fn check_let(&mut self, r#let: &Let) -> Result<(), LangError> {
+        let ty = self.infer(&r#let.value)?;
+        self.check_chained(&r#let.chained, &r#let.value, &ty)?;
+        if let Some(annotation) = &r#let.annotation {
+            if annotation.ty != ty {
+                return Err(LangError::TypeMismatch {
+                    annotation: annotation.span(),
+                    span: r#let.value.span(),
+                    expected: annotation.ty.clone(),
+                    found: ty,
+                });
+            }
+        }
+        self.declare(&r#let.variable, ty);
+        Ok(())
+    }
+
+    /// Check statements in a scope of their own, so the variables they declare are forgotten after.
+    fn check_scope(&mut self, statements: &[Statement]) -> Result<(), LangError> {
+        let outer = self.variables.clone();
+        let checked = self.check(statements);
+        self.variables = outer;
+        checked
+    }
+
+    /// Record the type of a variable, returning the type of the variable it shadows, if any.
+    pub(crate) fn declare(&mut self, name: &str, ty: Type) -> Option<Type> {
+        self.variables.insert(name.to_owned(), ty)
+    }
+
+    /// Forget a variable declared for a scope, restoring the variable it shadowed.
+    pub(crate) fn restore(&mut self, name: &str, shadowed: Option<Type>) {
+        match shadowed {
+            Some(ty) => self.variables.insert(name.to_owned(), ty),
+            None => self.variables.remove(name),
+        };
+    }
+
+    /// The type of the elements of an array variable, erroring if the variable is not an array.
+    pub(crate) fn array_element(&self, array: &Identifier) -> Result<Type, LangError> {
+        match self.variables.get(array.name()) {
+            Some(Type::Array(ty)) => Ok((**ty).clone()),
+            Some(ty) => Err(LangError::ExpectedArray {
+                span: array.span(),
+                name: array.name().to_owned(),
+                found: ty.clone(),
+            }),
+            None => Err(LangError::UninitialisedPointer {
+                span: array.span(),
+                name: array.name().to_owned(),
+            }),
+        }
+    }
+
+    fn check_for(&mut self, r#for: &For) -> Result<(), LangError> {
+        let ty = match &r#for.iterable {
+            Iterable::Array(array) => match self.variables.get(array.name()) {
+                Some(Type::Array(ty)) => (**ty).clone(),
+                _ => {
+                    return Err(LangError::UninitialisedPointer {
+                        span: array.span(),
+                        name: array.name().to_owned(),
+                    })
+                }
+            },
+            // a range counts up through numbers or characters, with both ends of the same type.
+            Iterable::Range { start, end, .. } => {
+                let ty = self.infer(start)?;
+                if ty != Type::Num && ty != Type::Char {
+                    return Err(LangError::TypeMismatch {
+                        annotation: r#for.iterable.span(),
+                        span: start.span(),
+                        expected: Type::Num,
+                        found: ty,
+                    });
+                }
+                let end_ty = self.infer(end)?;
+                if end_ty != ty {
+                    return Err(LangError::TypeMismatch {
+                        annotation: start.span(),
+                        span: end.span(),
+                        expected: ty,
+                        found: end_ty,
+                    });
+                }
+                ty
+            }
+            Iterable::Counter { start, .. } => self.infer(start)?,
+        };
+        // the item is only in scope within the loop, so restore whatever it shadowed afterwards.
+        let shadowed = self.declare(r#for.item.name(), ty);
+        let checked = match &r#for.iterable {
+            Iterable::Counter {
+                condition, step, ..
+            } => self
+                .infer(condition)
+                .and_then(|_| self.check_statement(&Statement::Mutate((**step).clone()))),
+            _ => Ok(()),
+        }
+        .and_then(|_| self.check_scope(&r#for.body));
+        self.restore(r#for.item.name(), shadowed);
+        checked
+    }
+
+    /// Check that each variable chained before the value of an assignment has the value's type.
+    fn check_chained(
+        &self,
+        chained: &[Identifier],
+        value: &Expression,
+        ty: &Type,
+    ) -> Result<(), LangError> {
+        for variable in chained {
+            let variable_ty = self.infer(&Expression::Identifier(variable.clone()))?;
+            if variable_ty != *ty {
+                return Err(LangError::TypeMismatch {
+                    annotation: variable.span(),
+                    span: value.span(),
+                    expected: variable_ty,
+                    found: ty.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a call to a built-in, returning the type of its value if it has one.
+    fn check_call(
+        &self,
+        function: &Identifier,
+        arguments: &[Expression],
+        span: Span,
+    ) -> Result<Option<Type>, LangError> {
+        match Builtin::resolve(function, arguments, span)? {
+            Builtin::Swap => {
+                let mut types: Vec<Type> = Vec::with_capacity(2);
+                for argument in arguments {
+                    let Expression::Identifier(variable) = argument else {
+                        return Err(LangError::ExpectedVariable {
+                            span: argument.span(),
+                        });
+                    };
+                    // an array lives in memory rather than in a register, so it can't be swapped.
+                    let ty = self.infer(argument)?;
+                    if let Type::Array(_) = ty {
+                        return Err(LangError::SwapArray {
+                            span: argument.span(),
+                            name: variable.name().to_owned(),
+                            found: ty,
+                        });
+                    }
+                    types.push(ty);
+                }
+                if types[0] != types[1] {
+                    return Err(LangError::TypeMismatch {
+                        annotation: arguments[0].span(),
+                        span: arguments[1].span(),
+                        expected: types[0].clone(),
+                        found: types[1].clone(),
+                    });
+                }
+                Ok(None)
+            }
+            Builtin::DivFloor | Builtin::Arg | Builtin::Alloc => {
+                for argument in arguments {
+                    let ty = self.infer(argument)?;
+                    if ty != Type::Num {
+                        return Err(LangError::TypeMismatch {
+                            annotation: function.span(),
+                            span: argument.span(),
+                            expected: Type::Num,
+                            found: ty,
+                        });
+                    }
+                }
+                Ok(Some(Type::Num))
+            }
+            Builtin::Cycles => Ok(Some(Type::Num)),
+            Builtin::SizeOf | Builtin::AddressOf => {
+                let Expression::Identifier(array) = &arguments[0] else {
+                    return Err(LangError::ExpectedVariable {
+                        span: arguments[0].span(),
+                    });
+                };
+                self.array_element(array)?;
+                Ok(Some(Type::Num))
+            }
+        }
+    }
+
+    /// Infer the type of an expression.
+    pub fn infer(&self, expr: &Expression) -> Result<Type, LangError> {
+        Ok(match expr {
+            Expression::Literal { lit } => match lit {
+                Literal::Number { .. } => Type::Num,
+                Literal::Boolean { .. } => Type::Bool,
+                Literal::Char { .. } => Type::Char,
+            },
+            Expression::Group { expr, span: _ } => self.infer(expr)?,
+            Expression::Identifier(identifier) => {
+                self.variables.get(identifier.name()).cloned().ok_or(
+                    LangError::UninitialisedVariable {
+                        span: identifier.span(),
+                        name: identifier.name().to_owned(),
+                    },
+                )?
+            }
+            Expression::Array { elements, span: _ } => {
+                let mut element_ty = Type::Num;
+                for element in elements {
+                    element_ty = self.infer(element)?;
+                }
+                Type::Array(Box::new(element_ty))
+            }
+            Expression::Repeat {
+                element,
+                count,
+                span: _,
+            } => {
+                self.infer(count)?;
+                Type::Array(Box::new(self.infer(element)?))
+            }
+            Expression::Index {
+                variable,
+                index,
+                span,
+            } => {
+                self.infer(index)?;
+                match self.variables.get(variable.name()) {
+                    Some(Type::Array(ty)) => (**ty).clone(),
+                    _ => {
+                        return Err(LangError::UninitialisedPointer {
+                            span: *span,
+                            name: variable.name().to_owned(),
+                        })
+                    }
+                }
+            }
+            Expression::Call {
+                function,
+                arguments,
+                span,
+            } => self
+                .check_call(function, arguments, *span)?
+                .ok_or(LangError::NullValueExpression { span: *span })?,
+            Expression::App { app } => match app {
+                Application::Unary {
+                    op: _,
+                    expr,
+                    span: _,
+                } => self.infer(expr)?,
+                Application::Binary {
+                    op,
+                    left,
+                    right,
+                    span,
+                } => {
+                    let left_ty = self.infer(left)?;
+                    let right_ty = self.infer(right)?;
+                    // only values of the same type can be equal.
+                    if matches!(op, OperatorType::Equal | OperatorType::NotEqual)
+                        && left_ty != right_ty
+                    {
+                        return Err(LangError::TypeMismatch {
+                            annotation: left.span(),
+                            span: right.span(),
+                            expected: left_ty,
+                            found: right_ty,
+                        });
+                    }
+                    // `&` and `|` only combine conditions.
+                    if matches!(op, OperatorType::And | OperatorType::Or) {
+                        for (operand, ty) in [(left, &left_ty), (right, &right_ty)] {
+                            if *ty != Type::Bool {
+                                return Err(LangError::TypeMismatch {
+                                    annotation: *span,
+                                    span: operand.span(),
+                                    expected: Type::Bool,
+                                    found: ty.clone(),
+                                });
+                            }
+                        }
+                    }
+                    match op {
+                        OperatorType::Plus
+                        | OperatorType::Minus
+                        | OperatorType::Multiply
+                        | OperatorType::Divide
+                        | OperatorType::Modulo => Type::Num,
+                        _ => Type::Bool,
+                    }
+                }
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex::Lexer, parse::LangParser};
+
+    fn check(src: &str) -> Result<(), LangError> {
+        let tokens = Lexer::new(src).run().unwrap();
+        let ast = LangParser::new(&tokens).parse_statement(Vec::new())?;
+        TypeChecker::new().check(&ast)
+    }
+
+    #[test]
+    fn annotation_matches_initialiser() {
+        assert!(check("let x: num := 5;\nlet b: bool := true;\nlet c: char := 'c';").is_ok());
+        assert!(check("let x := 5;\nlet y: num := x * 2;").is_ok());
+    }
+
+    #[test]
+    fn annotation_mismatch() {
+        match check("let x: bool := 5;") {
+            Err(LangError::TypeMismatch {
+                expected, found, ..
+            }) => {
+                assert_eq!(Type::Bool, expected);
+                assert_eq!(Type::Num, found);
+            }
+            _ => panic!("expected a type mismatch"),
+        }
+    }
+
+    #[test]
+    fn boolean_combiners() {
+        assert!(check("let x := 5;\nlet b := (x > 0) & (x < 10);\nlet c := b | false;").is_ok());
+        assert!(matches!(
+            check("let x := 5;\nlet b := x & (x < 10);"),
+            Err(LangError::TypeMismatch {
+                expected: Type::Bool,
+                found: Type::Num,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn swap_operands() {
+        assert!(check("let a := 1;\nlet b := 2;\nswap(a, b);").is_ok());
+        assert!(matches!(
+            check("let a := 1;\nlet c := 'c';\nswap(a, c);"),
+            Err(LangError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            check("let a := 1;\nswap(a, 2);"),
+            Err(LangError::ExpectedVariable { .. })
+        ));
+        assert!(matches!(
+            check("let xs := [1, 2];\nlet ys := [3, 4];\nswap(xs, ys);"),
+            Err(LangError::SwapArray { name, .. }) if name == "xs"
+        ));
+        assert!(matches!(
+            check("let a := 1;\nswap(a, b);"),
+            Err(LangError::UninitialisedVariable { .. })
+        ));
+        assert!(matches!(
+            check("let a := 1;\nswap(a);"),
+            Err(LangError::ArgumentCount {
+                expected: 2,
+                found: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check("let a := 1;\nlet b := swap(a, a);"),
+            Err(LangError::NullValueExpression { .. })
+        ));
+    }
+
+    #[test]
+    fn div_floor_operands() {
+        assert!(check("let a := 7;\nlet b: num := div_floor(a, 2);").is_ok());
+        assert!(matches!(
+            check("let a := div_floor(7, 'c');"),
+            Err(LangError::TypeMismatch {
+                expected: Type::Num,
+                found: Type::Char,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn array_layout_operands() {
+        assert!(check("let a := [1, 2];\nlet n: num := sizeof(a) + addressof(a);").is_ok());
+        assert!(matches!(
+            check("let x := 1;\nyield sizeof(x);"),
+            Err(LangError::ExpectedArray {
+                found: Type::Num,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check("yield addressof(xs);"),
+            Err(LangError::UninitialisedPointer { .. })
+        ));
+        assert!(matches!(
+            check("yield addressof([1, 2]);"),
+            Err(LangError::ExpectedVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn chained_assignment() {
+        assert!(check("let a := 1;\nlet b := a := 2;\nb := a := 3;").is_ok());
+        assert!(matches!(
+            check("let c := 'c';\nlet b := c := 2;"),
+            Err(LangError::TypeMismatch {
+                expected: Type::Char,
+                found: Type::Num,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check("let b := c := 2;"),
+            Err(LangError::UninitialisedVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn for_item_is_scoped() {
+        assert!(check("let xs := ['a', 'b'];\nfor x in xs {\nlet c: char := x;\n}").is_ok());
+        assert!(matches!(
+            check("let xs := [1];\nfor x in xs { }\nyield x;"),
+            Err(LangError::UninitialisedVariable { .. })
+        ));
+        assert!(matches!(
+            check("let x := 1;\nfor x in x { }"),
+            Err(LangError::UninitialisedPointer { .. })
+        ));
+    }
+
+    #[test]
+    fn if_branches_are_scoped() {
+        assert!(check("let x := 0;\nif x > 0 {\nx := 1;\n} else {\nx := 2;\n}\nyield x;").is_ok());
+        for src in [
+            "let x := 0;\nif x > 0 {\nlet y := 1;\nyield y;\n} else {\nyield y;\n}",
+            "let x := 0;\nif x > 0 {\nlet y := 1;\n}\nyield y;",
+            "if true {\n} else {\nlet y := 1;\n}\nyield y;",
+        ] {
+            assert!(
+                matches!(check(src), Err(LangError::UninitialisedVariable { .. })),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn loop_bodies_are_scoped() {
+        assert!(check("let i := 0;\nwhile i < 3 {\nlet z := 4;\ni := i + z;\n}\nyield i;").is_ok());
+        for src in [
+            "let i := 5;\nwhile i < 3 {\nlet z := 4;\ni := i + 1;\n}\nyield z;",
+            "for i := 0; i < 3; i := i + 1 {\nlet z := i;\n}\nyield z;",
+            "for i in 0..3 {\nlet z := i;\n}\nyield z;",
+        ] {
+            assert!(
+                matches!(check(src), Err(LangError::UninitialisedVariable { .. })),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn equality_operands() {
+        for src in [
+            "yield (1 == 2);",
+            "while true != false { }",
+            "let c := 'c';\nwhile c == 'd' { }",
+        ] {
+            assert!(check(src).is_ok(), "`{src}` should type check");
+        }
+        match check("while true == 1 { }") {
+            Err(LangError::TypeMismatch {
+                expected, found, ..
+            }) => {
+                assert_eq!(Type::Bool, expected);
+                assert_eq!(Type::Num, found);
+            }
+            _ => panic!("expected a type mismatch"),
+        }
+        assert!(matches!(
+            check("let c := 'c';\nwhile c != 99 { }"),
+            Err(LangError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn inferred_variable_mismatch() {
+        assert!(matches!(
+            check("let c := 'c';\nlet x: num := c;"),
+            Err(LangError::TypeMismatch { .. })
+        ));
+    }
+}
@@ -1,29 +1,34 @@
 use crate::lex::{span::Span, token::TokenType};
+use crate::parse::ast::Type;
 use miette::{Diagnostic, Report};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Error, Debug, Diagnostic)]
 pub enum LangError {
     #[error("unknown lexeme `{lexeme}`")]
+    #[diagnostic(code(E0001))]
     InvalidLexeme {
         #[label]
         span: Span,
         lexeme: String,
     },
     #[error("invalid character literal `{char_literal}`")]
+    #[diagnostic(code(E0002))]
     InvalidCharacterLiteral {
         #[label]
         span: Span,
         char_literal: String,
     },
     #[error("invalid integer literal `{num_literal}`")]
+    #[diagnostic(code(E0003))]
     InvalidIntegerLiteral {
         #[label]
         span: Span,
         num_literal: String,
     },
     #[error("invalid identifier name `{id_literal}`")]
-    #[diagnostic(help("identifiers must begin with a letter, and can contain any other combination of english letters, digits, and underscores"))]
+    #[diagnostic(code(E0004), help("identifiers must begin with a letter, and can contain any other combination of english letters, digits, and underscores"))]
     InvalidIdentifier {
         #[label]
         span: Span,
@@ -32,24 +37,31 @@ pub enum LangError {
     #[error(
         "invalid literal `{invalid_literal}`, expected a boolean, character, or integer literal"
     )]
+    #[diagnostic(code(E0005))]
     InvalidLiteral {
         span: Span,
         invalid_literal: TokenType,
     },
     #[error("`{op}` is not a valid unary operator")]
+    #[diagnostic(code(E0006))]
     InvalidUnaryOperator {
         #[label]
         span: Span,
         op: TokenType,
     },
     #[error("`{op}` is not a valid binary operator")]
+    #[diagnostic(code(E0007))]
     InvalidBinaryOperator {
         #[label]
         span: Span,
         op: TokenType,
     },
     #[error("unmatched delimiter `{expected}`, found `{found}`")]
+    #[diagnostic(code(E0008))]
     UnmatchedDelimiter {
+        #[label("unclosed delimiter")]
+        opening: Span,
+        #[label("expected `{expected}`")]
         span: Span,
         expected: TokenType,
         found: TokenType,
@@ -59,6 +71,7 @@ pub enum LangError {
             None => "".to_owned(),
             Some(string) => format!(", found `{string}`")
         })]
+    #[diagnostic(code(E0009))]
     UnexpectedEndOfFile {
         #[label]
         span: Span,
@@ -66,12 +79,14 @@ pub enum LangError {
         found: Option<String>,
     },
     #[error("uninitialised variable `{name}`")]
+    #[diagnostic(code(E0010))]
     UninitialisedVariable {
         #[label]
         span: Span,
         name: String,
     },
     #[error("uninitialised pointer to variable `{name}`")]
+    #[diagnostic(code(E0011))]
     UninitialisedPointer {
         #[label]
         span: Span,
@@ -79,6 +94,7 @@ pub enum LangError {
     },
 
     #[error("unexpected token `{tok}`, expected {expected}")]
+    #[diagnostic(code(E0012))]
     UnexpectedToken {
         #[label]
         span: Span,
@@ -87,6 +103,7 @@ pub enum LangError {
     },
 
     #[error("expected `{expected}`, found `{found}`")]
+    #[diagnostic(code(E0013))]
     ExpectedToken {
         #[label]
         span: Span,
@@ -94,10 +111,184 @@ pub enum LangError {
         found: TokenType,
     },
     #[error("found a null value expression. Expressions must always evaluate to some value")]
+    #[diagnostic(code(E0014))]
     NullValueExpression {
         #[label]
         span: Span,
     },
+    #[error("`break` outside of a loop")]
+    #[diagnostic(code(E0015))]
+    BreakOutsideLoop {
+        #[label]
+        span: Span,
+    },
+    #[error("array lengths must be positive constants")]
+    #[diagnostic(code(E0016))]
+    InvalidArrayLength {
+        #[label]
+        span: Span,
+    },
+    #[error("index `{index}` is out of bounds for an array of length {length}")]
+    #[diagnostic(
+        code(E0017),
+        help("negative indices count back from the end of the array")
+    )]
+    IndexOutOfBounds {
+        #[label]
+        span: Span,
+        index: i64,
+        length: usize,
+    },
+    #[error("unknown function `{name}`")]
+    #[diagnostic(
+        code(E0018),
+        help("the built-in functions are `swap`, `div_floor`, `cycles`, `arg` and `alloc`")
+    )]
+    UnknownFunction {
+        #[label]
+        span: Span,
+        name: String,
+    },
+    #[error("`{name}` takes {expected} arguments, found {found}")]
+    #[diagnostic(code(E0019))]
+    ArgumentCount {
+        #[label]
+        span: Span,
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("assignment in a condition")]
+    #[diagnostic(code(E0020), help("use `==` to compare values"))]
+    AssignmentInCondition {
+        #[label("this assigns rather than compares")]
+        span: Span,
+    },
+    #[error("unknown output stream `{name}`")]
+    #[diagnostic(code(E0021), help("the streams are `out` and `err`"))]
+    UnknownStream {
+        #[label]
+        span: Span,
+        name: String,
+    },
+    #[error("expected a variable")]
+    #[diagnostic(code(E0022))]
+    ExpectedVariable {
+        #[label]
+        span: Span,
+    },
+    #[error("unknown type `{name}`")]
+    #[diagnostic(code(E0023), help("the supported types are `num`, `bool`, and `char`"))]
+    UnknownType {
+        #[label]
+        span: Span,
+        name: String,
+    },
+    #[error("mismatched types, expected `{expected}`, found `{found}`")]
+    #[diagnostic(code(E0024))]
+    TypeMismatch {
+        #[label("expected `{expected}` because of this")]
+        annotation: Span,
+        #[label("this is of type `{found}`")]
+        span: Span,
+        expected: Type,
+        found: Type,
+    },
+    #[error("arrays overlap the values spilled from registers")]
+    #[diagnostic(
+        code(E0025),
+        help("give the machine more memory with the `#memory` pragma")
+    )]
+    MemoryOverlap {
+        #[label(
+            "this needs memory up to {data_end:#x}, but spilled values start at {stack_base:#x}"
+        )]
+        span: Span,
+        data_end: usize,
+        stack_base: usize,
+    },
+    #[error("cannot compare arrays of different lengths")]
+    #[diagnostic(code(E0026))]
+    ArrayLengthMismatch {
+        #[label("this has {left_length} elements")]
+        left: Span,
+        #[label("this has {right_length} elements")]
+        right: Span,
+        left_length: usize,
+        right_length: usize,
+    },
+    #[error("`=` is not an operator")]
+    #[diagnostic(
+        code(E0027),
+        help("use `:=` to assign a value, or `==` to compare two values")
+    )]
+    LoneEquals {
+        #[label]
+        span: Span,
+    },
+    #[error("comparisons cannot be chained")]
+    #[diagnostic(
+        code(E0028),
+        help("compare each pair on its own and join them with `&`, as in `(0 < x) & (x < 10)`")
+    )]
+    ChainedComparison {
+        #[label("this comparison")]
+        first: Span,
+        #[label("is chained with this one")]
+        second: Span,
+    },
+    #[error("invalid string literal `{string_literal}`")]
+    #[diagnostic(code(E0029))]
+    InvalidStringLiteral {
+        #[label]
+        span: Span,
+        string_literal: String,
+    },
+    #[error("data tables can only hold constant values")]
+    #[diagnostic(
+        code(E0030),
+        help(
+            "the table is filled in before the program runs, so use literals such as `3` or `'c'`"
+        )
+    )]
+    NonConstantData {
+        #[label("this is only known when the program runs")]
+        span: Span,
+    },
+    #[error("`data` is only allowed at the top level of a program")]
+    #[diagnostic(code(E0031), help("move the table out of the block"))]
+    NestedData {
+        #[label]
+        span: Span,
+    },
+    #[error("`#` can only start a line")]
+    #[diagnostic(
+        code(E0032),
+        help("a line starting with `#` is a pragma, so move it to a line of its own")
+    )]
+    MisplacedPragma {
+        #[label]
+        span: Span,
+    },
+    #[error("`{name}` is not an array")]
+    #[diagnostic(code(E0033))]
+    ExpectedArray {
+        #[label("this is of type `{found}`")]
+        span: Span,
+        name: String,
+        found: Type,
+    },
+    #[error("cannot swap the array `{name}`")]
+    #[diagnostic(
+        code(E0034),
+        help("swap the elements of the arrays one at a time instead")
+    )]
+    SwapArray {
+        #[label("this is of type `{found}`")]
+        span: Span,
+        name: String,
+        found: Type,
+    },
 }
 
 impl LangError {
@@ -105,3 +296,44 @@ impl LangError {
         <LangError as Into<Report>>::into(self).with_source_code(src)
     }
 }
+
+/// Diagnostics for valid programs that are likely to be mistakes. These never stop compilation.
+#[derive(Error, Debug, Diagnostic, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[diagnostic(severity(Warning))]
+pub enum LangWarning {
+    #[error("unused variable `{name}`")]
+    #[diagnostic(help("remove the variable, or `yield` it"))]
+    UnusedVariable {
+        #[label]
+        span: Span,
+        name: String,
+    },
+    #[error("unknown pragma `{name}`")]
+    #[diagnostic(help("the supported pragmas are `#memory <bytes>`"))]
+    UnknownPragma {
+        #[label]
+        span: Span,
+        name: String,
+    },
+    #[error("ambiguous condition")]
+    #[diagnostic(help(
+        "only the last comparison decides the branch, so compare once, or nest the conditions"
+    ))]
+    AmbiguousCondition {
+        #[label("this condition has {comparisons} comparisons")]
+        span: Span,
+        comparisons: usize,
+    },
+    #[error("malformed pragma, expected `{expected}`")]
+    MalformedPragma {
+        #[label]
+        span: Span,
+        expected: String,
+    },
+}
+
+impl LangWarning {
+    pub fn with_src(self, src: String) -> Report {
+        <LangWarning as Into<Report>>::into(self).with_source_code(src)
+    }
+}
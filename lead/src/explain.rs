@@ -0,0 +1,431 @@
+//! Longer explanations of the errors the compiler reports, and of the `R` errors that stop a
+//! running program, looked up by their code.
+
+/// Each error code, and a description of the error with an example of it and its fix.
+const EXPLANATIONS: [(&str, &str); 48] = [
+    (
+        "E0001",
+        "The source contains a character that does not start any token, such as `$`.
+
+    let x := 1 $ 2;
+
+Remove the character, or replace it with an operator, such as `+`.",
+    ),
+    (
+        "E0002",
+        "A character literal is not a single character between single quotes.
+
+    let c := 'ab';
+
+Write exactly one character, such as `'a'`.",
+    ),
+    (
+        "E0003",
+        "A number is too large to fit in a number, which holds values up to 2147483647.
+
+    yield 4294967296;
+
+Use a smaller number.",
+    ),
+    (
+        "E0004",
+        "A variable is declared with a name that is not an identifier, such as a keyword or a
+literal. Identifiers begin with a letter, and contain letters, digits, and underscores.
+
+    let true := 1;
+
+Rename the variable, such as to `t`.",
+    ),
+    (
+        "E0005",
+        "A literal was expected, but something else was found.
+
+Write a number, a boolean, or a character where the literal should be.",
+    ),
+    (
+        "E0006",
+        "An operator that only joins two expressions was used in front of a single expression.
+The unary operators are `-` and `!`.",
+    ),
+    (
+        "E0007",
+        "An operator that only applies to a single expression was used between two expressions.
+
+    yield 1 ! 2;
+
+The binary operators are `+`, `-`, `*`, `/`, `<`, `<=`, `>`, `>=`, `==` and `!=`.",
+    ),
+    (
+        "E0008",
+        "A bracket, parenthesis or brace is closed with a different delimiter than it was opened
+with.
+
+    yield (1 + 2];
+
+Close each delimiter with its match.",
+    ),
+    (
+        "E0009",
+        "The program ended in the middle of a statement.
+
+    let x :=
+
+Finish the statement, such as with `let x := 1;`.",
+    ),
+    (
+        "E0010",
+        "A variable is used before it has been declared with `let`, or outside the scope it was
+declared in.
+
+    yield x;
+
+Declare the variable first.
+
+    let x := 1;
+    yield x;",
+    ),
+    (
+        "E0011",
+        "A variable was used as an array, but it does not hold one.
+
+    let x := 1;
+    yield x[0];
+
+Index, or loop over, a variable declared as an array, such as `let x := [1];`.",
+    ),
+    (
+        "E0012",
+        "A token appeared where it can not be used, such as an operator at the start of a statement.
+
+    * 2;
+
+Write what the message expects, here a statement such as `yield 2;`.",
+    ),
+    (
+        "E0013",
+        "A particular token was required, but another was found, often a missing `;`.
+
+    let x := 1 yield x;
+
+Add the expected token.",
+    ),
+    (
+        "E0014",
+        "An expression that has no value was used where a value is needed. Comparisons only set
+flags for a condition, and arrays are not values of their own.
+
+    yield 1 < 2;
+
+Use comparisons in the condition of an `if` or `while`, and yield array elements rather than
+whole arrays.",
+    ),
+    (
+        "E0015",
+        "`break` was used outside of a loop, so there is nothing to break out of.
+
+    break;
+
+Only use `break` in the body of a `while` or `for` loop.",
+    ),
+    (
+        "E0016",
+        "The length of a repeated array is not a positive number known when compiling.
+
+    let n := 3;
+    let a := [0; n];
+
+Write the length with constants, such as `[0; 3]`.",
+    ),
+    (
+        "E0017",
+        "A constant index is outside the array. Negative indices count back from the end, so an
+array of length 3 can be indexed from -3 to 2.
+
+    let a := [1, 2, 3];
+    yield a[3];
+
+Use an index within the array.",
+    ),
+    (
+        "E0018",
+        "A function was called that does not exist. The only functions are the built-ins `swap`,
+`div_floor`, `cycles`, `arg` and `alloc`.
+
+    yield max(1, 2);
+
+Call one of the built-in functions.",
+    ),
+    (
+        "E0019",
+        "A built-in function was called with the wrong number of arguments.
+
+    swap(a);
+
+Pass as many arguments as the function takes, here two.",
+    ),
+    (
+        "E0020",
+        "A condition assigns to a variable instead of comparing it.
+
+    if x := 3 { }
+
+Use `==` to compare values.
+
+    if x == 3 { }",
+    ),
+    (
+        "E0021",
+        "A value was yielded to a stream that does not exist.
+
+    yield@log 1;
+
+The streams are `out` and `err`.",
+    ),
+    (
+        "E0022",
+        "A variable was required, but another expression was found.
+
+    swap(a, 2);
+
+Pass variables, such as `swap(a, b)`.",
+    ),
+    (
+        "E0023",
+        "A type annotation names a type that does not exist.
+
+    let x: int := 1;
+
+The types are `num`, `bool`, and `char`.",
+    ),
+    (
+        "E0024",
+        "A value does not have the type it is required to have, such as the type of an annotation,
+or of the other side of an `==`.
+
+    let x: bool := 5;
+
+Change the annotation or the value so the types agree.",
+    ),
+    (
+        "E0025",
+        "Arrays are stored from the bottom of memory up, and values spilled from registers from the
+top down. The program's arrays need more memory than is left below the spilled values, so the
+two would overwrite each other. This can only happen when the size of memory is known while
+compiling.
+
+Give the machine more memory with the `#memory` pragma, or use smaller arrays.",
+    ),
+    (
+        "E0026",
+        "Two arrays are compared with `==` or `!=`, but they have different lengths. Arrays are
+compared element by element, so they must be the same length.
+
+    let xs := [1, 2];
+    let ys := [1, 2, 3];
+    if xs == ys { }
+
+Compare arrays of the same length, or compare their elements by index.",
+    ),
+    (
+        "E0027",
+        "A single `=` is used, which is not an operator. Values are assigned with `:=`, and
+compared with `==`.
+
+    let x = 5;
+    if x = 5 { }
+
+Write `let x := 5;` to assign, and `if x == 5 { }` to compare.",
+    ),
+    (
+        "E0028",
+        "Two comparisons are chained together. A comparison only sets flags rather than giving a
+value, so the result of one cannot be compared again.
+
+    if 0 < x < 10 { }
+
+Compare each pair on its own, and join the comparisons with `&`.
+
+    if (0 < x) & (x < 10) { }",
+    ),
+    (
+        "E0029",
+        "A string literal is not closed, or holds a character that can't be read.
+
+    data greeting := \"hello;
+
+Close the string with a `\"`.",
+    ),
+    (
+        "E0030",
+        "A `data` table holds a value that is only known when the program runs. The table is placed
+in memory before the program starts, so every value must be a literal.
+
+    let x := 3;
+    data xs := [1, 2, x];
+
+Write the values out, as in `data xs := [1, 2, 3];`, or build the array with `let`.",
+    ),
+    (
+        "E0031",
+        "A `data` table is declared inside a block. Tables are placed in memory before the program
+starts, rather than when the block runs, so they are only allowed at the top level.
+
+    for i in 0..3 {
+        data xs := [1, 2, 3];
+    }
+
+Move the table out of the block.",
+    ),
+    (
+        "E0032",
+        "A `#` was found after the start of a line. A line starting with `#` is a pragma, such as
+`#memory 1024`, and `#` means nothing anywhere else.
+
+    yield 1; #memory 64
+
+Move the pragma to a line of its own.",
+    ),
+    (
+        "E0033",
+        "A variable that is not an array was used where an array is needed, such as with
+`sizeof` or `addressof`.
+
+    let x := 1;
+    yield sizeof(x);
+
+Pass an array variable, as in `let xs := [1, 2];` and `sizeof(xs)`.",
+    ),
+    (
+        "E0034",
+        "An array was passed to `swap`, which only exchanges the values of two variables held in
+registers, such as numbers, characters and booleans.
+
+    let xs := [1, 2];
+    let ys := [3, 4];
+    swap(xs, ys);
+
+Swap the arrays' elements one at a time instead.",
+    ),
+    (
+        "R0001",
+        "An address outside of the machine's memory was read while inspecting it, such as when
+dumping the program's arrays once it stops.
+
+Give the program more memory with `-m`, or the `#memory` pragma.",
+    ),
+    (
+        "R0002",
+        "An instruction read or wrote a word that does not fit within the machine's memory. This
+usually means an index computed at runtime is outside its array.
+
+    let xs := [1, 2, 3];
+    let i := arg(0);
+    yield xs[i];
+
+Check the index is within the array before using it, run with `--check-bounds` to stop at
+the index instead, or give the program more memory with `-m`.",
+    ),
+    (
+        "R0003",
+        "A number was divided by zero, or its remainder taken by zero.
+
+    let d := arg(0);
+    yield 10 / d;
+
+Check that the divisor is not zero before dividing by it.",
+    ),
+    (
+        "R0004",
+        "With `--overflow trapping`, the result of arithmetic did not fit in an unsigned word.
+
+    let x := 0;
+    yield x - 1;
+
+Keep the result within range, or run with `--overflow wrapping` to let it wrap around.",
+    ),
+    (
+        "R0005",
+        "The program read an argument it was not given. Arguments are counted from 0.
+
+    yield arg(1);
+
+Pass the program's arguments after `--`, as in `leadc run main.ed -- 3 5`.",
+    ),
+    (
+        "R0006",
+        "The program trapped. With `--check-bounds`, an index computed at runtime that is outside
+its array traps rather than reading past it.
+
+    let xs := [1, 2, 3];
+    yield xs[arg(0)];
+
+Check the index is within the array before using it.",
+    ),
+    (
+        "R0007",
+        "`alloc` asked for more bytes than are left on the heap. Allocations are never freed.
+
+    yield alloc(1000000000);
+
+Allocate less, or give the program more memory with `-m`, or the `#memory` pragma.",
+    ),
+    (
+        "R0008",
+        "The program ran for more steps than its sandbox allows, which usually means a loop never
+ends.
+
+Check that every loop ends, or allow the program more steps.",
+    ),
+    (
+        "R0009",
+        "The program needs more memory than its sandbox allows.
+
+Run the program with less memory, or raise the limit.",
+    ),
+    (
+        "R0010",
+        "The program yielded more values than its sandbox allows, which usually means a loop that
+yields never ends.
+
+Check that every loop that yields ends, or raise the limit.",
+    ),
+    (
+        "R0011",
+        "The program was still running when its time ran out, as set by `--timeout`.
+
+    while true { }
+
+Check that every loop ends, or allow the program more time.",
+    ),
+    (
+        "R0012",
+        "An instruction read a register that nothing had been saved in yet, such as in AIR that
+was written by hand.
+
+    MOV %0, %1
+
+Save a value in the register, such as with `CON`, before the instruction reads it.",
+    ),
+    (
+        "R0013",
+        "A branch names a label that is not in the program, such as in AIR that was written by
+hand.
+
+    BRA missing
+
+Add the label with `LBL`, or branch to one that exists.",
+    ),
+    (
+        "R0014",
+        "The stream the program yields to was closed while it was running, such as when its
+output is piped to a program that exits early.",
+    ),
+];
+
+/// Returns the explanation of an error code, such as `E0010`, if it is known.
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, explanation)| *explanation)
+}
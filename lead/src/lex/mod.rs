@@ -3,7 +3,7 @@ pub mod token;
 
 use crate::error::LangError;
 use span::Span;
-use token::{Token, TokenType, KEYWORDS};
+use token::{Token, TokenType, KEYWORDS, LONG_OPERATORS};
 use TSPL::{self, Parser};
 
 pub struct Lexer<'l> {
@@ -33,87 +33,139 @@ impl<'l> Lexer<'l> {
     }
 
     fn lex(&mut self, buf: &mut Vec<Token>) -> Result<(), LangError> {
-        self.skip_trivia();
+        loop {
+            self.skip_trivia();
 
-        let mut tok: Token = Token::new(TokenType::EOF, self.index, 0); // placeholder since the compiler cannot verify tok gets initialised.
-        if self.is_eof() {
-            buf.push(tok);
-            return Ok(());
-        }
+            let tok: Token;
+            if self.is_eof() {
+                buf.push(Token::new(TokenType::EOF, self.index, 0));
+                return Ok(());
+            }
 
-        let start = self.index;
+            let start = self.index;
 
-        match self.peek_one().unwrap() {
-            ' ' => {
-                self.skip_spaces();
-                return self.lex(buf);
+            // take the longest operator that matches, so `<=` is not read as `<` followed by `=`.
+            if let Some(operator) = LONG_OPERATORS
+                .iter()
+                .find(|operator| self.peek_many(operator.len()) == Some(operator))
+            {
+                buf.push(Token::from(
+                    self.advance_many(operator.len()).unwrap(),
+                    start,
+                )?);
+                continue;
             }
-            '(' | ')' | '{' | '}' | '[' | ']' | ',' | '.' | '-' | '+' | '*' | ';' | '/' => {
-                tok = Token::from(&self.advance_one().unwrap().to_string(), self.index)?;
-            }
-            '!' | '<' | '>' | ':' | '=' => {
-                match self.peek_many(2) {
-                    None => {
-                        tok = Token::from(&self.advance_one().unwrap().to_string(), self.index)?
-                    }
-                    Some(string) => {
-                        if string.chars().nth(1).unwrap() == '=' {
-                            tok = Token::from(self.advance_many(2).unwrap(), self.index)?
-                        } else {
-                            tok = Token::from(&self.advance_one().unwrap().to_string(), self.index)?
+
+            match self.peek_one().unwrap() {
+                ' ' => {
+                    self.skip_spaces();
+                    continue;
+                }
+                // shebangs and pragmas fill a line of their own, and are read separately.
+                '#' if start == 0 || self.src[..start].ends_with('\n') => {
+                    self.take_while(|ch| ch != '\n');
+                    continue;
+                }
+                '#' => {
+                    return Err(LangError::MisplacedPragma {
+                        span: Span::new((start, start + 1)),
+                    });
+                }
+                '(' | ')' | '{' | '}' | '[' | ']' | ',' | '.' | '-' | '+' | '*' | '%' | ';'
+                | '/' | '@' | '&' | '|' | '!' | '<' | '>' | ':' => {
+                    tok = Token::from(&self.advance_one().unwrap().to_string(), start)?;
+                }
+                // `==` is taken as a long operator, so this `=` is on its own.
+                '=' => {
+                    return Err(LangError::LoneEquals {
+                        span: Span::new((start, start + 1)),
+                    });
+                }
+                '\'' => {
+                    let ch = match self.parse_quoted_char() {
+                        Ok(ch) => ch,
+                        Err(_) => {
+                            return Err(LangError::InvalidCharacterLiteral {
+                                span: Span::new((start, self.index)),
+                                char_literal: self.input()[start..self.index].to_owned(),
+                            });
                         }
-                    }
-                };
-            }
-            '\'' => {
-                let ch = match self.parse_quoted_char() {
-                    Ok(ch) => ch,
-                    Err(_) => {
-                        return Err(LangError::InvalidCharacterLiteral {
-                            span: Span::new((start, self.index)),
-                            char_literal: self.input()[start..self.index].to_owned(),
-                        });
-                    }
-                };
-                tok = Token::new(TokenType::Char(ch), self.index, self.index - start);
-            }
-            ch if ch.is_digit(10) => {
-                match self.parse_u64() {
-                    Ok(n) => tok = Token::from_num(n, start, self.index),
-                    Err(_) => {
-                        return Err(LangError::InvalidIntegerLiteral {
-                            span: Span::new((start, self.index)),
-                            num_literal: self.input()[start..self.index].to_owned(),
-                        });
-                    }
-                };
-            }
-            'a'..='z' | 'A'..='Z' => {
-                let name = self.take_while(|ch| Self::is_valid_identifier_char(ch));
+                    };
+                    tok = Token::new(TokenType::Char(ch), start, self.index - start);
+                }
+                '"' => {
+                    let string = match self.parse_quoted_string() {
+                        Ok(string) => string,
+                        Err(_) => {
+                            return Err(LangError::InvalidStringLiteral {
+                                span: Span::new((start, self.index)),
+                                string_literal: self.input()[start..self.index].to_owned(),
+                            });
+                        }
+                    };
+                    tok = Token::new(TokenType::Str(string), start, self.index - start);
+                }
+                ch if ch.is_digit(10) => {
+                    match self.parse_u64() {
+                        Ok(n) => tok = Token::from_num(n, start, self.index),
+                        Err(_) => {
+                            return Err(LangError::InvalidIntegerLiteral {
+                                span: Span::new((start, self.index)),
+                                num_literal: self.input()[start..self.index].to_owned(),
+                            });
+                        }
+                    };
+                }
+                ch if Self::is_identifier_start(ch) => {
+                    let name = self.take_while(Self::is_valid_identifier_char);
 
-                if KEYWORDS.contains(&name) {
-                    tok = Token::from_keyword(name, start)?;
-                } else {
-                    tok = Token::new(TokenType::Identifier(name.to_owned()), start, name.len())
+                    if name == "r" && self.is_raw_identifier() {
+                        // `r#` escapes a keyword, so `r#while` is an identifier named `while`.
+                        self.advance_one();
+                        let name = self.take_while(Self::is_valid_identifier_char);
+                        tok = Token::new(
+                            TokenType::Identifier(name.to_owned()),
+                            start,
+                            self.index - start,
+                        )
+                    } else if KEYWORDS.contains(&name) {
+                        tok = Token::from_keyword(name, start)?;
+                    } else {
+                        tok = Token::new(TokenType::Identifier(name.to_owned()), start, name.len())
+                    }
+                }
+                ch => {
+                    return Err(LangError::InvalidLexeme {
+                        span: Span::new((start, start + ch.len_utf8())),
+                        lexeme: ch.to_string(),
+                    });
                 }
             }
-            ch => {
-                return Err(LangError::InvalidLexeme {
-                    span: Span::new((start, start + 1)),
-                    lexeme: ch.to_string(),
-                });
-            }
+
+            buf.push(tok);
         }
+    }
+
+    /// returns whether the lexer is at the `#` of a raw identifier, followed by the start of the
+    /// identifier.
+    fn is_raw_identifier(&mut self) -> bool {
+        self.peek_many(2).is_some_and(|string| {
+            let mut chars = string.chars();
+            chars.next() == Some('#') && chars.next().is_some_and(Self::is_identifier_start)
+        })
+    }
 
-        buf.push(tok);
-        return self.lex(buf);
+    /// returns whether a character can start an identifier. Any alphabetic character can, including
+    /// accented and other non-ascii letters.
+    fn is_identifier_start(ch: char) -> bool {
+        ch.is_alphabetic()
     }
 
     /// returns weather a given character is a valid non starting identifier character
     fn is_valid_identifier_char(ch: char) -> bool {
         match ch {
             '_' => true,
-            ch => ch.is_ascii_alphabetic() || ch.is_digit(10),
+            ch => ch.is_alphabetic() || ch.is_ascii_digit(),
         }
     }
 }
@@ -140,9 +192,14 @@ mod tests {
                 TokenType::Bool(false),
                 TokenType::Let,
                 TokenType::If,
+                TokenType::Else,
                 TokenType::For,
+                TokenType::In,
                 TokenType::While,
                 TokenType::Yield,
+                TokenType::Return,
+                TokenType::Break,
+                TokenType::Data,
             ],
             KEYWORDS
                 .into_iter()
@@ -196,4 +253,154 @@ mod tests {
         ];
         assert_eq!(expected, process(src));
     }
+
+    #[test]
+    fn raw_identifiers() {
+        let src = "let r#while := 1;\nwhile r#while < r#x { }";
+        let tokens = Lexer::new(src).run().unwrap();
+        let spans: Vec<(TokenType, &str)> = tokens
+            .iter()
+            .map(|token| {
+                let (start, end) = token.span().span();
+                (token.ty(), &src[start..end])
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Ty::Let, "let"),
+                (Ty::Identifier("while".to_owned()), "r#while"),
+                (Ty::Assign, ":="),
+                (Ty::Number(1), "1"),
+                (Ty::Semicolon, ";"),
+                (Ty::While, "while"),
+                (Ty::Identifier("while".to_owned()), "r#while"),
+                (Ty::LessThan, "<"),
+                (Ty::Identifier("x".to_owned()), "r#x"),
+                (Ty::LeftBrace, "{"),
+                (Ty::RightBrace, "}"),
+                (Ty::EOF, ""),
+            ],
+            spans
+        );
+        // a lone `r` is still an ordinary identifier.
+        assert_eq!(vec![Ty::Identifier("r".to_owned()), Ty::EOF], process("r"));
+    }
+
+    #[test]
+    fn accented_identifiers() {
+        let src = "let café := 'é';\nyield naïve_2;";
+        let tokens = Lexer::new(src).run().unwrap();
+        let spans: Vec<(TokenType, &str)> = tokens
+            .iter()
+            .map(|token| {
+                let (start, end) = token.span().span();
+                (token.ty(), &src[start..end])
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (Ty::Let, "let"),
+                (Ty::Identifier("café".to_owned()), "café"),
+                (Ty::Assign, ":="),
+                (Ty::Char('é'), "'é'"),
+                (Ty::Semicolon, ";"),
+                (Ty::Yield, "yield"),
+                (Ty::Identifier("naïve_2".to_owned()), "naïve_2"),
+                (Ty::Semicolon, ";"),
+                (Ty::EOF, ""),
+            ],
+            spans
+        );
+
+        match Lexer::new("let x := €;").run() {
+            Err(LangError::InvalidLexeme { span, lexeme }) => {
+                assert_eq!((9, 12), span.span());
+                assert_eq!("€", lexeme);
+            }
+            _ => panic!("expected an invalid lexeme"),
+        }
+    }
+
+    #[test]
+    fn pragmas_start_lines() {
+        assert_eq!(
+            vec![Ty::Yield, Ty::Number(1), Ty::Semicolon, Ty::EOF],
+            process("#memory 64\nyield 1;\n#note")
+        );
+        for src in ["yield 1; #memory 64", "yield 1 # 2;", "  #memory 64"] {
+            assert!(
+                matches!(
+                    Lexer::new(src).run(),
+                    Err(LangError::MisplacedPragma { .. })
+                ),
+                "`{src}` should not lex"
+            );
+        }
+    }
+
+    #[test]
+    fn lone_equals() {
+        match Lexer::new("let x = 5;").run() {
+            Err(LangError::LoneEquals { span }) => assert_eq!((6, 7), span.span()),
+            _ => panic!("expected a lone `=`"),
+        }
+        assert!(Lexer::new("let x := 5 == 5;").run().is_ok());
+    }
+
+    #[test]
+    fn comparison_and_shift_operators() {
+        for (src, ty) in [
+            ("<", Ty::LessThan),
+            (">", Ty::GreaterThan),
+            ("<=", Ty::LessThanEq),
+            (">=", Ty::GreaterThanEq),
+            ("<<", Ty::ShiftLeft),
+            (">>", Ty::ShiftRight),
+        ] {
+            assert_eq!(vec![ty, Ty::EOF], process(src), "`{src}`");
+        }
+    }
+
+    #[test]
+    fn adjacent_operators() {
+        let a = || Ty::Identifier("a".to_owned());
+        let b = || Ty::Identifier("b".to_owned());
+        assert_eq!(
+            vec![a(), Ty::LessThan, Ty::LessThan, b(), Ty::EOF],
+            process("a< <b")
+        );
+        assert_eq!(vec![a(), Ty::ShiftLeft, b(), Ty::EOF], process("a<<b"));
+        assert_eq!(
+            vec![a(), Ty::ShiftRight, Ty::GreaterThan, b(), Ty::EOF],
+            process("a>>>b")
+        );
+        assert_eq!(
+            vec![a(), Ty::ShiftLeft, Ty::LessThanEq, b(), Ty::EOF],
+            process("a<<<=b")
+        );
+        assert_eq!(
+            vec![a(), Ty::LessThan, Ty::Minus, b(), Ty::EOF],
+            process("a<-b")
+        );
+        assert_eq!(
+            vec![Ty::Bang, Ty::BangEq, Ty::Assign, Ty::Colon, Ty::EOF],
+            process("!!=:=:")
+        );
+        assert!(matches!(
+            Lexer::new("a <<= b").run(),
+            Err(LangError::LoneEquals { .. })
+        ));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            vec![Ty::Str("hi there".to_owned()), Ty::Semicolon, Ty::EOF],
+            process("\"hi there\";")
+        );
+        assert!(matches!(
+            Lexer::new("data s := \"open").run(),
+            Err(LangError::InvalidStringLiteral { .. })
+        ));
+    }
 }
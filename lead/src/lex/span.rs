@@ -6,6 +6,7 @@ use std::{
 };
 
 use miette::SourceSpan;
+use serde::{Deserialize, Serialize};
 
 fn get_primes_sieve() -> &'static Mutex<Sieve> {
     static INSTANCE: OnceLock<Mutex<Sieve>> = OnceLock::new();
@@ -15,8 +16,14 @@ fn get_primes_sieve() -> &'static Mutex<Sieve> {
     })
 }
 
+/// Start span ids again from the first prime, so that compiling the same program gives its spans
+/// the same ids.
+pub fn reset_ids() {
+    *get_primes_sieve().lock().unwrap() = Sieve::new();
+}
+
 /// A span of text in the source code of the program. `Span`s are asserted to run from left to right.
-#[derive(Clone, Copy, Ord, PartialOrd)]
+#[derive(Clone, Copy, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct Span {
     id: u64,
     span: (usize, usize),
@@ -70,7 +77,7 @@ impl Span {
     pub fn superspan(a: impl Spans, b: impl Spans) -> Self {
         Span::with_id(
             join_spans(a.span().span, b.span().span),
-            a.span().id * b.span().id,
+            a.span().id.wrapping_mul(b.span().id),
         )
     }
 
@@ -82,7 +89,7 @@ impl Span {
         let high_bound: usize = spans
             .iter()
             .fold(spans[0].span().span.1, |x, y| max(x, y.span().span.1));
-        let id: u64 = spans.iter().map(|x| x.span().id).product();
+        let id: u64 = spans.iter().fold(1, |id, x| id.wrapping_mul(x.span().id));
 
         Self {
             span: (low_bound, high_bound),
@@ -114,6 +121,17 @@ impl Span {
         self.span
     }
 
+    /// Returns the line and column of the start of `self` within `src`, both counting from 1.
+    /// Columns are counted in bytes.
+    pub fn line_col(&self, src: &str) -> (usize, usize) {
+        let before = src.get(..self.span.0).unwrap_or(src);
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (
+            before.matches('\n').count() + 1,
+            before.len() - line_start + 1,
+        )
+    }
+
     pub fn id(&self) -> u64 {
         self.id
     }
@@ -173,4 +191,12 @@ mod tests {
         let span = Span::new((0, 5));
         assert!(!span.is_disjoint(&span))
     }
+
+    #[test]
+    fn line_col_counts_from_one() {
+        let src = "let x := 1;\nyield x;";
+        assert_eq!((1, 1), Span::new((0, 3)).line_col(src));
+        assert_eq!((1, 5), Span::new((4, 5)).line_col(src));
+        assert_eq!((2, 7), Span::new((18, 19)).line_col(src));
+    }
 }
@@ -1,7 +1,13 @@
 use super::span::{Span, Spans};
 use crate::error::LangError;
 
-pub const KEYWORDS: [&'static str; 7] = ["true", "false", "let", "if", "for", "while", "yield"];
+pub const KEYWORDS: [&'static str; 12] = [
+    "true", "false", "let", "if", "else", "for", "in", "while", "yield", "return", "break", "data",
+];
+
+/// The operators longer than one character. The lexer takes the longest operator that matches, so
+/// `<<` is one token while `< <` is two.
+pub const LONG_OPERATORS: [&str; 8] = ["<<", ">>", "<=", ">=", "==", "!=", ":=", ".."];
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum TokenType {
@@ -18,7 +24,11 @@ pub enum TokenType {
     Plus,        // +
     Slash,       // /
     Star,        // *
+    Percent,     // %
     Semicolon,   // ;
+    At,          // @
+    Ampersand,   // &
+    Pipe,        // |
 
     // One or two char Tokens
     LessThan,      // <
@@ -30,19 +40,28 @@ pub enum TokenType {
     Assign,        // :=
     Bang,          // !
     BangEq,        // !=
+    DotDot,        // ..
+    ShiftLeft,     // <<
+    ShiftRight,    // >>
 
     // Literals
     Identifier(String),
     Char(char),
     Number(u64),
     Bool(bool),
+    Str(String),
 
     // Keywords
     Let,
     If,
+    Else,
     For,
+    In,
     While,
     Yield,
+    Return,
+    Break,
+    Data,
 
     // End of file
     EOF,
@@ -87,7 +106,11 @@ impl Token {
             "-" => TokenType::Minus,
             "+" => TokenType::Plus,
             "*" => TokenType::Star,
+            "%" => TokenType::Percent,
             ";" => TokenType::Semicolon,
+            "@" => TokenType::At,
+            "&" => TokenType::Ampersand,
+            "|" => TokenType::Pipe,
             "!" => TokenType::Bang,
             "<" => TokenType::LessThan,
             ">" => TokenType::GreaterThan,
@@ -97,6 +120,9 @@ impl Token {
             "<=" => TokenType::LessThanEq,
             ">=" => TokenType::GreaterThanEq,
             ":=" => TokenType::Assign,
+            ".." => TokenType::DotDot,
+            "<<" => TokenType::ShiftLeft,
+            ">>" => TokenType::ShiftRight,
             "/" => TokenType::Slash,
             _ => {
                 return Err(LangError::InvalidLexeme {
@@ -136,10 +162,15 @@ impl Token {
             "true" => return Ok(Token::from_bool(true, start)),
             "false" => return Ok(Token::from_bool(false, start)),
             "if" => TokenType::If,
+            "else" => TokenType::Else,
             "let" => TokenType::Let,
             "for" => TokenType::For,
+            "in" => TokenType::In,
             "while" => TokenType::While,
             "yield" => TokenType::Yield,
+            "return" => TokenType::Return,
+            "break" => TokenType::Break,
+            "data" => TokenType::Data,
             _ => unreachable!(),
         };
         Ok(Self {
@@ -181,7 +212,11 @@ impl std::fmt::Display for TokenType {
                 TokenType::Plus => "+",
                 TokenType::Slash => "/",
                 TokenType::Star => "*",
+                TokenType::Percent => "%",
                 TokenType::Semicolon => ";",
+                TokenType::At => "@",
+                TokenType::Ampersand => "&",
+                TokenType::Pipe => "|",
 
                 // One or two char Tokens
                 TokenType::LessThan => "<",
@@ -193,19 +228,28 @@ impl std::fmt::Display for TokenType {
                 TokenType::Assign => ":=",
                 TokenType::Bang => "!",
                 TokenType::BangEq => "!=",
+                TokenType::DotDot => "..",
+                TokenType::ShiftLeft => "<<",
+                TokenType::ShiftRight => ">>",
 
                 // Literals
                 TokenType::Identifier(string) => return write!(f, "{string}"),
                 TokenType::Char(ch) => return write!(f, "{ch}"),
                 TokenType::Number(num) => return write!(f, "{num}"),
                 TokenType::Bool(boolean) => return write!(f, "{boolean}"),
+                TokenType::Str(string) => return write!(f, "\"{string}\""),
 
                 // Keywords
                 TokenType::Let => "let",
                 TokenType::If => "if",
+                TokenType::Else => "else",
                 TokenType::For => "for",
+                TokenType::In => "in",
                 TokenType::While => "while",
                 TokenType::Yield => "yield",
+                TokenType::Return => "return",
+                TokenType::Break => "break",
+                TokenType::Data => "data",
 
                 // End of file
                 TokenType::EOF => "EOF",
@@ -1,5 +1,133 @@
 pub mod air;
+pub mod check;
 pub mod error;
+pub mod explain;
 pub mod lex;
+pub mod lint;
 pub mod parse;
+pub mod pragma;
 pub mod transcode;
+
+use air::{
+    air::{Inst, Instruction},
+    emit_program,
+    optimise::{eliminate_common_subexpressions, hoist_loop_invariants},
+    GenerationState,
+};
+use check::TypeChecker;
+use error::LangError;
+use lex::Lexer;
+use parse::LangParser;
+
+/// Make compilation reproducible from here on: span ids start again from the first prime, and
+/// labels are numbered rather than random. This is the mode for golden tests and CI, where AIR and
+/// span ids are compared between runs.
+pub fn deterministic() {
+    lex::span::reset_ids();
+    air::emit::number_labels();
+}
+
+/// Compile source code to AIR. This never panics, returning an error for any invalid program,
+/// which makes it suitable as a fuzz target.
+pub fn try_compile(src: &str) -> Result<Vec<Instruction>, LangError> {
+    let tokens = Lexer::new(src).run()?;
+    let ast = LangParser::new(&tokens).parse_statement(Vec::new())?;
+    TypeChecker::new().check(&ast)?;
+    let mut state = GenerationState::new();
+    emit_program(&mut state, ast)?;
+    let air: Vec<Inst> = state.take_instructions();
+    Ok(
+        hoist_loop_invariants(eliminate_common_subexpressions(air), false)
+            .into_iter()
+            .map(Inst::instruction)
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAGMENTS: [&str; 24] = [
+        "let",
+        "x",
+        "y",
+        ":=",
+        ":",
+        "num",
+        ";",
+        "(",
+        ")",
+        "[",
+        "]",
+        "{",
+        "}",
+        ",",
+        "-",
+        "!",
+        "+",
+        "<",
+        "==",
+        "1",
+        "4294967296",
+        "'c'",
+        "if",
+        "yield",
+    ];
+
+    #[test]
+    fn empty_program() {
+        assert!(try_compile("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn lone_operators() {
+        for src in [
+            "+", "-", "!", "*", "/", "%", "<", "<=", "!=", ":", ":=", ",", ".",
+        ] {
+            assert!(try_compile(src).is_err(), "`{src}` should not compile");
+        }
+    }
+
+    #[test]
+    fn unterminated_char() {
+        assert!(try_compile("'").is_err());
+        assert!(try_compile("let c := 'a").is_err());
+    }
+
+    #[test]
+    fn huge_numbers() {
+        assert!(try_compile("yield 99999999999999999999999;").is_err());
+        assert!(try_compile("yield 4294967296;").is_err());
+        assert!(try_compile("yield 2147483648;").is_err());
+        assert!(try_compile("yield 2147483647;").is_ok());
+    }
+
+    #[test]
+    fn value_less_operands() {
+        assert!(try_compile("let a := 1;\nlet b := 2;\nyield -(a < b);").is_err());
+        assert!(try_compile("let a := 1;\nlet b := 2;\nyield (a < b) + 1;").is_err());
+    }
+
+    #[test]
+    fn long_programs() {
+        assert!(try_compile(&"yield 1;".repeat(10_000)).is_ok());
+    }
+
+    #[test]
+    fn deterministic_fuzz() {
+        // a linear congruential generator keeps the generated programs the same between runs.
+        let mut seed: u64 = 0x1ead;
+        for _ in 0..2000 {
+            let mut src = String::new();
+            for _ in 0..12 {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                src.push_str(FRAGMENTS[(seed >> 33) as usize % FRAGMENTS.len()]);
+                src.push(' ');
+            }
+            let _ = try_compile(&src);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::LangWarning,
+    lex::span::{Span, Spans},
+    parse::ast::{Application, Expression, Iterable, OperatorType, Statement},
+};
+
+/// Find the suspicious, but valid, parts of a program.
+pub fn lint(statements: &[Statement]) -> Vec<LangWarning> {
+    let mut variables = Variables::default();
+    variables.visit_scope(statements);
+
+    let mut warnings: Vec<LangWarning> = variables
+        .declared
+        .into_iter()
+        .filter(|(_, _, read)| !read)
+        .map(|(name, span, _)| LangWarning::UnusedVariable { span, name })
+        .collect();
+    ambiguous_conditions(statements, &mut warnings);
+    warnings
+}
+
+/// Warn about `if` and `while` conditions with more than one comparison. Each comparison lowers to
+/// a `CMP`, and only the flag hint of the last one is checked, so the earlier ones are lost.
+fn ambiguous_conditions(statements: &[Statement], warnings: &mut Vec<LangWarning>) {
+    for statement in statements {
+        let (condition, body) = match statement {
+            Statement::If(r#if) => {
+                if let Some(otherwise) = &r#if.otherwise {
+                    ambiguous_conditions(otherwise, warnings);
+                }
+                (&r#if.condition, &r#if.iff)
+            }
+            Statement::While(r#while) => (&r#while.condition, &r#while.body),
+            Statement::For(r#for) => match &r#for.iterable {
+                Iterable::Counter { condition, .. } => (condition.as_ref(), &r#for.body),
+                _ => {
+                    ambiguous_conditions(&r#for.body, warnings);
+                    continue;
+                }
+            },
+            _ => continue,
+        };
+        let comparisons = comparisons(condition);
+        if comparisons > 1 {
+            warnings.push(LangWarning::AmbiguousCondition {
+                span: condition.span(),
+                comparisons,
+            });
+        }
+        ambiguous_conditions(body, warnings);
+    }
+}
+
+/// Count the comparisons in an expression.
+fn comparisons(expr: &Expression) -> usize {
+    match expr {
+        Expression::Group { expr, span: _ } => comparisons(expr),
+        Expression::App {
+            app: Application::Unary { expr, .. },
+        } => comparisons(expr),
+        Expression::App {
+            app:
+                Application::Binary {
+                    op: OperatorType::And | OperatorType::Or,
+                    left,
+                    right,
+                    ..
+                },
+        } => {
+            // each side of `&` and `|` is materialised on its own, so it is a separate condition.
+            comparisons(left).max(comparisons(right))
+        }
+        Expression::App {
+            app: Application::Binary {
+                op, left, right, ..
+            },
+        } => {
+            let comparison = match op {
+                OperatorType::LessThan
+                | OperatorType::LessThanEq
+                | OperatorType::GreaterThan
+                | OperatorType::GreaterThanEq
+                | OperatorType::NotEqual
+                | OperatorType::Equal => 1,
+                _ => 0,
+            };
+            comparison + comparisons(left) + comparisons(right)
+        }
+        _ => 0,
+    }
+}
+
+/// The variables a program declares and whether each is read. A name is looked up from the
+/// innermost scope out, so a variable that shadows another is tracked apart from it.
+#[derive(Default)]
+struct Variables {
+    declared: Vec<(String, Span, bool)>,
+    /// the index of each variable in scope by name, innermost scope last.
+    scopes: Vec<HashMap<String, usize>>,
+}
+
+impl Variables {
+    fn declare(&mut self, name: &str, span: Span) {
+        self.declared.push((name.to_owned(), span, false));
+        let index = self.declared.len() - 1;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_owned(), index);
+        }
+    }
+
+    fn read(&mut self, name: &str) {
+        let declaration = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied());
+        if let Some(index) = declaration {
+            self.declared[index].2 = true;
+        }
+    }
+
+    /// Visit the statements of a block, forgetting the variables they declare after, as the
+    /// checker does.
+    fn visit_scope(&mut self, statements: &[Statement]) {
+        self.scopes.push(HashMap::new());
+        visit_statements(statements, self);
+        self.scopes.pop();
+    }
+}
+
+fn visit_statements(statements: &[Statement], variables: &mut Variables) {
+    for statement in statements {
+        match statement {
+            Statement::Let(r#let) => {
+                visit_expression(&r#let.value, variables);
+                variables.declare(&r#let.variable, r#let.span());
+            }
+            Statement::Mutate(mutate) => visit_expression(&mutate.value, variables),
+            Statement::Expr(expr) | Statement::Yield(expr, _) | Statement::Return(expr) => {
+                visit_expression(expr, variables)
+            }
+            Statement::Break(_) => (),
+            Statement::Data(data) => variables.declare(data.name.name(), data.span()),
+            Statement::If(r#if) => {
+                visit_expression(&r#if.condition, variables);
+                variables.visit_scope(&r#if.iff);
+                if let Some(otherwise) = &r#if.otherwise {
+                    variables.visit_scope(otherwise);
+                }
+            }
+            Statement::While(r#while) => {
+                visit_expression(&r#while.condition, variables);
+                variables.visit_scope(&r#while.body);
+            }
+            Statement::For(r#for) => {
+                match &r#for.iterable {
+                    Iterable::Array(array) => variables.read(array.name()),
+                    Iterable::Range { start, end, .. } => {
+                        visit_expression(start, variables);
+                        visit_expression(end, variables);
+                    }
+                    Iterable::Counter { start, .. } => visit_expression(start, variables),
+                }
+                // the item is in scope for the condition, step and body. It is set by the loop, so
+                // it isn't reported when the body doesn't read it.
+                variables.scopes.push(HashMap::new());
+                variables.declare(r#for.item.name(), r#for.item.span());
+                variables.read(r#for.item.name());
+                if let Iterable::Counter {
+                    condition, step, ..
+                } = &r#for.iterable
+                {
+                    visit_expression(condition, variables);
+                    visit_expression(&step.value, variables);
+                }
+                variables.visit_scope(&r#for.body);
+                variables.scopes.pop();
+            }
+        }
+    }
+}
+
+/// Record every variable read by an expression.
+fn visit_expression(expr: &Expression, variables: &mut Variables) {
+    match expr {
+        Expression::Literal { lit: _ } => (),
+        Expression::Identifier(identifier) => variables.read(identifier.name()),
+        Expression::Group { expr, span: _ } => visit_expression(expr, variables),
+        Expression::Array { elements, span: _ } => {
+            for element in elements {
+                visit_expression(element, variables);
+            }
+        }
+        Expression::Repeat {
+            element,
+            count,
+            span: _,
+        } => {
+            visit_expression(element, variables);
+            visit_expression(count, variables);
+        }
+        Expression::Index {
+            variable,
+            index,
+            span: _,
+        } => {
+            variables.read(variable.name());
+            visit_expression(index, variables);
+        }
+        Expression::Call {
+            function: _,
+            arguments,
+            span: _,
+        } => {
+            for argument in arguments {
+                visit_expression(argument, variables);
+            }
+        }
+        Expression::App { app } => match app {
+            Application::Unary {
+                op: _,
+                expr,
+                span: _,
+            } => visit_expression(expr, variables),
+            Application::Binary {
+                op: _,
+                left,
+                right,
+                span: _,
+            } => {
+                visit_expression(left, variables);
+                visit_expression(right, variables);
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex::Lexer, parse::LangParser};
+
+    fn lint_src(src: &str) -> Vec<LangWarning> {
+        let tokens = Lexer::new(src).run().unwrap();
+        lint(
+            &LangParser::new(&tokens)
+                .parse_statement(Vec::new())
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn unused_variable() {
+        match lint_src("let x := 1;\nlet y := 2;\nyield y;").as_slice() {
+            [LangWarning::UnusedVariable { span, name }] => {
+                assert_eq!("x", name);
+                assert_eq!(Span::new((0, 10)), *span);
+            }
+            warnings => panic!("expected one unused variable, found {warnings:?}"),
+        }
+    }
+
+    #[test]
+    fn mutated_variable_is_unused() {
+        assert_eq!(1, lint_src("let x := 1;\nx := 2;").len());
+        assert!(lint_src("let x := 1;\nx := x + 2;\nyield x;").is_empty());
+    }
+
+    #[test]
+    fn ambiguous_condition() {
+        let src = "let a := 1;\nlet b := 2;\nwhile true {\nif (a < b) == (b > a) {\nyield a;\n}\n}";
+        match lint_src(src).as_slice() {
+            [LangWarning::AmbiguousCondition { span, comparisons }] => {
+                assert_eq!(3, *comparisons);
+                assert_eq!(Span::new((40, 58)), *span);
+            }
+            warnings => panic!("expected one ambiguous condition, found {warnings:?}"),
+        }
+        assert!(lint_src("let a := 1;\nif a < 2 {\nyield a;\n}").is_empty());
+        assert!(lint_src("let a := 1;\nif (a > 0) & (a < 2) {\nyield a;\n}").is_empty());
+    }
+
+    #[test]
+    fn shadowed_variables() {
+        let unused = |src: &str| -> Vec<Span> {
+            lint_src(src)
+                .into_iter()
+                .filter_map(|warning| match warning {
+                    LangWarning::UnusedVariable { span, .. } => Some(span),
+                    _ => None,
+                })
+                .collect()
+        };
+        // an unused variable shadowing a used one is reported, and the used one isn't.
+        assert_eq!(
+            vec![Span::new((35, 45))],
+            unused("let x := 1;\nlet c := 0;\nif c < 1 {\nlet x := 2;\n}\nyield x;")
+        );
+        assert_eq!(
+            vec![Span::new((0, 10))],
+            unused("let x := 1;\nwhile true {\nlet x := 2;\nyield x;\n}")
+        );
+        assert_eq!(
+            vec![Span::new((0, 10))],
+            unused("let x := 1;\nfor x in 0..3 {\nyield x;\n}")
+        );
+        assert!(unused("let x := 1;\nlet c := 0;\nif c < 1 {\nyield x;\n}").is_empty());
+    }
+
+    #[test]
+    fn nested_uses() {
+        assert!(lint_src("let a := [1, 2];\nlet i := 0;\nif i < 1 {\nyield a[i];\n}").is_empty());
+    }
+}
@@ -1,16 +1,37 @@
+use crate::air::air::Stream;
 use crate::error::LangError;
 use crate::lex::{span::*, token::Token};
+use serde::{Deserialize, Serialize};
 
 type Statements = Vec<Statement>;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+/// The version of the JSON form of the syntax tree, raised whenever its shape changes.
+pub const AST_SCHEMA_VERSION: u32 = 6;
+
+/// A parsed program as exported for external tools, tagged with the schema it follows.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AstDocument {
+    pub version: u32,
+    pub statements: Statements,
+}
+
+impl AstDocument {
+    pub fn new(statements: Statements) -> Self {
+        Self {
+            version: AST_SCHEMA_VERSION,
+            statements,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Literal {
     Boolean { val: bool, span: Span },
     Char { val: char, span: Span },
     Number { val: i32, span: Span },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Expression {
     App {
         app: Application,
@@ -27,14 +48,26 @@ pub enum Expression {
         elements: Vec<Box<Expression>>,
         span: Span,
     },
+    /// An array of `count` copies of `element`, such as `[0; 8]`.
+    Repeat {
+        element: Box<Expression>,
+        count: Box<Expression>,
+        span: Span,
+    },
     Index {
         variable: Identifier,
         index: Box<Expression>,
         span: Span,
     },
+    /// A call to a built-in function, such as `swap(a, b)`.
+    Call {
+        function: Identifier,
+        arguments: Vec<Expression>,
+        span: Span,
+    },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Identifier {
     id: String,
     span: Span,
@@ -63,7 +96,7 @@ impl Spans for Identifier {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Application {
     Unary {
         op: OperatorType,
@@ -78,34 +111,85 @@ pub enum Application {
     },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Mutate {
     pub variable: String,
     pub value: Expression,
+    /// The variables between this one and the value in a chained assignment, such as the `b` in
+    /// `a := b := 0;`.
+    pub chained: Vec<Identifier>,
     span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Let {
     pub variable: String,
     pub value: Expression,
+    /// The declared type of the variable, if one was given.
+    pub annotation: Option<Annotation>,
+    /// The already declared variables between this one and the value in a chained assignment,
+    /// such as the `b` in `let a := b := 0;`.
+    pub chained: Vec<Identifier>,
     span: Span,
 }
 
+/// The types of values in the language.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Type {
+    Num,
+    Bool,
+    Char,
+    Array(Box<Type>),
+}
+
+/// The functions built in to the language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `swap(a, b)` exchanges the values of two variables.
+    Swap,
+    /// `div_floor(a, b)` divides `a` by `b`, rounding towards negative infinity.
+    DivFloor,
+    /// `cycles()` is the number of instructions the machine has executed so far.
+    Cycles,
+    /// `arg(i)` is the `i`th number the program was run with, counting from 0.
+    Arg,
+    /// `alloc(n)` reserves `n` bytes from the heap while the program runs, and is their address.
+    Alloc,
+    /// `sizeof(arr)` is the number of bytes an array takes up in memory, known when compiling.
+    SizeOf,
+    /// `addressof(arr)` is the address of the first element of an array, known when compiling.
+    AddressOf,
+}
+
+/// A type annotation on a variable, such as the `: num` in `let x: num := 5;`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub ty: Type,
+    span: Span,
+}
+
+impl Spans for Annotation {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
 impl Spans for Let {
     fn span(&self) -> Span {
         self.span
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct If {
     pub condition: Expression,
     pub iff: Statements,
+    /// The body of the `else`, if there is one.
+    pub otherwise: Option<Statements>,
     span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct While {
     pub condition: Expression,
     pub body: Statements,
@@ -118,14 +202,85 @@ impl Spans for While {
     }
 }
 
-#[derive(Debug, Clone)]
+/// A loop over the elements of an array, such as `for x in xs { }`, over a range, such as
+/// `for i in 0..10 { }`, or with a counter, such as `for i := 0; i < 10; i := i + 1 { }`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct For {
+    /// The variable holding each element in turn, only in scope within the body.
+    pub item: Identifier,
+    pub iterable: Iterable,
+    pub body: Statements,
+    span: Span,
+}
+
+/// What a `for` loop iterates over.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Iterable {
+    /// The elements of an array variable.
+    Array(Identifier),
+    /// The numbers or characters from `start` up to, but not including, `end`.
+    Range {
+        start: Expression,
+        end: Expression,
+        span: Span,
+    },
+    /// A counter starting at `start`, changed by `step` after each iteration, for as long as
+    /// `condition` holds.
+    Counter {
+        start: Box<Expression>,
+        condition: Box<Expression>,
+        step: Box<Mutate>,
+        span: Span,
+    },
+}
+
+impl Spans for Iterable {
+    fn span(&self) -> Span {
+        match self {
+            Self::Array(array) => array.span(),
+            Self::Range { span, .. } | Self::Counter { span, .. } => *span,
+        }
+    }
+}
+
+impl Spans for For {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// A table of constants placed in memory before the program runs, such as
+/// `data primes := [2, 3, 5, 7];`. A string, as in `data greeting := "hi";`, is an array of its
+/// characters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Data {
+    pub name: Identifier,
+    /// The array of values, either an array literal or a repeated value.
+    pub table: Expression,
+    span: Span,
+}
+
+impl Spans for Data {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Statement {
     Let(Let),
     Mutate(Mutate),
     Expr(Expression),
     If(If),
     While(While),
-    Yield(Expression),
+    For(For),
+    /// Yield a value to an output stream, written `yield@err x;` for a stream other than the default.
+    Yield(Expression, Stream),
+    /// Yield a value and stop the program.
+    Return(Expression),
+    /// Leave the innermost loop.
+    Break(Span),
+    Data(Data),
 }
 
 impl Spans for Statement {
@@ -136,17 +291,23 @@ impl Spans for Statement {
             Self::Expr(expr) => expr.span(),
             Self::If(r#if) => r#if.span(),
             Self::While(r#while) => r#while.span(),
-            Self::Yield(r#yield) => r#yield.span(),
+            Self::For(r#for) => r#for.span(),
+            Self::Yield(r#yield, _) => r#yield.span(),
+            Self::Return(r#return) => r#return.span(),
+            Self::Break(span) => *span,
+            Self::Data(data) => data.span(),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum OperatorType {
     Plus,
     Minus,
     Divide,
     Multiply,
+    /// The remainder of a division, with the sign of the dividend.
+    Modulo,
     LessThan,
     GreaterThan,
     LessThanEq,
@@ -154,6 +315,10 @@ pub enum OperatorType {
     Equal,
     Not,
     NotEqual,
+    /// Eager boolean and, evaluating both sides.
+    And,
+    /// Eager boolean or, evaluating both sides.
+    Or,
 }
 
 // spans for ast
@@ -176,11 +341,21 @@ impl Spans for Expression {
             Expression::App { app } => app.span(),
             Expression::Identifier(identifier) => identifier.span(),
             Expression::Array { elements: _, span } => *span,
+            Expression::Repeat {
+                element: _,
+                count: _,
+                span,
+            } => *span,
             Expression::Index {
                 variable: _,
                 index: _,
                 span,
             } => *span,
+            Expression::Call {
+                function: _,
+                arguments: _,
+                span,
+            } => *span,
         }
     }
 }
@@ -241,6 +416,21 @@ impl Literal {
     }
 }
 
+impl OperatorType {
+    /// Whether the operator compares two values, which only sets flags rather than giving a value.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            OperatorType::LessThan
+                | OperatorType::GreaterThan
+                | OperatorType::LessThanEq
+                | OperatorType::GreaterThanEq
+                | OperatorType::Equal
+                | OperatorType::NotEqual
+        )
+    }
+}
+
 impl Application {
     /// Create an Application from a unary operator and expression
     pub fn from_unary(tok: &Token, op: OperatorType, expr: Expression) -> Self {
@@ -328,9 +518,15 @@ impl Mutate {
         Mutate {
             variable: name,
             value,
+            chained: Vec::new(),
             span,
         }
     }
+
+    pub fn with_chained(mut self, chained: Vec<Identifier>) -> Self {
+        self.chained = chained;
+        self
+    }
 }
 
 impl Let {
@@ -351,16 +547,99 @@ impl Let {
         Ok(Let {
             variable: name,
             value,
+            annotation: None,
+            chained: Vec::new(),
             span,
         })
     }
+
+    pub fn with_annotation(mut self, annotation: Option<Annotation>) -> Self {
+        self.annotation = annotation;
+        self
+    }
+
+    pub fn with_chained(mut self, chained: Vec<Identifier>) -> Self {
+        self.chained = chained;
+        self
+    }
+}
+
+impl Type {
+    /// Get the type named by a type annotation, if it exists.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "num" => Type::Num,
+            "bool" => Type::Bool,
+            "char" => Type::Char,
+            _ => return None,
+        })
+    }
+}
+
+impl Builtin {
+    /// Resolve the built-in called by a call expression, checking it is given the right number of
+    /// arguments.
+    pub fn resolve(
+        function: &Identifier,
+        arguments: &[Expression],
+        span: Span,
+    ) -> Result<Self, LangError> {
+        let builtin = match function.name() {
+            "swap" => Builtin::Swap,
+            "div_floor" => Builtin::DivFloor,
+            "cycles" => Builtin::Cycles,
+            "arg" => Builtin::Arg,
+            "alloc" => Builtin::Alloc,
+            "sizeof" => Builtin::SizeOf,
+            "addressof" => Builtin::AddressOf,
+            name => {
+                return Err(LangError::UnknownFunction {
+                    span: function.span(),
+                    name: name.to_owned(),
+                })
+            }
+        };
+        if arguments.len() != builtin.arity() {
+            return Err(LangError::ArgumentCount {
+                span,
+                name: function.name().to_owned(),
+                expected: builtin.arity(),
+                found: arguments.len(),
+            });
+        }
+        Ok(builtin)
+    }
+
+    /// The number of arguments the built-in takes.
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Swap | Builtin::DivFloor => 2,
+            Builtin::Cycles => 0,
+            Builtin::Arg | Builtin::Alloc | Builtin::SizeOf | Builtin::AddressOf => 1,
+        }
+    }
+}
+
+impl Annotation {
+    pub fn new(ty: Type, span: impl Spans) -> Self {
+        Self {
+            ty,
+            span: span.span(),
+        }
+    }
 }
 
 impl If {
-    pub fn from(condition: Expression, body: Statements, span: Span) -> Self {
+    pub fn from(
+        condition: Expression,
+        body: Statements,
+        otherwise: Option<Statements>,
+        span: Span,
+    ) -> Self {
         Self {
             condition,
             iff: body,
+            otherwise,
             span,
         }
     }
@@ -376,8 +655,36 @@ impl While {
     }
 }
 
+impl For {
+    pub fn from(item: Identifier, iterable: Iterable, body: Statements, span: Span) -> Self {
+        Self {
+            item,
+            iterable,
+            body,
+            span,
+        }
+    }
+}
+
+impl Data {
+    pub fn from(name: Identifier, table: Expression, span: Span) -> Self {
+        Self { name, table, span }
+    }
+}
+
 // impl display for ast
 
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Num => write!(f, "num"),
+            Type::Bool => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::Array(ty) => write!(f, "[{ty}]"),
+        }
+    }
+}
+
 impl std::fmt::Debug for Literal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
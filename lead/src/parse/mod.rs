@@ -1,4 +1,5 @@
 use crate::{
+    air::air::Stream,
     error::LangError,
     lex::{
         span::{Span, Spans},
@@ -7,7 +8,8 @@ use crate::{
 };
 
 use self::ast::{
-    Application, Expression, Identifier, If, Let, Literal, Mutate, OperatorType, Statement, While,
+    Annotation, Application, Data, Expression, For, Identifier, If, Iterable, Let, Literal, Mutate,
+    OperatorType, Statement, Type, While,
 };
 
 pub mod ast;
@@ -85,6 +87,8 @@ pub trait HasTrivia {
 pub struct LangParser<'i> {
     src: &'i [Token],
     index: usize,
+    /// how many blocks the parser is inside of.
+    depth: usize,
 }
 
 impl<'i> Parser<'i, Token> for LangParser<'i> {
@@ -99,9 +103,15 @@ impl<'i> Parser<'i, Token> for LangParser<'i> {
 
 impl<'i> LangParser<'i> {
     pub fn new(src: &'i [Token]) -> Self {
-        Self { src, index: 0 }
+        Self {
+            src,
+            index: 0,
+            depth: 0,
+        }
     }
 
+    /// Parse statements until the end of the file or block. Statements are terminated by `;`,
+    /// rather than separated by it, so a lone `;` is an empty statement and is skipped.
     pub fn parse_statement(
         &mut self,
         mut buf: Vec<Statement>,
@@ -117,6 +127,10 @@ impl<'i> LangParser<'i> {
                     //self.consume(TokenType::RightBrace)?;
                     break;
                 }
+                TokenType::Semicolon => {
+                    self.advance_one();
+                    continue;
+                }
 
                 TokenType::Identifier(_) => {
                     //self.advance_one();
@@ -131,12 +145,21 @@ impl<'i> LangParser<'i> {
                 | TokenType::Char(_)
                 | TokenType::LeftParen
                 | TokenType::Bang
-                | TokenType::Minus => Statement::Expr(self.parse_expr()?),
+                | TokenType::Minus
+                | TokenType::Plus => Statement::Expr(self.parse_expr()?),
 
                 // keywords
                 TokenType::Let => Statement::Let(self.parse_let()?),
                 TokenType::While | TokenType::If => self.parse_wif()?,
+                TokenType::For => Statement::For(self.parse_for()?),
                 TokenType::Yield => self.parse_yield()?,
+                TokenType::Return => self.parse_return()?,
+                TokenType::Break => {
+                    let span = self.consume(TokenType::Break)?.span();
+                    self.consume(TokenType::Semicolon)?;
+                    Statement::Break(span)
+                }
+                TokenType::Data => Statement::Data(self.parse_data()?),
                 _ => {
                     let tok = self.peek_one()?;
                     return Err(LangError::UnexpectedToken {
@@ -152,9 +175,75 @@ impl<'i> LangParser<'i> {
         return Ok(buf);
     }
 
+    /// Parse the statements of a block, up to its closing `}`.
+    fn parse_body(&mut self) -> Result<Vec<Statement>, LangError> {
+        self.depth += 1;
+        let body = self.parse_statement(Vec::new());
+        self.depth -= 1;
+        body
+    }
+
+    /// Parse a `data` table, which is only allowed outside of every block as it is filled in
+    /// before the program runs.
+    fn parse_data(&mut self) -> Result<Data, LangError> {
+        let start = self.consume(TokenType::Data)?.span();
+        if self.depth > 0 {
+            return Err(LangError::NestedData { span: start });
+        }
+        let name = self.parse_identifier()?;
+        self.consume(TokenType::Assign)?;
+
+        let tok = self.peek_one()?.clone();
+        let table = match tok.token_type() {
+            // a string is an array of its characters.
+            TokenType::Str(string) => {
+                self.advance_one();
+                Expression::Array {
+                    elements: string
+                        .chars()
+                        .map(|ch| {
+                            Box::new(Expression::Literal {
+                                lit: Literal::from_char(&tok, ch),
+                            })
+                        })
+                        .collect(),
+                    span: tok.span(),
+                }
+            }
+            TokenType::LeftSquare => self.parse_array()?,
+            _ => {
+                return Err(LangError::UnexpectedToken {
+                    span: tok.span(),
+                    tok: tok.ty(),
+                    expected: "an array or a string".to_string(),
+                })
+            }
+        };
+        let end = self.consume(TokenType::Semicolon)?.span();
+        Ok(Data::from(name, table, Span::together([start, end])))
+    }
+
     fn parse_yield(&mut self) -> Result<Statement, LangError> {
         self.consume(TokenType::Yield)?;
-        let statement = Statement::Yield(self.parse_expr()?);
+        let stream = match self.peek_one()?.token_type() {
+            TokenType::At => {
+                self.consume(TokenType::At)?;
+                let name = self.parse_identifier()?;
+                Stream::from_name(name.name()).ok_or(LangError::UnknownStream {
+                    span: name.span(),
+                    name: name.name().to_owned(),
+                })?
+            }
+            _ => Stream::Out,
+        };
+        let statement = Statement::Yield(self.parse_expr()?, stream);
+        self.consume(TokenType::Semicolon)?;
+        Ok(statement)
+    }
+
+    fn parse_return(&mut self) -> Result<Statement, LangError> {
+        self.consume(TokenType::Return)?;
+        let statement = Statement::Return(self.parse_expr()?);
         self.consume(TokenType::Semicolon)?;
         Ok(statement)
     }
@@ -164,20 +253,30 @@ impl<'i> LangParser<'i> {
         let ty = start.token_type();
 
         let condition = self.parse_expr()?;
+        if let TokenType::Assign = self.peek_one()?.token_type() {
+            return Err(LangError::AssignmentInCondition {
+                span: self.peek_one()?.span(),
+            });
+        }
 
         let lb_span = self.consume(TokenType::LeftBrace)?.span();
 
-        let body: Vec<Statement> = self.parse_statement(Vec::new())?;
+        let body: Vec<Statement> = self.parse_body()?;
+        let rb_span = self.consume_closing(TokenType::RightBrace, lb_span)?.span();
 
-        let span = Span::together([
-            start.span(),
-            condition.span(),
-            lb_span,
-            self.consume(TokenType::RightBrace)?.span(),
-        ]);
+        let mut span = Span::together([start.span(), condition.span(), lb_span, rb_span]);
 
         Ok(match ty {
-            TokenType::If => Statement::If(If::from(condition, body, span)),
+            TokenType::If => {
+                let otherwise = match self.parse_else()? {
+                    Some((otherwise, else_span)) => {
+                        span = Span::together([span, else_span]);
+                        Some(otherwise)
+                    }
+                    None => None,
+                };
+                Statement::If(If::from(condition, body, otherwise, span))
+            }
             TokenType::While => Statement::While(While::from(condition, body, span)),
             _ => {
                 unreachable!()
@@ -185,33 +284,187 @@ impl<'i> LangParser<'i> {
         })
     }
 
+    /// Parse the `else` after the body of an `if` and the span it covers, if there is one.
+    /// `else if` is an `else` whose body is the following `if`.
+    fn parse_else(&mut self) -> Result<Option<(Vec<Statement>, Span)>, LangError> {
+        if self.peek_one()?.token_type() != &TokenType::Else {
+            return Ok(None);
+        }
+        let else_span = self.advance_one().unwrap().span();
+        if self.peek_one()?.token_type() == &TokenType::If {
+            let r#if = self.parse_wif()?;
+            let span = Span::together([else_span, r#if.span()]);
+            return Ok(Some((vec![r#if], span)));
+        }
+
+        let lb_span = self.consume(TokenType::LeftBrace)?.span();
+        let body: Vec<Statement> = self.parse_body()?;
+        let rb_span = self.consume_closing(TokenType::RightBrace, lb_span)?.span();
+        Ok(Some((body, Span::together([else_span, rb_span]))))
+    }
+
+    pub fn parse_for(&mut self) -> Result<For, LangError> {
+        let start = self.consume(TokenType::For)?.span();
+        let item = self.parse_identifier()?;
+        let iterable = match self.peek_one()?.token_type() {
+            TokenType::Assign => self.parse_counter()?,
+            _ => {
+                self.consume(TokenType::In)?;
+                self.parse_iterable()?
+            }
+        };
+
+        let lb_span = self.consume(TokenType::LeftBrace)?.span();
+        let body: Vec<Statement> = self.parse_body()?;
+        let rb_span = self.consume_closing(TokenType::RightBrace, lb_span)?.span();
+
+        Ok(For::from(
+            item,
+            iterable,
+            body,
+            Span::together([start, lb_span, rb_span]),
+        ))
+    }
+
+    /// Parse what a `for` loop iterates over, either an array variable or a range `start..end`.
+    fn parse_iterable(&mut self) -> Result<Iterable, LangError> {
+        let start = self.parse_expr()?;
+        if self.peek_one()?.token_type() == &TokenType::DotDot {
+            self.advance_one();
+            let end = self.parse_expr()?;
+            let span = Span::together([start.span(), end.span()]);
+            return Ok(Iterable::Range { start, end, span });
+        }
+        match start {
+            Expression::Identifier(array) => Ok(Iterable::Array(array)),
+            _ => Err(LangError::ExpectedVariable { span: start.span() }),
+        }
+    }
+
+    /// Parse the `:= start; condition; step` of a counting `for` loop, after its variable.
+    fn parse_counter(&mut self) -> Result<Iterable, LangError> {
+        let assign = self.consume(TokenType::Assign)?.span();
+        let start = self.parse_expr()?;
+        self.consume(TokenType::Semicolon)?;
+        let condition = self.parse_expr()?;
+        self.consume(TokenType::Semicolon)?;
+
+        // the step is an assignment without the `;`, as the body follows it.
+        let variable = self.peek_one()?.clone();
+        self.parse_identifier()?;
+        self.consume(TokenType::Assign)?;
+        let step = Mutate::from(&variable, self.parse_expr()?);
+
+        let span = Span::together([assign, step.span()]);
+        Ok(Iterable::Counter {
+            start: Box::new(start),
+            condition: Box::new(condition),
+            step: Box::new(step),
+            span,
+        })
+    }
+
+    /// Parse a variable name where an expression is not allowed.
+    fn parse_identifier(&mut self) -> Result<Identifier, LangError> {
+        let tok = self.peek_one()?.clone();
+        match tok.token_type() {
+            TokenType::Identifier(name) => {
+                self.advance_one();
+                Ok(Identifier::new(name.clone(), &tok))
+            }
+            _ => Err(LangError::ExpectedVariable { span: tok.span() }),
+        }
+    }
+
     pub fn parse_let(&mut self) -> Result<Let, LangError> {
         let start = self.consume(TokenType::Let)?;
-        let (variable, expr) = self.parse_assign()?;
-        Let::from(variable, expr, start)
+        let variable = self.parse_variable()?;
+        let annotation = self.parse_annotation()?;
+        let (chained, expr) = self.parse_assigned_value()?;
+        Ok(Let::from(variable, expr, start)?
+            .with_annotation(annotation)
+            .with_chained(chained))
+    }
+
+    /// Parse an optional type annotation, `: <type>`.
+    fn parse_annotation(&mut self) -> Result<Option<Annotation>, LangError> {
+        if *self.peek_one()?.token_type() != TokenType::Colon {
+            return Ok(None);
+        }
+        let colon = self.consume(TokenType::Colon)?;
+        let tok = self.peek_one()?.clone();
+        let ty = match tok.token_type() {
+            TokenType::Identifier(name) => Type::from_name(name).ok_or(LangError::UnknownType {
+                span: tok.span(),
+                name: name.to_owned(),
+            })?,
+            _ => {
+                return Err(LangError::UnexpectedToken {
+                    span: tok.span(),
+                    tok: tok.ty(),
+                    expected: "type".to_string(),
+                })
+            }
+        };
+        self.advance_one();
+        Ok(Some(Annotation::new(ty, Span::superspan(colon, tok))))
     }
 
     pub fn parse_mutate(&mut self) -> Result<Mutate, LangError> {
-        let (variable, expr) = self.parse_assign()?;
-        Ok(Mutate::from(variable, expr))
+        let (variable, chained, expr) = self.parse_assign()?;
+        Ok(Mutate::from(variable, expr).with_chained(chained))
+    }
+
+    pub fn parse_assign(&mut self) -> Result<(&Token, Vec<Identifier>, Expression), LangError> {
+        let variable = self.parse_variable()?;
+        let (chained, value) = self.parse_assigned_value()?;
+        Ok((variable, chained, value))
     }
 
-    pub fn parse_assign(&mut self) -> Result<(&Token, Expression), LangError> {
-        let variable = self.advance_one().ok_or(LangError::UnexpectedEndOfFile {
-            span: Span::new((self.index - 1, self.index)),
+    fn parse_variable(&mut self) -> Result<&'i Token, LangError> {
+        self.advance_one().ok_or(LangError::UnexpectedEndOfFile {
+            span: Span::new((self.index.saturating_sub(1), self.index)),
             expected: "identifier".to_string(),
             found: None,
-        })?;
+        })
+    }
+
+    /// Parse the `:= <expression>;` of an assignment, along with the variables of a chained
+    /// assignment before the expression, such as the `b :=` in `a := b := 0;`.
+    fn parse_assigned_value(&mut self) -> Result<(Vec<Identifier>, Expression), LangError> {
         self.consume(TokenType::Assign)?;
+        let mut chained: Vec<Identifier> = Vec::new();
+        while let TokenType::Identifier(name) = self.peek_one()?.token_type().clone() {
+            if *self.peek_nth(2)?.token_type() != TokenType::Assign {
+                break;
+            }
+            chained.push(Identifier::new(name, self.advance_one().unwrap()));
+            self.consume(TokenType::Assign)?;
+        }
         let value = self.parse_expr()?;
         self.consume(TokenType::Semicolon)?;
-        Ok((variable, value))
+        Ok((chained, value))
+    }
+
+    /// Parse the whole input as one expression, such as one given to `leadc eval`, erroring on
+    /// anything after it.
+    pub fn parse_lone_expression(&mut self) -> Result<Expression, LangError> {
+        let expr = self.parse_expr()?;
+        if self.is_eof() {
+            return Ok(expr);
+        }
+        let tok = self.peek_one()?;
+        Err(LangError::UnexpectedToken {
+            span: tok.span(),
+            tok: tok.ty(),
+            expected: "the end of the expression".to_string(),
+        })
     }
 
     pub fn parse_expr(&mut self) -> Result<Expression, LangError> {
         if self.is_eof() {
             return Err(LangError::UnexpectedEndOfFile {
-                span: Span::new((self.index - 1, self.index)),
+                span: Span::new((self.index.saturating_sub(1), self.index)),
                 expected: "expression".to_owned(),
                 found: None,
             });
@@ -227,7 +480,7 @@ impl<'i> LangParser<'i> {
                 self.parse_partial(left)?
             }
             // unary operators
-            TokenType::Minus | TokenType::Bang => {
+            TokenType::Minus | TokenType::Plus | TokenType::Bang => {
                 let tok = self.peek_one().unwrap().clone();
                 let op = self.parse_unary_operator()?;
                 let expr = self.parse_expr()?;
@@ -239,22 +492,13 @@ impl<'i> LangParser<'i> {
             TokenType::LeftParen => {
                 let mut span = self.advance_one().unwrap().span();
                 let expr = self.parse_expr()?;
-                match self.peek_one()?.ty() {
-                    TokenType::RightParen => span.join(self.advance_one().unwrap()),
-                    ty => {
-                        return Err(LangError::UnmatchedDelimiter {
-                            span: self.peek_one()?.span(),
-                            expected: TokenType::RightParen,
-                            found: ty,
-                        });
-                    }
-                };
+                span.join(self.consume_closing(TokenType::RightParen, span)?);
                 Expression::Group {
                     expr: Box::new(expr),
                     span,
                 }
             }
-            // identifier or array index
+            // identifier, array index, or call
             TokenType::Identifier(name) => {
                 let identifier = Identifier::new((*name).clone(), self.advance_one().unwrap());
                 match self.peek_one()?.token_type() {
@@ -269,13 +513,18 @@ impl<'i> LangParser<'i> {
                             span,
                         }
                     }
+                    TokenType::LeftParen => self.parse_call(identifier)?,
                     _ => Expression::Identifier(identifier),
                 }
             }
             TokenType::LeftSquare => self.parse_array()?,
-            tok => {
-                dbg!(tok);
-                todo!()
+            _ => {
+                let tok = self.peek_one()?;
+                return Err(LangError::UnexpectedToken {
+                    span: tok.span(),
+                    tok: tok.ty(),
+                    expected: "expression".to_string(),
+                });
             }
         };
 
@@ -292,13 +541,15 @@ impl<'i> LangParser<'i> {
             | TokenType::Plus
             | TokenType::Slash
             | TokenType::Star
+            | TokenType::Percent
             | TokenType::LessThan
             | TokenType::GreaterThan
             | TokenType::LessThanEq
             | TokenType::GreaterThanEq
             | TokenType::EqEq
-            | TokenType::Bang
-            | TokenType::BangEq => {
+            | TokenType::BangEq
+            | TokenType::Ampersand
+            | TokenType::Pipe => {
                 // we are parsing a binary expression
                 let op = self.parse_binary_operator()?;
                 let right = self.parse_expr()?;
@@ -306,6 +557,14 @@ impl<'i> LangParser<'i> {
                     app: Application::from_binary(op, left, right),
                 });
             }
+            TokenType::Bang => {
+                // `!` only negates, so it can't join two expressions.
+                let tok = self.peek_one()?;
+                Err(LangError::InvalidBinaryOperator {
+                    span: tok.span(),
+                    op: tok.ty(),
+                })
+            }
             _ => return Ok(left),
         }
     }
@@ -321,7 +580,14 @@ impl<'i> LangParser<'i> {
         let literal = match tok.token_type() {
             TokenType::Bool(b) => Literal::from_bool(tok, *b),
             TokenType::Char(c) => Literal::from_char(tok, *c),
-            TokenType::Number(n) => Literal::from_number(tok, (*n).try_into().unwrap()), // todo: fix this panic
+            TokenType::Number(n) => Literal::from_number(
+                tok,
+                (*n).try_into()
+                    .map_err(|_| LangError::InvalidIntegerLiteral {
+                        span: tok.span(),
+                        num_literal: n.to_string(),
+                    })?,
+            ),
             _ => {
                 return Err(LangError::InvalidLiteral {
                     span: tok.span(),
@@ -338,6 +604,7 @@ impl<'i> LangParser<'i> {
         let op = match tok.token_type() {
             TokenType::Bang => OperatorType::Not,
             TokenType::Minus => OperatorType::Minus,
+            TokenType::Plus => OperatorType::Plus,
             _ => {
                 return Err(LangError::InvalidUnaryOperator {
                     span: tok.span(),
@@ -356,12 +623,15 @@ impl<'i> LangParser<'i> {
             TokenType::Plus => OperatorType::Plus,
             TokenType::Slash => OperatorType::Divide,
             TokenType::Star => OperatorType::Multiply,
+            TokenType::Percent => OperatorType::Modulo,
             TokenType::LessThan => OperatorType::LessThan,
             TokenType::GreaterThan => OperatorType::GreaterThan,
             TokenType::LessThanEq => OperatorType::LessThanEq,
             TokenType::GreaterThanEq => OperatorType::GreaterThanEq,
             TokenType::EqEq => OperatorType::Equal,
             TokenType::BangEq => OperatorType::NotEqual,
+            TokenType::Ampersand => OperatorType::And,
+            TokenType::Pipe => OperatorType::Or,
             _ => {
                 return Err(LangError::InvalidBinaryOperator {
                     span: tok.span(),
@@ -373,9 +643,37 @@ impl<'i> LangParser<'i> {
         return Ok(op);
     }
 
+    /// Parse the parenthesised, comma separated arguments of a call to `function`.
+    fn parse_call(&mut self, function: Identifier) -> Result<Expression, LangError> {
+        let mut arguments: Vec<Expression> = Vec::new();
+        let left_paren = self.consume(TokenType::LeftParen)?.span();
+        while *self.peek_one()?.token_type() != TokenType::RightParen {
+            if !arguments.is_empty() {
+                self.consume(TokenType::Comma)?;
+            }
+            if self.is_eof() {
+                break;
+            }
+            arguments.push(self.parse_expr()?);
+        }
+        let right_paren = self.consume_closing(TokenType::RightParen, left_paren)?;
+        Ok(Expression::Call {
+            span: Span::superspan(&function, right_paren),
+            function,
+            arguments,
+        })
+    }
+
     fn parse_array(&mut self) -> Result<Expression, LangError> {
         let mut elements: Vec<Box<Expression>> = Vec::new();
         let left_square = self.consume(TokenType::LeftSquare)?;
+        if *self.peek_one()?.token_type() != TokenType::RightSquare {
+            let first = self.parse_expr()?;
+            if *self.peek_one()?.token_type() == TokenType::Semicolon {
+                return self.parse_repeat(left_square, first);
+            }
+            elements.push(Box::new(first));
+        }
         while *self.peek_one()?.token_type() != TokenType::RightSquare {
             if *self.peek_one()?.token_type() == TokenType::Comma {
                 self.consume(TokenType::Comma)?;
@@ -389,6 +687,22 @@ impl<'i> LangParser<'i> {
         })
     }
 
+    /// Parse the rest of a repeated array, `[element; count]`, after its element.
+    fn parse_repeat(
+        &mut self,
+        left_square: Token,
+        element: Expression,
+    ) -> Result<Expression, LangError> {
+        self.consume(TokenType::Semicolon)?;
+        let count = self.parse_expr()?;
+        let right_square = self.consume_closing(TokenType::RightSquare, left_square.span())?;
+        Ok(Expression::Repeat {
+            element: Box::new(element),
+            count: Box::new(count),
+            span: Span::superspan(left_square, right_square),
+        })
+    }
+
     /// checks if their are no tokens remaining, or the current token is `EOF`.
     fn is_eof(&mut self) -> bool {
         match self.input().get(self.index) {
@@ -431,6 +745,26 @@ impl<'i> LangParser<'i> {
         }
     }
 
+    /// consume a closing delimiter, erroring with the span of the unclosed `opening` delimiter
+    /// if it is not found
+    fn consume_closing(&mut self, ty: TokenType, opening: Span) -> Result<Token, LangError> {
+        let tok = self.peek_one()?;
+        if *tok.token_type() != ty {
+            let (found, mut span) = (tok.ty(), tok.span());
+            // the end of the file has no width to label, so label the last token before it.
+            if found == TokenType::EOF && self.index > 0 {
+                span = self.src[self.index - 1].span();
+            }
+            return Err(LangError::UnmatchedDelimiter {
+                opening,
+                span,
+                expected: ty,
+                found,
+            });
+        }
+        self.consume(ty)
+    }
+
     fn peek_one(&mut self) -> Result<&Token, LangError> {
         Parser::peek_one(self).ok_or(LangError::UnexpectedEndOfFile {
             span: Span::new((self.index, self.index + 1)),
@@ -455,3 +789,381 @@ impl<'i> LangParser<'i> {
 
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::Lexer;
+
+    fn parse(src: &str) -> Result<Vec<Statement>, LangError> {
+        let tokens = Lexer::new(src).run().unwrap();
+        LangParser::new(&tokens).parse_statement(Vec::new())
+    }
+
+    #[test]
+    fn annotated_let() {
+        let ast = parse("let x: num := 5;\nlet b: bool := true;\nlet c: char := 'c';").unwrap();
+        let types: Vec<Option<Type>> = ast
+            .iter()
+            .map(|statement| match statement {
+                Statement::Let(r#let) => r#let.annotation.as_ref().map(|a| a.ty.clone()),
+                _ => panic!("expected a let statement"),
+            })
+            .collect();
+        assert_eq!(
+            vec![Some(Type::Num), Some(Type::Bool), Some(Type::Char)],
+            types
+        );
+    }
+
+    #[test]
+    fn unannotated_let() {
+        match parse("let x := 5;").unwrap().first() {
+            Some(Statement::Let(r#let)) => assert!(r#let.annotation.is_none()),
+            _ => panic!("expected a let statement"),
+        }
+    }
+
+    #[test]
+    fn empty_statements() {
+        assert_eq!(0, parse(";;").unwrap().len());
+        assert_eq!(1, parse("let x := 1;;").unwrap().len());
+        assert_eq!(2, parse(";let x := 1;; ;yield x;").unwrap().len());
+    }
+
+    #[test]
+    fn trailing_semicolon() {
+        assert_eq!(1, parse("yield 1;\n;").unwrap().len());
+        assert_eq!(1, parse("if true { yield 1; };").unwrap().len());
+    }
+
+    #[test]
+    fn statements_on_one_line() {
+        let ast = parse("let x := 1; let y := 2;").unwrap();
+        assert_eq!(2, ast.len());
+        assert!(matches!(&ast[0], Statement::Let(r#let) if r#let.variable == "x"));
+        assert!(matches!(&ast[1], Statement::Let(r#let) if r#let.variable == "y"));
+    }
+
+    #[test]
+    fn chained_assignment() {
+        let ast = parse("a := b := c := 0;\nlet x := y := a;").unwrap();
+        match &ast[0] {
+            Statement::Mutate(mutate) => {
+                assert_eq!("a", mutate.variable);
+                let chained: Vec<&str> = mutate.chained.iter().map(Identifier::name).collect();
+                assert_eq!(vec!["b", "c"], chained);
+            }
+            _ => panic!("expected a mutation"),
+        }
+        match &ast[1] {
+            Statement::Let(r#let) => {
+                assert_eq!(1, r#let.chained.len());
+                assert!(matches!(&r#let.value, Expression::Identifier(id) if id.name() == "a"));
+            }
+            _ => panic!("expected a let statement"),
+        }
+    }
+
+    #[test]
+    fn assignment_in_condition() {
+        match parse("if x := 3 { }") {
+            Err(LangError::AssignmentInCondition { span }) => {
+                assert_eq!(Span::new((5, 7)), span)
+            }
+            _ => panic!("expected an assignment in a condition"),
+        }
+        assert!(matches!(
+            parse("while x := 3 { }"),
+            Err(LangError::AssignmentInCondition { .. })
+        ));
+    }
+
+    #[test]
+    fn boolean_statements() {
+        let statements = parse("true;\nlet b := false;\n!b;").unwrap();
+        assert!(matches!(
+            statements[0],
+            Statement::Expr(Expression::Literal {
+                lit: Literal::Boolean { val: true, .. }
+            })
+        ));
+        match &statements[1] {
+            Statement::Let(r#let) => assert!(matches!(
+                r#let.value,
+                Expression::Literal {
+                    lit: Literal::Boolean { val: false, .. }
+                }
+            )),
+            _ => panic!("expected a let statement"),
+        }
+        assert!(matches!(
+            statements[2],
+            Statement::Expr(Expression::App {
+                app: Application::Unary {
+                    op: OperatorType::Not,
+                    ..
+                }
+            })
+        ));
+    }
+
+    #[test]
+    fn unary_plus() {
+        let statements = parse("yield +5;\nyield 1 + +2;").unwrap();
+        assert!(matches!(
+            &statements[0],
+            Statement::Yield(
+                Expression::App {
+                    app: Application::Unary {
+                        op: OperatorType::Plus,
+                        ..
+                    }
+                },
+                _
+            )
+        ));
+        match &statements[1] {
+            Statement::Yield(
+                Expression::App {
+                    app:
+                        Application::Binary {
+                            op: OperatorType::Plus,
+                            right,
+                            ..
+                        },
+                },
+                _,
+            ) => assert!(matches!(
+                **right,
+                Expression::App {
+                    app: Application::Unary {
+                        op: OperatorType::Plus,
+                        ..
+                    }
+                }
+            )),
+            _ => panic!("expected a binary plus"),
+        }
+    }
+
+    #[test]
+    fn bang_is_not_binary() {
+        match parse("yield 1 ! 2;") {
+            Err(LangError::InvalidBinaryOperator { span, op }) => {
+                assert_eq!(Span::new((8, 9)), span);
+                assert_eq!(TokenType::Bang, op);
+            }
+            _ => panic!("expected an invalid binary operator"),
+        }
+    }
+
+    #[test]
+    fn for_loop() {
+        match parse("for x in xs { yield x; }").unwrap().first() {
+            Some(Statement::For(r#for)) => {
+                assert_eq!("x", r#for.item.name());
+                assert!(matches!(&r#for.iterable, Iterable::Array(xs) if xs.name() == "xs"));
+                assert_eq!(1, r#for.body.len());
+                assert_eq!(Span::new((0, 24)), r#for.span());
+            }
+            _ => panic!("expected a for loop"),
+        }
+        assert!(matches!(
+            parse("for x in [1, 2] { }"),
+            Err(LangError::ExpectedVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn if_else() {
+        let src = "if x { yield 1; } else { yield 2; yield 3; }";
+        match parse(src).unwrap().first() {
+            Some(Statement::If(r#if)) => {
+                assert_eq!(1, r#if.iff.len());
+                assert_eq!(Some(2), r#if.otherwise.as_ref().map(Vec::len));
+                assert_eq!(Span::new((0, src.len())), r#if.span());
+            }
+            _ => panic!("expected an if"),
+        }
+        match parse("if x { } else if y { } else { yield 1; }")
+            .unwrap()
+            .first()
+        {
+            Some(Statement::If(r#if)) => match r#if.otherwise.as_deref() {
+                Some([Statement::If(inner)]) => assert!(inner.otherwise.is_some()),
+                _ => panic!("expected an else if"),
+            },
+            _ => panic!("expected an if"),
+        }
+        assert!(parse("if x { } else yield 1;").is_err());
+    }
+
+    #[test]
+    fn range_loop() {
+        match parse("for i in 0..n + 1 { }").unwrap().first() {
+            Some(Statement::For(r#for)) => match &r#for.iterable {
+                Iterable::Range { start, end, span } => {
+                    assert!(matches!(start, Expression::Literal { .. }));
+                    assert!(matches!(end, Expression::App { .. }));
+                    assert_eq!(Span::new((9, 17)), *span);
+                }
+                _ => panic!("expected a range"),
+            },
+            _ => panic!("expected a for loop"),
+        }
+        assert!(parse("for i in 0.. { }").is_err());
+    }
+
+    #[test]
+    fn counter_loop() {
+        match parse("for i := 0; i < 10; i := i + 1 { yield i; }")
+            .unwrap()
+            .first()
+        {
+            Some(Statement::For(r#for)) => {
+                assert_eq!("i", r#for.item.name());
+                match &r#for.iterable {
+                    Iterable::Counter { step, span, .. } => {
+                        assert_eq!("i", step.variable);
+                        assert_eq!(Span::new((6, 30)), *span);
+                    }
+                    _ => panic!("expected a counter"),
+                }
+                assert_eq!(1, r#for.body.len());
+            }
+            _ => panic!("expected a for loop"),
+        }
+        assert!(parse("for i := 0; i < 10; i := i + 1; { }").is_err());
+        assert!(parse("for i := 0; i < 10; 1 := 2 { }").is_err());
+    }
+
+    #[test]
+    fn data_tables() {
+        match parse("data greeting := \"hi\";").unwrap().first() {
+            Some(Statement::Data(data)) => {
+                assert_eq!("greeting", data.name.name());
+                assert!(matches!(
+                    &data.table,
+                    Expression::Array { elements, .. } if elements.len() == 2
+                ));
+            }
+            _ => panic!("expected a data table"),
+        }
+        assert!(parse("data primes := [2, 3, 5, 7];").is_ok());
+        assert!(matches!(
+            parse("if true {\ndata xs := [1];\n}"),
+            Err(LangError::NestedData { .. })
+        ));
+        assert!(parse("data x := 5;").is_err());
+    }
+
+    #[test]
+    fn yield_stream() {
+        let ast = parse("yield@err 1;\nyield 2;\nyield@out 3;").unwrap();
+        let streams: Vec<Stream> = ast
+            .iter()
+            .map(|statement| match statement {
+                Statement::Yield(_, stream) => *stream,
+                _ => panic!("expected a yield"),
+            })
+            .collect();
+        assert_eq!(vec![Stream::Err, Stream::Out, Stream::Out], streams);
+        match parse("yield@log 1;") {
+            Err(LangError::UnknownStream { span, name }) => {
+                assert_eq!("log", name);
+                assert_eq!(Span::new((6, 9)), span);
+            }
+            _ => panic!("expected an unknown stream"),
+        }
+    }
+
+    #[test]
+    fn unclosed_brace() {
+        match parse("if x < 3 { yield 1;") {
+            Err(LangError::UnmatchedDelimiter {
+                opening,
+                span,
+                expected,
+                found,
+            }) => {
+                assert_eq!(Span::new((9, 10)), opening);
+                assert_eq!(Span::new((18, 19)), span);
+                assert_eq!(TokenType::RightBrace, expected);
+                assert_eq!(TokenType::EOF, found);
+            }
+            _ => panic!("expected an unmatched delimiter"),
+        }
+    }
+
+    #[test]
+    fn unclosed_paren() {
+        match parse("yield (1 + 2;") {
+            Err(LangError::UnmatchedDelimiter {
+                opening, expected, ..
+            }) => {
+                assert_eq!(Span::new((6, 7)), opening);
+                assert_eq!(TokenType::RightParen, expected);
+            }
+            _ => panic!("expected an unmatched delimiter"),
+        }
+    }
+
+    #[test]
+    fn call() {
+        match parse("swap(a, b);").unwrap().first() {
+            Some(Statement::Expr(Expression::Call {
+                function,
+                arguments,
+                span,
+            })) => {
+                assert_eq!("swap", function.name());
+                assert_eq!(2, arguments.len());
+                assert_eq!(Span::new((0, 10)), *span);
+            }
+            _ => panic!("expected a call"),
+        }
+        assert!(matches!(
+            parse("swap(a b);"),
+            Err(LangError::ExpectedToken { .. })
+        ));
+    }
+
+    #[test]
+    fn repeated_array() {
+        match parse("let a := [7; 4];").unwrap().first() {
+            Some(Statement::Let(Let {
+                value:
+                    Expression::Repeat {
+                        element,
+                        count,
+                        span,
+                    },
+                ..
+            })) => {
+                assert!(matches!(
+                    **element,
+                    Expression::Literal {
+                        lit: Literal::Number { val: 7, .. }
+                    }
+                ));
+                assert!(matches!(
+                    **count,
+                    Expression::Literal {
+                        lit: Literal::Number { val: 4, .. }
+                    }
+                ));
+                assert_eq!(Span::new((9, 15)), *span);
+            }
+            _ => panic!("expected a repeated array"),
+        }
+    }
+
+    #[test]
+    fn unknown_type_annotation() {
+        assert!(matches!(
+            parse("let x: string := 5;"),
+            Err(LangError::UnknownType { .. })
+        ));
+    }
+}
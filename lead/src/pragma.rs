@@ -0,0 +1,74 @@
+use crate::{error::LangWarning, lex::span::Span};
+
+/// A directive configuring how a program is run, written on its own line as `#name value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pragma {
+    /// `#memory <bytes>` sets the memory size of the virtual machine.
+    Memory(usize),
+}
+
+/// Find the pragmas in a program. A shebang on the first line is skipped, and unknown or malformed
+/// pragmas are returned as warnings.
+pub fn pragmas(src: &str) -> (Vec<Pragma>, Vec<LangWarning>) {
+    let mut pragmas: Vec<Pragma> = Vec::new();
+    let mut warnings: Vec<LangWarning> = Vec::new();
+    let mut offset: usize = 0;
+
+    for (number, line) in src.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let Some(directive) = line.trim_end().strip_prefix('#') else {
+            continue;
+        };
+        if number == 0 && directive.starts_with('!') {
+            continue;
+        }
+
+        let span = Span::new((start, start + line.trim_end().len()));
+        let mut words = directive.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("memory"), Some(bytes), None) => match bytes.parse::<usize>() {
+                Ok(bytes) => pragmas.push(Pragma::Memory(bytes)),
+                Err(_) => warnings.push(LangWarning::MalformedPragma {
+                    span,
+                    expected: "#memory <bytes>".to_owned(),
+                }),
+            },
+            (Some("memory"), _, _) => warnings.push(LangWarning::MalformedPragma {
+                span,
+                expected: "#memory <bytes>".to_owned(),
+            }),
+            (name, _, _) => warnings.push(LangWarning::UnknownPragma {
+                span,
+                name: name.unwrap_or_default().to_owned(),
+            }),
+        }
+    }
+    (pragmas, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebang_and_memory() {
+        let (pragmas, warnings) = pragmas("#!/usr/bin/env leadc\n#memory 1024\nyield 1;");
+        assert_eq!(vec![Pragma::Memory(1024)], pragmas);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn unknown_and_malformed() {
+        let (pragmas, warnings) = pragmas("yield 1;\n#!shebang\n#memory lots\n#stack 8");
+        assert!(pragmas.is_empty());
+        assert!(matches!(
+            warnings.as_slice(),
+            [
+                LangWarning::UnknownPragma { .. },
+                LangWarning::MalformedPragma { .. },
+                LangWarning::UnknownPragma { span, .. },
+            ] if *span == Span::new((32, 40))
+        ));
+    }
+}
@@ -1,5 +1,9 @@
 use assert_cmd::prelude::*;
-use assert_fs::fixture::FileWriteStr;
+use assert_fs::fixture::{FileWriteStr, PathChild};
+use lead::{
+    lex::span::Spans,
+    parse::ast::{AstDocument, Statement, AST_SCHEMA_VERSION},
+};
 use predicates::prelude::*;
 use std::process::Command;
 
@@ -8,8 +12,1263 @@ fn array_index() -> Result<(), Box<dyn std::error::Error>> {
     let file = assert_fs::NamedTempFile::new("main.ed")?;
     file.write_str("let x := [1, 2, 3, 4, 32 + 12];\nlet y := x[2];\nyield y;")?;
 
-    let mut cmd = Command::cargo_bin("lead")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
     cmd.arg("run").arg(file.path());
     cmd.assert().success().stdout(predicate::str::contains("3"));
     Ok(())
 }
+
+#[test]
+fn compound_array_elements() -> Result<(), Box<dyn std::error::Error>> {
+    // each element is stored from the register holding its result, however it was computed.
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let xs := [5, 6, 7];\nlet i := 1;\nlet ys := [xs[i] + 1, div_floor(0 - 7, 2), xs[xs[0] - 3]];\nyield ys[0];\nyield ys[1];\nyield ys[2];",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("7\n4294967292\n7\n");
+    Ok(())
+}
+
+#[test]
+fn source_comments() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 42;\nyield x;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("build").arg("--source-comments").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("CONST %0, =0x2a ; 42\n"))
+        .stdout(predicate::str::contains("YLD %0 ; x\n"));
+    Ok(())
+}
+
+#[test]
+fn run_directory() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("a.ed").write_str("yield 1;")?;
+    dir.child("b.ed").write_str("yield 2;")?;
+    dir.child("notes.txt").write_str("not a program")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(dir.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("a.ed <==\n1\n"))
+        .stdout(predicate::str::contains("b.ed <==\n2\n"))
+        .stdout(predicate::str::ends_with("2 succeeded, 0 failed\n"));
+
+    dir.child("c.ed").write_str("yield x;")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(dir.path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::ends_with("2 succeeded, 1 failed\n"));
+    Ok(())
+}
+
+#[test]
+fn check_bounds() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let a := [1, 2, 3];\nyield a[arg(0)];")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg("--check-bounds")
+        .arg(file.path())
+        .args(["--", "2"]);
+    cmd.assert().success().stdout("3\n");
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg("--check-bounds")
+        .arg(file.path())
+        .args(["--", "1073741825"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("trapped"));
+    Ok(())
+}
+
+#[test]
+fn check_bounds_one_past_the_end() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let a := [1, 2, 3];\nlet b := [7];\nyield a[arg(0)];")?;
+
+    // unchecked, the index reads the next array.
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path()).args(["--", "3"]);
+    cmd.assert().success().stdout("7\n");
+
+    for index in ["3", "-1"] {
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run")
+            .arg("--check-bounds")
+            .arg(file.path())
+            .args(["--", index]);
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("trapped"));
+    }
+    Ok(())
+}
+
+#[test]
+fn program_arguments() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield arg(0) + arg(1);")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path()).args(["--", "3", "5"]);
+    cmd.assert().success().stdout("8\n");
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path()).args(["--", "3"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("program argument 1 was read"));
+    Ok(())
+}
+
+#[test]
+fn cycles() -> Result<(), Box<dyn std::error::Error>> {
+    // each reading is one `CYC`, and is yielded by the instruction after it.
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield cycles();\nyield cycles();")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("0\n2\n");
+    Ok(())
+}
+
+#[test]
+fn combined_conditions() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let x := 5;\nif (x > 0) & (x < 10) { yield 1; }\nif (x > 0) & (x < 3) { yield 2; }\nif (x < 0) | (x == 5) { yield 3; }\nif (x < 0) | (x > 9) { yield 4; }",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("1\n3\n");
+    Ok(())
+}
+
+#[test]
+fn array_equality() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let xs := [1, 2, 3];\nlet ys := [1, 2, 3];\nlet zs := [1, 5, 3];\nif xs == ys { yield 1; }\nif xs == zs { yield 2; }\nif xs != zs { yield 3; }\nif xs != ys { yield 4; }",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("1\n3\n");
+    Ok(())
+}
+
+#[test]
+fn array_length_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let xs := [1, 2];\nlet ys := [1, 2, 3];\nif xs == ys { yield 1; }")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("E0026"));
+    Ok(())
+}
+
+#[test]
+fn no_output_note() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 5;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("program produced no output"));
+    Ok(())
+}
+
+#[test]
+fn no_output_note_omitted() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 5;\nyield x;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("program produced no output").not());
+
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 5;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path()).arg("-q");
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("program produced no output").not());
+    Ok(())
+}
+
+#[test]
+fn negative_array_index() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let a := [1, 2, 3];\nlet b := [4, 5];\nyield a[-1];\nyield b[-2];")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("3\n4\n"));
+    Ok(())
+}
+
+#[test]
+fn lex_spans() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 1;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("lex").arg("--spans").arg(file.path());
+    cmd.assert().success().stdout(predicate::str::diff(
+        "Let@(0,3)\nIdentifier(\"x\")@(4,5)\nAssign@(6,8)\nNumber(1)@(9,10)\nSemicolon@(10,11)\nEOF@(11,11)\n",
+    ));
+    Ok(())
+}
+
+#[test]
+fn deterministic_output() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let i := 0;\nwhile i < 3 {\n\tyield i;\n\ti := i + 1;\n}")?;
+
+    let output = |subcommand: &[&str]| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("--deterministic").args(subcommand).arg(file.path());
+        Ok(cmd.assert().success().get_output().stdout.clone())
+    };
+
+    let air = output(&["build"])?;
+    assert_eq!(air, output(&["build"])?);
+    assert!(String::from_utf8(air)?.contains("L0-check-condition:"));
+
+    let tokens = output(&["lex", "--spans"])?;
+    assert_eq!(tokens, output(&["lex", "--spans"])?);
+    assert!(String::from_utf8(tokens)?.starts_with("Let@(0,3)#"));
+    Ok(())
+}
+
+#[test]
+fn parse_spans() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 1;\nwhile x < 3 {\n\tx := x + 1;\n}")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("parse").arg("--spans").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with("(0, 10) Let("))
+        .stdout(predicate::str::contains("\n(12, 40) while "))
+        .stdout(predicate::str::contains("\n\t(27, 37) Mutate("));
+    Ok(())
+}
+
+#[test]
+fn parse_json() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 1;\nwhile x < 3 {\n\tx := x + 1;\n}\nyield x;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("parse")
+        .arg("--format")
+        .arg("json")
+        .arg(file.path());
+    let output = cmd.assert().success().get_output().stdout.clone();
+
+    let document: AstDocument = serde_json::from_slice(&output)?;
+    assert_eq!(AST_SCHEMA_VERSION, document.version);
+    let [Statement::Let(_), Statement::While(r#while), Statement::Yield(_, _)] =
+        &document.statements[..]
+    else {
+        panic!("expected a let, a while and a yield");
+    };
+    assert!(matches!(r#while.body[..], [Statement::Mutate(_)]));
+    let spans: Vec<(usize, usize)> = document
+        .statements
+        .iter()
+        .map(|statement| statement.span().span())
+        .collect();
+    assert_eq!(vec![(0, 10), (12, 40), (47, 48)], spans);
+
+    // exporting the tree read back from JSON gives the same JSON.
+    assert_eq!(
+        String::from_utf8(output)?.trim_end(),
+        serde_json::to_string(&document)?
+    );
+    Ok(())
+}
+
+#[test]
+fn swap() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let a := 1;\nlet b := 2;\nswap(a, b);\nyield a;\nyield b;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("2\n1\n"));
+    Ok(())
+}
+
+#[test]
+fn chained_assignment() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let a := 1;\nlet b := 2;\nlet c := a := b := 7;\na := b := 3 + 4 * c;\nyield a;\nyield b;\nyield c;",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("31\n31\n7\n"));
+    Ok(())
+}
+
+#[test]
+fn for_loop_sums_array() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let xs := [3, 5, 7, 11];\nlet total := 0;\nfor x in xs {\ntotal := total + x;\n}\nyield total;",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout(predicate::str::diff("26\n"));
+    Ok(())
+}
+
+#[test]
+fn range_loops() -> Result<(), Box<dyn std::error::Error>> {
+    for (src, expected) in [
+        (
+            "let total := 0;\nfor i in 0..5 {\ntotal := total + i;\n}\nyield total;",
+            "10\n",
+        ),
+        ("for i in 5..5 {\nyield i;\n}\nyield 0;", "0\n"),
+        ("for i in 3..1 {\nyield i;\n}\nyield 0;", "0\n"),
+        ("for i in -2..1 {\nyield i + 2;\n}", "0\n1\n2\n"),
+        // changing the item does not change how many times the loop runs.
+        ("for i in 0..3 {\ni := i + 10;\nyield i;\n}", "10\n11\n12\n"),
+        ("for c in 'a'..'d' {\nyield c;\n}", "97\n98\n99\n"),
+        ("let s := 1;\nfor i in s..3 {\nyield i;\n}", "1\n2\n"),
+        ("let s := 1;\nfor i in (s)..3 {\nyield i;\n}", "1\n2\n"),
+        // the end is read once, so assigning to its variable does not change it.
+        (
+            "let e := 2;\nfor i in 0..e {\ne := 5;\nyield i;\n}",
+            "0\n1\n",
+        ),
+    ] {
+        let file = assert_fs::NamedTempFile::new("main.ed")?;
+        file.write_str(src)?;
+
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run").arg(file.path());
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::diff(expected));
+    }
+    Ok(())
+}
+
+#[test]
+fn counter_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let example = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/count.ed");
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(example);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("0\n2\n4\n6\n8\n20\n"));
+
+    // the counter is only in scope within the loop, and `break` leaves it early.
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let i := 7;\nfor i := 0; i < 10; i := i + 1 {\nif i == 3 {\nbreak;\n}\nyield i;\n}\nyield i;")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("0\n1\n2\n7\n"));
+
+    // the counter can start from a variable.
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let s := 1;\nfor i := s; i < 3; i := i + 1 {\nyield i;\n}\nyield s;")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("1\n2\n1\n"));
+    Ok(())
+}
+
+#[test]
+fn nested_for_loops_break() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let xs := [1, 2, 3];\nfor x in xs {\nfor y in xs {\nif y > x {\nbreak;\n}\nyield y;\n}\n}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("1\n1\n2\n1\n2\n3\n"));
+    Ok(())
+}
+
+#[test]
+fn unary_plus() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 4;\nyield +5;\nyield 1 + +2;\nyield x + +x;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("5\n3\n8\n");
+    Ok(())
+}
+
+#[test]
+fn signed_comparison() -> Result<(), Box<dyn std::error::Error>> {
+    // compared as unsigned, -1 would be the largest number, and the loop would not run.
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("while (-1) < 1 {\nyield 1;\nbreak;\n}\nyield 2;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("1\n2\n"));
+    Ok(())
+}
+
+#[test]
+fn build_renders_air() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "let x := 1;\nyield x + 2;";
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(src)?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("build").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff(lead::air::render_air(
+            &lead::try_compile(src)?,
+        )));
+    Ok(())
+}
+
+#[test]
+fn binary_air_runs_like_source() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    let src = dir.child("main.ed");
+    src.write_str(
+        "let xs := [3, 1, 2];\nlet total := 0;\nfor x in xs {\nif x > 1 {\ntotal := total + x;\n}\nyield x;\n}\nyield total;\nyield (-7) / 2;",
+    )?;
+    let out = dir.child("out.lab");
+
+    Command::cargo_bin("leadc")?
+        .arg("build")
+        .arg("--emit=bin")
+        .arg("-o")
+        .arg(out.path())
+        .arg(src.path())
+        .assert()
+        .success()
+        .stdout("");
+    assert!(lead::air::binary::is_binary(&std::fs::read(out.path())?));
+
+    let expected = Command::cargo_bin("leadc")?
+        .arg("run")
+        .arg(src.path())
+        .output()?;
+    assert!(expected.status.success());
+    Command::cargo_bin("leadc")?
+        .arg("run")
+        .arg(out.path())
+        .assert()
+        .success()
+        .stdout(String::from_utf8(expected.stdout)?);
+
+    // binary output needs somewhere to go other than the terminal.
+    Command::cargo_bin("leadc")?
+        .arg("build")
+        .arg("--emit=bin")
+        .arg(src.path())
+        .assert()
+        .failure();
+    Ok(())
+}
+
+#[test]
+fn control_flow_graph() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let i := 0;\nwhile i < 10 {\nif i == 5 {\nbreak;\n}\ni := i + 1;\n}\nyield i;",
+    )?;
+    let output = Command::cargo_bin("leadc")?
+        .arg("build")
+        .arg("--emit=cfg")
+        .arg(file.path())
+        .output()?;
+    assert!(output.status.success());
+    let dot = String::from_utf8(output.stdout)?;
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.trim_end().ends_with('}'));
+    assert!(dot.contains("YLD %0\\l\"];"));
+    assert!(dot.contains("[label=\"<\"]"));
+    assert!(dot.contains("[label=\">=\"]"));
+
+    // the loop shows up as an edge from a block back to an earlier one.
+    let back_edge = dot.lines().any(|line| {
+        let Some((from, to)) = line.trim().trim_end_matches(';').split_once(" -> b") else {
+            return false;
+        };
+        let from: usize = from.trim_start_matches('b').parse().unwrap();
+        let to: usize = to.split(' ').next().unwrap().parse().unwrap();
+        to <= from
+    });
+    assert!(back_edge, "no back-edge in\n{dot}");
+    Ok(())
+}
+
+#[test]
+fn data_tables() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "data squares := [0, 1, 4, 9, 16];\ndata word := \"lead\";\nlet i := 3;\nyield squares[i];\nyield squares[1] + squares[4];\nfor c in word {\nyield c;\n}",
+    )?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("9\n17\n108\n101\n97\n100\n"));
+
+    // the table is in memory before the first instruction runs.
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("build").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("STR").not());
+    Ok(())
+}
+
+#[test]
+fn run_hand_written_air() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.air")?;
+    file.write_str("CONST %0, =0x5\nCONST %1, =3\nADD %2, %0, %1\nYLD %2\n")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("8\n");
+
+    file.write_str("CONST %0, =0x5\nADD %2, %0\n")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("`ADD` takes 3 registers, found 2"));
+    Ok(())
+}
+
+#[test]
+fn check_compiles_without_running() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x: num := 1;\nyield x + 2;")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("check").arg(file.path());
+    cmd.assert().success().stdout("").stderr("");
+
+    file.write_str("let x: bool := 1;\nyield x;")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("check").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("E0024"));
+    Ok(())
+}
+
+#[test]
+fn check_scopes_if_branches() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 0;\nif x > 0 { let y := 1; yield y; } else { yield y; }")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("check").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("uninitialised variable `y`"));
+
+    file.write_str(
+        "let x := 0;\nif x > 0 { let y := 1; x := y; } else { let y := 2; x := y; }\nyield x;",
+    )?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("2\n");
+    Ok(())
+}
+
+#[test]
+fn check_labels_unclosed_brace_at_end_of_file() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 1;\nif x > 0 {\nyield x;\n")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("check").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unclosed delimiter"))
+        .stderr(predicate::str::contains("expected `}`"));
+    Ok(())
+}
+
+#[test]
+fn build_annotates_registers() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let a := 1;\nlet total := a + 2;\nyield total;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("build").arg("--annotate-regs").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("CONST %0/*a*/, =0x1"))
+        .stdout(predicate::str::contains("YLD %2/*total*/"));
+    Ok(())
+}
+
+#[test]
+fn join_yields() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield 1;\nyield@err 9;\nyield 2;\nyield 3;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg("--join").arg(",").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("1,2,3\n"))
+        .stderr(predicate::str::diff("9\n"));
+    Ok(())
+}
+
+#[test]
+fn boolean_values() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("true;\nlet t := true;\nlet f := false;\nyield t;\nyield f;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("1\n0\n"));
+    Ok(())
+}
+
+#[test]
+fn explain_error_code() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield x;")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("E0010"));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("explain").arg("E0010");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("declared with `let`"));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("explain").arg("R0003");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("divided by zero"));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("explain").arg("E9999");
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn version_lists_instructions() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("version");
+    cmd.assert()
+        .success()
+        .stdout(format!("leadc {}\n", env!("CARGO_PKG_VERSION")));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("version").arg("--instructions");
+    let mut assert = cmd.assert().success();
+    for name in ["ADD ", "YLD ", "STR ", "BRA "] {
+        assert = assert.stdout(predicate::str::contains(format!("\n{name}")));
+    }
+    Ok(())
+}
+
+#[test]
+fn if_else() -> Result<(), Box<dyn std::error::Error>> {
+    for (src, expected) in [
+        ("let a := 1;\nif a < 2 {\nyield 1;\n} else {\nyield 2;\n}\nyield 3;", "1\n3\n"),
+        ("let a := 5;\nif a < 2 {\nyield 1;\n} else {\nyield 2;\n}\nyield 3;", "2\n3\n"),
+        // a condition without a comparison is checked against zero.
+        ("let b := false;\nif b {\nyield 1;\n} else {\nyield 2;\n}", "2\n"),
+        ("if true {\nyield 1;\n} else {\nyield 2;\n}", "1\n"),
+        (
+            "let a := 2;\nif a == 1 {\nyield 1;\n} else if a == 2 {\nyield 2;\n} else {\nyield 3;\n}",
+            "2\n",
+        ),
+    ] {
+        let file = assert_fs::NamedTempFile::new("main.ed")?;
+        file.write_str(src)?;
+
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run").arg(file.path());
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::diff(expected));
+    }
+    Ok(())
+}
+
+#[test]
+fn condition_kinds() -> Result<(), Box<dyn std::error::Error>> {
+    for (src, expected) in [
+        ("let a := 1;\nif a < 2 {\nyield 1;\n}\nyield 2;", "1\n2\n"),
+        ("let a := 3;\nif a < 2 {\nyield 1;\n}\nyield 2;", "2\n"),
+        ("let b := true;\nif b {\nyield 1;\n}\nyield 2;", "1\n2\n"),
+        ("let b := false;\nif b {\nyield 1;\n}\nyield 2;", "2\n"),
+        ("if false {\nyield 1;\n}\nyield 2;", "2\n"),
+        (
+            "let b := true;\nwhile b {\nyield 1;\nb := false;\n}\nyield 2;",
+            "1\n2\n",
+        ),
+        // the loop's condition must not see the flags left by the `if`.
+        (
+            "let a := 3;\nif a > 2 {\nyield 1;\n}\nlet i := 0;\nwhile i < 2 {\nyield i;\ni := i + 1;\n}",
+            "1\n0\n1\n",
+        ),
+    ] {
+        let file = assert_fs::NamedTempFile::new("main.ed")?;
+        file.write_str(src)?;
+
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run").arg(file.path());
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::diff(expected));
+    }
+    Ok(())
+}
+
+#[test]
+fn yield_to_stderr() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield@err 1;\nyield 2;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("2\n"))
+        .stderr(predicate::str::contains("1\n"));
+    Ok(())
+}
+
+#[test]
+fn unused_variable_warning() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 1;\nlet y := 2;\nyield y;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("2\n"))
+        .stderr(predicate::str::contains("unused variable `x`"))
+        .stderr(predicate::str::contains("unused variable `y`").not());
+    Ok(())
+}
+
+#[test]
+fn repeated_array() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let a := [7; 4];\nlet b := [1];\nyield a[3];\nyield b[0];")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("7\n1\n"));
+    Ok(())
+}
+
+#[test]
+fn return_stops_the_program() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let x := 0;\nlet one := 1;\nwhile x < 10 {\n\tyield x;\n\tx := x + one;\n\treturn x;\n}\nyield 99;",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("0\n1\n"));
+    Ok(())
+}
+
+#[test]
+fn break_exits_the_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let x := 0;\nlet one := 1;\nwhile x < 10 {\n\tyield x;\n\tx := x + one;\n\tbreak;\n}\nyield 99;",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("0\n99\n"));
+    Ok(())
+}
+
+#[test]
+fn cached_air_is_reused() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield 7;")?;
+    let cache = assert_fs::TempDir::new()?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg(file.path())
+        .arg("--cache")
+        .arg("--cache-dir")
+        .arg(cache.path());
+    cmd.assert().success().stdout(predicate::str::diff("7\n"));
+
+    // replace the cached program, so a second run shows whether the cache was used.
+    let cached: Vec<_> = std::fs::read_dir(cache.path())?.collect::<Result<_, _>>()?;
+    assert_eq!(1, cached.len());
+    let json = std::fs::read_to_string(cached[0].path())?;
+    assert!(json.contains(r#"{"CON":[0,7]}"#));
+    std::fs::write(
+        cached[0].path(),
+        json.replace(r#"{"CON":[0,7]}"#, r#"{"CON":[0,42]}"#),
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg(file.path())
+        .arg("--cache")
+        .arg("--cache-dir")
+        .arg(cache.path());
+    cmd.assert().success().stdout(predicate::str::diff("42\n"));
+
+    // changing the source misses the cache.
+    file.write_str("yield 8;")?;
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg(file.path())
+        .arg("--cache")
+        .arg("--cache-dir")
+        .arg(cache.path());
+    cmd.assert().success().stdout(predicate::str::diff("8\n"));
+    Ok(())
+}
+
+#[test]
+fn cached_module_keeps_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let unused := 1;\nlet x := 2;\nyield x / 0;")?;
+    let cache = assert_fs::TempDir::new()?;
+
+    // the second run loads the module from the cache, and reports the same as the first.
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run")
+            .arg(file.path())
+            .arg("--dump-state")
+            .arg("--cache")
+            .arg("--cache-dir")
+            .arg(cache.path());
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("unused variable `unused`"))
+            .stderr(predicate::str::contains("(variable `x`) = 2"))
+            .stderr(predicate::str::contains("[3:7]"));
+    }
+    Ok(())
+}
+
+#[test]
+fn unwritable_cache_warns() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield 7;")?;
+    // a file where the cache folder should be cannot be written into.
+    let blocker = assert_fs::NamedTempFile::new("cache")?;
+    blocker.write_str("")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg(file.path())
+        .arg("--cache")
+        .arg("--cache-dir")
+        .arg(blocker.path());
+    cmd.assert()
+        .success()
+        .stdout("7\n")
+        .stderr(predicate::str::contains(
+            "warning: Error writing to the cache",
+        ));
+    Ok(())
+}
+
+#[test]
+fn div_floor() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let a := 7;\nyield div_floor(a, 2);\nyield a / 2;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("3\n3\n"));
+    Ok(())
+}
+
+#[test]
+fn yields_print_before_runtime_error() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let zero := 0;\nyield 1;\nyield 2;\nyield 3 / zero;\nyield 4;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::diff("1\n2\n"))
+        .stderr(predicate::str::contains("division by zero"));
+    Ok(())
+}
+
+#[test]
+fn overflow_mode() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 2147483647;\nyield x * 4;")?;
+
+    for (mode, output) in [("wrapping", "4294967292\n"), ("saturating", "4294967295\n")] {
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run").arg("--overflow").arg(mode).arg(file.path());
+        cmd.assert().success().stdout(predicate::str::diff(output));
+    }
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg("--overflow=trapping").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("arithmetic overflowed"));
+    Ok(())
+}
+
+#[test]
+fn constants_overflow_like_variables() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield 2 + 3;\nyield 0 - 1;")?;
+
+    for (mode, output) in [("wrapping", "5\n4294967295\n"), ("saturating", "5\n0\n")] {
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run").arg("--overflow").arg(mode).arg(file.path());
+        cmd.assert().success().stdout(predicate::str::diff(output));
+    }
+    for src in ["yield 2 + 3;\nyield 0 - 1;", "yield 2 + 3;\nyield -7;"] {
+        file.write_str(src)?;
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run").arg("--overflow=trapping").arg(file.path());
+        cmd.assert()
+            .failure()
+            .stdout(predicate::str::diff("5\n"))
+            .stderr(predicate::str::contains("arithmetic overflowed"));
+    }
+    Ok(())
+}
+
+#[test]
+fn constant_division_by_zero() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 5 / 0;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("runtime error: division by zero"))
+        .stderr(predicate::str::contains("panicked").not());
+    Ok(())
+}
+
+#[test]
+fn timeout_stops_infinite_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("yield 1;\nwhile true { }")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg("--timeout").arg("100").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::diff("1\n"))
+        .stderr(predicate::str::contains("time limit of 100ms"));
+    Ok(())
+}
+
+#[test]
+fn dump_state_names_registers() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let total := 3;\ntotal := total * 4;\nyield total;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg("--dump-state").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("12\n"))
+        .stderr(predicate::str::contains("(variable `total`) = 12"));
+    Ok(())
+}
+
+#[test]
+fn log_level_follows_verbosity() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 5;\nyield x;")?;
+    let quiet_log = assert_fs::NamedTempFile::new("quiet.log")?;
+    let verbose_log = assert_fs::NamedTempFile::new("verbose.log")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg(file.path())
+        .arg("-q")
+        .arg("--log-path")
+        .arg(quiet_log.path());
+    cmd.assert().success();
+    assert!(!std::fs::read_to_string(quiet_log.path())?.contains("DEBUG"));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg(file.path())
+        .arg("-v")
+        .arg("--log-path")
+        .arg(verbose_log.path());
+    cmd.assert().success();
+    assert!(std::fs::read_to_string(verbose_log.path())?.contains("DEBUG"));
+    Ok(())
+}
+
+#[test]
+fn memory_pragma() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    // the index is past the end of the array, but within the memory set by the pragma.
+    file.write_str(
+        "#!/usr/bin/env leadc\n#memory 1024\nlet a := [3; 100];\nlet i := 200;\nyield a[99];\nyield a[i];",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("3\n0\n"));
+
+    // the command line takes precedence over the pragma. memory still fits the array.
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path()).arg("-m").arg("256");
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn auto_memory_size() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let xs := [7; 2000];\nlet i := 1999;\nyield xs[i];")?;
+    for args in [&["-m", "auto"][..], &["-m", "0", "--memory-slack", "0"]] {
+        let mut cmd = Command::cargo_bin("leadc")?;
+        cmd.arg("run").args(args).arg(file.path());
+        cmd.assert().success().stdout("7\n");
+    }
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg("-m").arg("lots").arg(file.path());
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn unknown_pragma_warning() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("#stack 64\nyield 1;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::diff("1\n"))
+        .stderr(predicate::str::contains("unknown pragma `stack`"));
+    Ok(())
+}
+
+#[test]
+fn eval_expression() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("eval").arg("2 + 3 * 4");
+    cmd.assert().success().stdout("14\n");
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("eval").arg("2 +");
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("expected `expression`"));
+
+    // only an expression is evaluated, so no statements can follow it.
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("eval").arg("1); yield (2");
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "expected the end of the expression",
+        ));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("eval").arg("1 # c");
+    cmd.assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("`#` can only start a line"));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("eval").arg("");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("panicked").not());
+    Ok(())
+}
+
+#[test]
+fn remainder() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "yield 17 % 5;\nlet i := 0;\nwhile i < 7 {\nif (i % 3) == 0 {\nyield i;\n}\ni := i + 1;\n}",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("2\n0\n3\n6\n");
+    Ok(())
+}
+
+#[test]
+fn runtime_error_diagnostics() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let x := 5;\nlet y := x - 5;\nyield x / y;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("R0003"))
+        .stderr(predicate::str::contains("[3:7]"))
+        .stderr(predicate::str::contains("the program stopped here"))
+        .stderr(predicate::str::contains("help: check that the divisor"));
+
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let a := [1, 2];\nyield a[arg(0)];")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg("--check-bounds").arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("R0005"))
+        .stderr(predicate::str::contains("[2:9]"))
+        .stderr(predicate::str::contains("the program stopped here"))
+        .stderr(predicate::str::contains(
+            "help: pass the program's arguments",
+        ));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .arg("--check-bounds")
+        .arg(file.path())
+        .args(["--", "1073741825"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("R0006"))
+        .stderr(predicate::str::contains("[2:9]"))
+        .stderr(predicate::str::contains("help: `--check-bounds` traps"));
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path()).args(["--", "100000"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("R0002"))
+        .stderr(predicate::str::contains("[2:7]"))
+        .stderr(predicate::str::contains("help: check the index is within"));
+
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str("let big := 2147483647;\nyield big * 4;")?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .args(["--overflow", "trapping"])
+        .arg(file.path());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("R0004"))
+        .stderr(predicate::str::contains("[2:7]"))
+        .stderr(predicate::str::contains(
+            "help: run with `--overflow wrapping`",
+        ));
+    Ok(())
+}
+
+#[test]
+fn array_layout() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let a := [1, 2, 3];\ndata t := \"lead\";\nyield sizeof(a);\nyield addressof(a);\nyield sizeof(t);\nyield addressof(t);",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run").arg(file.path());
+    cmd.assert().success().stdout("12\n0\n16\n12\n");
+    Ok(())
+}
+
+#[test]
+fn trapping_arithmetic_stays_in_loop() -> Result<(), Box<dyn std::error::Error>> {
+    let file = assert_fs::NamedTempFile::new("main.ed")?;
+    file.write_str(
+        "let big := 2000000000;\nlet i := 0;\nwhile i < 0 {\nyield big * 4;\ni := i + 1;\n}\nyield 7;",
+    )?;
+
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("run")
+        .args(["--overflow", "trapping"])
+        .arg(file.path());
+    cmd.assert().success().stdout("7\n");
+    Ok(())
+}
@@ -25,3 +25,14 @@ fn fib() -> Result<(), Box<dyn std::error::Error>> {
     )));
     Ok(())
 }
+
+#[test]
+fn fib_stat() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("leadc")?;
+    cmd.arg("stat").arg("../examples/fib.ed");
+
+    cmd.assert().success().stdout(predicate::str::diff(
+        "instructions: 14\nlabels: 2\nvariables: 3\nstatic memory: 0 bytes\n",
+    ));
+    Ok(())
+}
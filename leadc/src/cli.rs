@@ -1,10 +1,19 @@
-use clap::{ArgGroup, Args, Parser, Subcommand};
-use lead_vm::DEFAULT_MEMORY_SIZE;
-use std::path::PathBuf;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use lead_vm::OverflowMode;
+use std::{num::ParseIntError, path::PathBuf};
+
+/// A memory size that asks for memory to be sized to fit the program.
+pub const AUTO_MEMORY_SIZE: usize = 0;
+/// The memory left free beyond a program's arrays when memory is sized automatically, one page.
+pub const DEFAULT_MEMORY_SLACK: usize = 4096;
 
 #[derive(Parser)]
 #[command(about, long_about = None)]
 pub struct Cli {
+    /// assign span ids and labels reproducibly, so the same program always compiles to the same
+    /// output. Use this for golden tests and CI
+    #[clap(long, global = true)]
+    pub deterministic: bool,
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -16,14 +25,57 @@ pub enum Commands {
     //     file: PathBuf,
     //     args: RunArgs,
     // },
+    /// evaluate an expression and print its value, as the program `yield (<EXPR>);` would
+    Eval {
+        expr: String,
+    },
     Build {
         file: PathBuf,
+        /// follow each register holding a variable with the variable's name, as `%3/*x*/`
+        #[clap(long)]
+        annotate_regs: bool,
+        /// follow each instruction with a `;` comment holding the source it was lowered from
+        #[clap(long)]
+        source_comments: bool,
+        /// the form to emit the AIR in
+        #[clap(long, value_enum, default_value_t = Emit::Air)]
+        emit: Emit,
+        /// write the AIR to this file instead of stdout
+        #[clap(short, long, required_if_eq("emit", "bin"))]
+        output: Option<PathBuf>,
     },
     Lex {
         file: PathBuf,
+        /// print each token on its own line with its source span, as `TYPE@(start,end)`
+        #[clap(long)]
+        spans: bool,
     },
     Parse {
         file: PathBuf,
+        /// print the source span of each statement, indenting nested statements
+        #[clap(long, conflicts_with = "format")]
+        spans: bool,
+        /// the form to print the syntax tree in
+        #[clap(long, value_enum, default_value_t = Format::Debug)]
+        format: Format,
+    },
+    /// compile a program and report any errors or warnings, without running it or printing AIR
+    Check {
+        file: PathBuf,
+    },
+    /// print statistics about a compiled program
+    Stat {
+        file: PathBuf,
+    },
+    /// print a longer explanation of an error code, such as `E0010`
+    Explain {
+        code: String,
+    },
+    /// print the version of the compiler
+    Version {
+        /// list every AIR instruction with a description of what it does
+        #[clap(long)]
+        instructions: bool,
     },
     Repl,
     #[cfg(debug_assertions)]
@@ -32,6 +84,26 @@ pub enum Commands {
     },
 }
 
+/// The forms `leadc parse` can print the syntax tree in.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// each statement's debug form, one per line
+    Debug,
+    /// the whole tree as JSON, tagged with the version of its schema, for external tools
+    Json,
+}
+
+/// The forms `leadc build` can emit AIR in.
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// AIR as text, one instruction per line
+    Air,
+    /// the compact binary encoding, which `leadc run` can run directly
+    Bin,
+    /// the control-flow graph of basic blocks, in Graphviz DOT format
+    Cfg,
+}
+
 #[derive(Args, Clone)]
 #[clap(groups(vec![
             ArgGroup::new("input")
@@ -44,10 +116,15 @@ pub struct RunArgs {
     pub file: Option<PathBuf>,
     #[clap(long)]
     pub stdin: bool,
-    /// memory size of the virtual machine in bytes
-    #[arg(default_value_t = DEFAULT_MEMORY_SIZE)]
-    #[clap(short)]
-    pub memory_size: usize,
+    /// memory size of the virtual machine in bytes, overriding any `#memory` pragma. `auto`, or 0,
+    /// sizes memory to fit the program's arrays, with `--memory-slack` bytes to spare
+    /// [default: 256]
+    #[clap(short, value_parser = parse_memory_size)]
+    pub memory_size: Option<usize>,
+    /// the bytes of memory left free beyond the program's arrays when memory is sized automatically
+    /// [default: 4096]
+    #[clap(long, value_name = "BYTES")]
+    pub memory_slack: Option<usize>,
     /// quiet
     #[clap(short('q'))]
     pub quiet: bool,
@@ -61,4 +138,45 @@ pub struct RunArgs {
     /// a path to a folder to log to
     #[clap(short, long)]
     pub log_path: Option<PathBuf>,
+
+    /// print the registers, named by their variables, to stderr once the program stops
+    #[clap(long)]
+    pub dump_state: bool,
+
+    /// stop the program if it is still running after this many milliseconds
+    #[clap(long)]
+    pub timeout: Option<u64>,
+
+    /// print the values yielded to stdout on one line once the program stops, separated by this
+    #[clap(long, value_name = "SEP")]
+    pub join: Option<String>,
+
+    /// check indices computed at runtime, stopping the program with an error instead of reading
+    /// outside the array
+    #[clap(long)]
+    pub check_bounds: bool,
+
+    /// what arithmetic does with a result that does not fit in an unsigned word: `wrapping`,
+    /// `saturating`, or `trapping` to stop the program with an error
+    #[clap(long, value_name = "MODE", default_value = "wrapping")]
+    pub overflow: OverflowMode,
+
+    /// reuse the AIR built by a previous run of the same source
+    #[clap(long)]
+    pub cache: bool,
+    /// the folder to cache AIR in, defaulting to one in the temporary directory
+    #[clap(long, requires("cache"))]
+    pub cache_dir: Option<PathBuf>,
+
+    /// numbers to run the program with, after `--`, which it reads with `arg(i)`
+    #[clap(last = true, allow_negative_numbers = true)]
+    pub arguments: Vec<i32>,
+}
+
+/// Parse a memory size in bytes, where `auto` is the same as 0.
+fn parse_memory_size(size: &str) -> Result<usize, ParseIntError> {
+    match size {
+        "auto" => Ok(AUTO_MEMORY_SIZE),
+        size => size.parse(),
+    }
 }
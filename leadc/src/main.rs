@@ -1,9 +1,14 @@
 use lead::{
-    air::air::Instruction,
+    air::{
+        air::{Inst, Instruction, Reg},
+        binary,
+        cfg::ControlFlowGraph,
+        comment_with_source, render_air, render_annotated_air, AirModule,
+    },
     lex::{span::Spans, token::Token, token::TokenType},
-    parse::ast::Statement,
+    parse::ast::{AstDocument, Iterable, Statement},
 };
-use leadc::cli::{Cli, Commands, RunArgs};
+use leadc::cli::{Cli, Commands, Emit, Format, RunArgs};
 use leadc::pipeline::Pipeline;
 
 use clap::Parser;
@@ -13,20 +18,26 @@ use log4rs::{
     config::{Appender, Config, Root},
     encode::pattern::PatternEncoder,
 };
-use miette::Result;
-use std::{io::stdin, path::PathBuf};
+use miette::{Context, IntoDiagnostic, Result};
+use std::{
+    collections::HashMap,
+    fs::{read_dir, read_to_string, write},
+    io::stdin,
+    path::PathBuf,
+};
 
 fn main() -> Result<()> {
     let mut cli = Cli::parse();
 
-    // for now just set the level at debug.
     let mut file_path = String::from("logs/leadc.log");
+    let mut level = LevelFilter::Info;
 
     if let Commands::Run(ref mut args) = cli.command {
         file_path = match args.log_path.take() {
             Some(pth) => pth.into_os_string().into_string().unwrap(),
             None => file_path,
         };
+        level = log_level(args);
     }
 
     let logfile = FileAppender::builder()
@@ -36,20 +47,35 @@ fn main() -> Result<()> {
 
     let config = Config::builder()
         .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .build(
-            Root::builder()
-                .appender("logfile")
-                .build(LevelFilter::Debug),
-        )
+        .build(Root::builder().appender("logfile").build(level))
         .unwrap();
 
     log4rs::init_config(config).unwrap();
 
+    if cli.deterministic {
+        lead::deterministic();
+    }
+
     match cli.command {
         Commands::Run(args) => run(args)?,
-        Commands::Build { file } => build(file)?,
-        Commands::Lex { file } => lex(file)?,
-        Commands::Parse { file } => parse(file)?,
+        Commands::Eval { expr } => eval(&expr)?,
+        Commands::Build {
+            file,
+            annotate_regs,
+            source_comments,
+            emit,
+            output,
+        } => build(file, annotate_regs, source_comments, emit, output)?,
+        Commands::Lex { file, spans } => lex(file, spans, cli.deterministic)?,
+        Commands::Parse {
+            file,
+            spans,
+            format,
+        } => parse(file, spans, format)?,
+        Commands::Check { file } => check(file)?,
+        Commands::Stat { file } => stat(file)?,
+        Commands::Explain { code } => explain(&code)?,
+        Commands::Version { instructions } => version(instructions),
 
         #[cfg(debug_assertions)]
         Commands::Test { file } => test(file)?,
@@ -59,9 +85,38 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn lex(file: PathBuf) -> Result<()> {
+/// The level to log at for the verbosity of a run.
+fn log_level(args: &RunArgs) -> LevelFilter {
+    if args.quiet {
+        LevelFilter::Warn
+    } else if args.verbose {
+        LevelFilter::Debug
+    } else if args.very_verbose {
+        LevelFilter::Trace
+    } else {
+        LevelFilter::Info
+    }
+}
+
+fn lex(file: PathBuf, spans: bool, ids: bool) -> Result<()> {
     let tokens: Vec<Token> = Pipeline::try_from(file)?.lex()?.into();
 
+    if spans {
+        for token in tokens {
+            let (start, end) = token.span().span();
+            // span ids are only worth showing when they are the same from run to run.
+            match ids {
+                true => println!(
+                    "{:?}@({start},{end})#{}",
+                    token.token_type(),
+                    token.span().id()
+                ),
+                false => println!("{:?}@({start},{end})", token.token_type()),
+            }
+        }
+        return Ok(());
+    }
+
     let mut indent: usize = 0;
 
     for token in tokens {
@@ -83,43 +138,273 @@ fn lex(file: PathBuf) -> Result<()> {
     Ok(())
 }
 
-fn parse(file: PathBuf) -> Result<()> {
+fn parse(file: PathBuf, spans: bool, format: Format) -> Result<()> {
     let ast: Vec<Statement> = Pipeline::try_from(file)?.lex()?.parse()?.into();
+    if format == Format::Json {
+        let json = serde_json::to_string(&AstDocument::new(ast)).into_diagnostic()?;
+        println!("{json}");
+        return Ok(());
+    }
     for statement in ast {
-        println!("{:?}", statement);
+        match spans {
+            true => print_spanned(&statement, 0),
+            false => println!("{:?}", statement),
+        }
     }
     Ok(())
 }
 
+/// Print a statement prefixed with its span, with the bodies of `if`, `while`, and `for`
+/// statements indented beneath it.
+fn print_spanned(statement: &Statement, depth: usize) {
+    let indent = "\t".repeat(depth);
+    let body = match statement {
+        Statement::If(r#if) => {
+            println!("{indent}{} if {:?}", statement.span(), r#if.condition);
+            for statement in &r#if.iff {
+                print_spanned(statement, depth + 1);
+            }
+            match &r#if.otherwise {
+                Some(otherwise) => {
+                    println!("{indent}else");
+                    otherwise
+                }
+                None => return,
+            }
+        }
+        Statement::While(r#while) => {
+            println!("{indent}{} while {:?}", statement.span(), r#while.condition);
+            &r#while.body
+        }
+        Statement::For(r#for) => {
+            println!(
+                "{indent}{} for {} {}",
+                statement.span(),
+                r#for.item.name(),
+                match &r#for.iterable {
+                    Iterable::Array(array) => format!("in {}", array.name()),
+                    Iterable::Range { start, end, .. } => format!("in {start:?}..{end:?}"),
+                    Iterable::Counter {
+                        start, condition, ..
+                    } => format!(":= {start:?}; {condition:?}"),
+                }
+            );
+            &r#for.body
+        }
+        _ => {
+            println!("{indent}{} {statement:?}", statement.span());
+            return;
+        }
+    };
+    for statement in body {
+        print_spanned(statement, depth + 1);
+    }
+}
+
 fn run(args: RunArgs) -> Result<()> {
-    match args.stdin {
+    match &args.file {
+        Some(dir) if dir.is_dir() => run_dir(dir.clone(), args),
+        _ => run_program(args),
+    }
+}
+
+/// Run every `.ed` file in a directory in order of name, each under a header naming it, then
+/// print how many succeeded. This fails if any program failed to build or run.
+fn run_dir(dir: PathBuf, args: RunArgs) -> Result<()> {
+    let mut files: Vec<PathBuf> = read_dir(&dir)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("reading {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ed"))
+        .collect();
+    files.sort();
+
+    let (mut succeeded, mut failed) = (0, 0);
+    for file in files {
+        println!("==> {} <==", file.display());
+        let args = RunArgs {
+            file: Some(file),
+            ..args.clone()
+        };
+        match run_program(args) {
+            Ok(()) => succeeded += 1,
+            Err(report) => {
+                eprintln!("{report:?}");
+                failed += 1;
+            }
+        }
+    }
+    println!("{succeeded} succeeded, {failed} failed");
+    match failed {
+        0 => Ok(()),
+        _ => Err(miette::miette!(
+            "{failed} of {} programs failed",
+            succeeded + failed
+        )),
+    }
+}
+
+/// Build and run a single program, from a file or stdin.
+fn run_program(args: RunArgs) -> Result<()> {
+    let cache_dir: Option<PathBuf> = args.cache.then(|| {
+        args.cache_dir
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("leadc"))
+    });
+    let pipeline = match args.stdin {
         true => Pipeline::try_from(&mut stdin())?,
-        false => Pipeline::try_from(
-            args.file
+        false => {
+            let file = args
+                .file
                 .clone()
-                .expect("stdin and file should be mutally exclusive"),
-        )?,
+                .expect("stdin and file should be mutally exclusive");
+            match file.extension().is_some_and(|extension| extension == "air") {
+                true => Pipeline::from_air_file(&file)?,
+                false => Pipeline::try_from(file)?,
+            }
+        }
+    }
+    .with_run_args(args)?;
+
+    match (&pipeline, cache_dir) {
+        (Pipeline::IntermediateRepr(..), _) => Ok(pipeline),
+        (_, Some(dir)) => pipeline.build_cached(&dir),
+        (_, None) => pipeline.lex()?.parse()?.build(),
     }
-    .with_run_args(args)?
-    .lex()?
-    .parse()?
-    .build()?
+    .map(report_warnings)?
     .run()
 }
 
-fn build(file: PathBuf) -> Result<()> {
-    let air: Vec<Instruction> = Pipeline::try_from(file)?
+/// Compile and run a bare expression by yielding it from a program of its own.
+fn eval(expr: &str) -> Result<()> {
+    let Ok(pipeline) = expr.parse::<Pipeline>();
+    pipeline
+        .lex()?
+        .parse_expression()?
+        .build()
+        .map(report_warnings)?
+        .run()
+}
+
+fn build(
+    file: PathBuf,
+    annotate_regs: bool,
+    source_comments: bool,
+    emit: Emit,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let src = match source_comments {
+        true => read_to_string(&file)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("reading {}", file.display()))?,
+        false => String::new(),
+    };
+    let module: AirModule = Pipeline::try_from(file)?
         .lex()?
         .parse()?
-        .build()?
+        .build()
+        .map(report_warnings)?
         .try_into()?;
 
-    for instruction in air {
-        print!("{instruction}");
+    if emit == Emit::Bin {
+        let output = output.expect("clap requires an output file for binary AIR");
+        return write(&output, binary::encode(&module))
+            .into_diagnostic()
+            .wrap_err_with(|| format!("writing binary AIR to {}", output.display()));
+    }
+
+    let air: Vec<Instruction> = module
+        .instructions
+        .iter()
+        .map(Inst::instruction_borrow)
+        .cloned()
+        .collect();
+    let mut rendered = match annotate_regs {
+        _ if emit == Emit::Cfg => ControlFlowGraph::from_instructions(&air).render_dot(&air),
+        true => {
+            let names: HashMap<Reg, String> = module
+                .variables
+                .into_iter()
+                .map(|(name, reg)| (reg, name))
+                .collect();
+            render_annotated_air(&air, &names)
+        }
+        false => render_air(&air),
+    };
+    if source_comments && emit == Emit::Air {
+        rendered = comment_with_source(&rendered, &module.instructions, &src);
     }
+    match output {
+        Some(output) => write(&output, rendered)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("writing AIR to {}", output.display())),
+        None => {
+            print!("{rendered}");
+            Ok(())
+        }
+    }
+}
+
+fn version(instructions: bool) {
+    println!("leadc {}", env!("CARGO_PKG_VERSION"));
+    if instructions {
+        for instruction in Instruction::kinds() {
+            let syntax = instruction.to_string();
+            println!(
+                "{:<6} {:<20} {}",
+                instruction.name(),
+                syntax.trim_end(),
+                instruction.description()
+            );
+        }
+    }
+}
+
+fn explain(code: &str) -> Result<()> {
+    match lead::explain::explain(code) {
+        Some(explanation) => println!("{explanation}"),
+        None => return Err(miette::miette!("unknown error code `{code}`")),
+    }
+    Ok(())
+}
+
+/// Build a program for its diagnostics alone. Warnings go to stderr, and an error is returned if
+/// the program does not compile.
+fn check(file: PathBuf) -> Result<()> {
+    Pipeline::try_from(file)?
+        .lex()?
+        .parse()?
+        .build()
+        .map(report_warnings)?;
+    Ok(())
+}
+
+fn stat(file: PathBuf) -> Result<()> {
+    let module: AirModule = Pipeline::try_from(file)?
+        .lex()?
+        .parse()?
+        .build()
+        .map(report_warnings)?
+        .try_into()?;
+
+    println!("instructions: {}", module.instructions.len());
+    println!("labels: {}", module.label_count());
+    println!(
+        "variables: {}",
+        module.variables.len() + module.pointers.len()
+    );
+    println!("static memory: {} bytes", module.static_memory);
     Ok(())
 }
 
+/// Print the warnings found while building a program to stderr.
+fn report_warnings(pipeline: Pipeline) -> Pipeline {
+    for warning in pipeline.warnings() {
+        eprintln!("{warning:?}");
+    }
+    pipeline
+}
+
 #[cfg(debug_assertions)]
 fn test(_file: PathBuf) -> Result<()> {
     println!("{:?}", std::env::current_dir().unwrap());
@@ -1,22 +1,40 @@
 use lead::{
     air::{
-        air::{Inst, Instruction},
-        generate_program, GenerationState,
+        air::{Inst, Instruction, Stream},
+        binary::{self, DecodeError},
+        emit_program,
+        optimise::{eliminate_common_subexpressions, hoist_loop_invariants},
+        text::parse_air,
+        AirModule, GenerationState,
     },
-    lex::{token::Token, Lexer},
+    check::TypeChecker,
+    error::LangWarning,
+    lex::{span::Span, token::Token, Lexer},
+    lint::lint,
     parse::{ast::Statement, LangParser},
+    pragma::{pragmas, Pragma},
 };
 
-use crate::cli::RunArgs;
+use crate::cli::{RunArgs, AUTO_MEMORY_SIZE, DEFAULT_MEMORY_SLACK};
 
-use lead_vm::{Machine, Message, VMFlags, DEFAULT_VERBOSITY};
+use lead_vm::{
+    error::VmError, Machine, Message, OverflowMode, VMFlags, DEFAULT_MEMORY_SIZE, DEFAULT_VERBOSITY,
+};
+use log::info;
 use miette::{Diagnostic, Result};
 use std::{
-    fs::read_to_string,
+    convert::Infallible,
+    fs::{create_dir_all, read, read_to_string, write},
     io::{Read, Stdin},
-    path::PathBuf,
-    sync::mpsc::channel,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{self, AtomicBool},
+        mpsc::{channel, RecvError, RecvTimeoutError, Sender},
+        Arc,
+    },
     thread,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
@@ -35,8 +53,10 @@ impl Into<VMFlags> for RunArgs {
         };
 
         VMFlags {
-            memory_size: self.memory_size,
+            memory_size: self.memory_size.unwrap_or(DEFAULT_MEMORY_SIZE),
             verbosity,
+            trace_memory: false,
+            overflow_mode: self.overflow,
         }
     }
 }
@@ -46,7 +66,8 @@ pub enum Pipeline {
     Text(String, Option<RunArgs>),
     Tokens(String, Option<RunArgs>, Vec<Token>),
     SyntaxTree(String, Option<RunArgs>, Vec<Statement>),
-    IntermediateRepr(String, Option<RunArgs>, Vec<Inst>),
+    /// the module, and warnings found while building it
+    IntermediateRepr(String, Option<RunArgs>, AirModule, Vec<LangWarning>),
 }
 
 impl std::fmt::Debug for Pipeline {
@@ -55,7 +76,7 @@ impl std::fmt::Debug for Pipeline {
             Self::Text(_, _) => write!(f, "Text"),
             Self::Tokens(_, _, _) => write!(f, "Tokens"),
             Self::SyntaxTree(_, _, _) => write!(f, "SyntaxTree"),
-            Self::IntermediateRepr(_, _, _) => write!(f, "Intermediate Representation"),
+            Self::IntermediateRepr(_, _, _, _) => write!(f, "Intermediate Representation"),
         }
     }
 }
@@ -89,6 +110,22 @@ pub enum PipelineError {
     ErrorReadFromStdin(String),
     #[error("{} can only be created from {}", 0, 1)]
     InvalidInto(String, String),
+    #[error("Error writing to the cache: {0}")]
+    CacheError(String),
+    /// an error that stopped the program, pointing at the source of the instruction that caused it
+    #[error("runtime error: {error}")]
+    #[diagnostic(forward(error))]
+    RuntimeError {
+        error: VmError,
+        #[source_code]
+        src: String,
+        #[label("the program stopped here")]
+        span: Option<Span>,
+    },
+    #[error("the program was still running after the time limit of {0}ms")]
+    Timeout(u64),
+    #[error("Error reading binary AIR: {0}")]
+    DecodeError(DecodeError),
 }
 
 impl Pipeline {
@@ -97,7 +134,12 @@ impl Pipeline {
             Pipeline::Text(src, _) => Ok(Pipeline::Text(src, Some(args))),
             Pipeline::Tokens(src, _, tokens) => Ok(Pipeline::Tokens(src, Some(args), tokens)),
             Pipeline::SyntaxTree(src, _, ast) => Ok(Pipeline::SyntaxTree(src, Some(args), ast)),
-            _ => Err(PipelineError::InvalidWithArgs(format!("{self:?}")).into()),
+            Pipeline::IntermediateRepr(src, _, module, warnings) => Ok(Pipeline::IntermediateRepr(
+                src,
+                Some(args),
+                module,
+                warnings,
+            )),
         }
     }
 
@@ -128,68 +170,342 @@ impl Pipeline {
         }
     }
 
+    /// Parse `Tokens` as a single expression, into a program that yields its value.
+    pub fn parse_expression(self) -> Result<Self> {
+        match self {
+            Self::Tokens(src, args, tokens) => {
+                let expr = LangParser::new(&tokens)
+                    .parse_lone_expression()
+                    .map_err(|e| e.with_src(src.clone()))?;
+                let ast = vec![Statement::Yield(expr, Stream::Out)];
+                Ok(Self::SyntaxTree(src, args, ast))
+            }
+            _ => Err(PipelineError::InvalidParse(format!("{self:?}")).into()),
+        }
+    }
+
     pub fn build(self) -> Result<Self> {
         match self {
             Self::SyntaxTree(src, args, ast) => {
+                TypeChecker::new()
+                    .check(&ast)
+                    .map_err(|err| err.with_src(src.clone()))?;
+                let mut warnings = pragmas(&src).1;
+                warnings.extend(lint(&ast));
                 let mut gen_state: GenerationState = GenerationState::new();
-                // this is not efficient at the moment
-                let air: Vec<Inst> = generate_program(&mut gen_state, ast)
-                    .map_err(|err| err.with_src(src.clone()))?
-                    .into_iter()
-                    .flatten()
-                    .collect();
-                Ok(Pipeline::IntermediateRepr(src.clone(), args, air))
+                gen_state.set_bounds_checks(args.as_ref().is_some_and(|args| args.check_bounds));
+                gen_state.set_wrapping_arithmetic(
+                    args.as_ref()
+                        .is_none_or(|args| args.overflow == OverflowMode::Wrapping),
+                );
+                emit_program(&mut gen_state, ast).map_err(|err| err.with_src(src.clone()))?;
+                let air: Vec<Inst> = gen_state.take_instructions();
+                let traps = args
+                    .as_ref()
+                    .is_some_and(|args| args.overflow == OverflowMode::Trapping);
+                let air = hoist_loop_invariants(eliminate_common_subexpressions(air), traps);
+                Ok(Pipeline::IntermediateRepr(
+                    src.clone(),
+                    args,
+                    AirModule::new(air, &gen_state),
+                    warnings,
+                ))
             }
             _ => Err(PipelineError::InvalidBuild(format!("{self:?}")).into()),
         }
     }
 
+    /// Read hand-written AIR text from a file, ready to run.
+    pub fn from_air_file(path: &Path) -> Result<Self> {
+        let src = read_to_string(path).map_err(|e| PipelineError::ReadError(format!("{:?}", e)))?;
+        let instructions = parse_air(&src).map_err(|err| err.with_src(src.clone()))?;
+        Ok(Self::IntermediateRepr(
+            src,
+            None,
+            AirModule::from_instructions(instructions),
+            Vec::new(),
+        ))
+    }
+
+    /// Build from `Text`, loading the module and its warnings from `dir` if the same source has
+    /// been built before by the same version of the compiler with the same cache format, and
+    /// caching them there otherwise. Failing to write to the cache only warns, as the program can
+    /// run without it.
+    pub fn build_cached(self, dir: &Path) -> Result<Self> {
+        let Self::Text(src, args) = self else {
+            return Err(PipelineError::InvalidBuild(format!("{self:?}")).into());
+        };
+
+        // bounds checks and the overflow mode change the AIR built from the same source.
+        let options = args.as_ref().map(|args| (args.check_bounds, args.overflow));
+        let key = stable_hash(
+            format!("{COMPILER_VERSION}:{CACHE_FORMAT_VERSION}:{options:?}:{src}").as_bytes(),
+        );
+        let path = dir.join(format!("v{CACHE_FORMAT_VERSION}-{key:016x}.json"));
+
+        let cached: Option<(AirModule, Vec<LangWarning>)> = read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok());
+        if let Some((module, warnings)) = cached {
+            info!("loaded AIR from the cache at {}", path.display());
+            return Ok(Self::IntermediateRepr(src, args, module, warnings));
+        }
+
+        let pipeline = Self::Text(src, args).lex()?.parse()?.build()?;
+        if let Self::IntermediateRepr(_, _, module, warnings) = &pipeline {
+            let written = serde_json::to_string(&(module, warnings))
+                .map_err(|err| format!("{err}"))
+                .and_then(|json| {
+                    create_dir_all(dir)
+                        .and_then(|_| write(&path, json))
+                        .map_err(|err| format!("{err}"))
+                });
+            match written {
+                Ok(()) => info!("cached AIR at {}", path.display()),
+                Err(err) => eprintln!("warning: {}", PipelineError::CacheError(err)),
+            }
+        }
+        Ok(pipeline)
+    }
+
+    /// The warnings found while building, rendered against the source.
+    pub fn warnings(&self) -> Vec<miette::Report> {
+        match self {
+            Self::IntermediateRepr(src, _, _, warnings) => warnings
+                .iter()
+                .map(|warning| warning.clone().with_src(src.clone()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Run the program, printing each value as it is yielded. If the program errors, every value
+    /// yielded before the error is printed, and then the error is returned.
     pub fn run(self) -> Result<()> {
         match self {
-            Self::IntermediateRepr(_, args, instructions) => {
+            Self::IntermediateRepr(src, args, module, _) => {
                 let (sndr, rcvr) = channel();
-                let vm_flags = match args {
-                    Some(args) => args.into(),
-                    None => VMFlags::none(),
-                };
-                let instructions = instructions
-                    .iter()
-                    .map(|inst| inst.clone().instruction()) // should check if this clone is nessessary?
-                    .collect();
-                let mut vm = Machine::new(instructions, sndr, vm_flags);
-                let vm_thread = thread::spawn(move || vm.run());
-
-                loop {
-                    match rcvr.recv() {
+                let quiet = args.as_ref().is_some_and(|args| args.quiet);
+                let timeout = args.as_ref().and_then(|args| args.timeout);
+                let dump_state = args.as_ref().is_some_and(|args| args.dump_state);
+                let mut output = Output::new(args.as_ref().and_then(|args| args.join.clone()));
+                let deadline = timeout.map(|ms| Instant::now() + Duration::from_millis(ms));
+                let stop = Arc::new(AtomicBool::new(false));
+                let mut vm = Self::machine(&src, args, module, sndr);
+                vm.stop_when(stop.clone());
+                let vm_thread = thread::spawn(move || {
+                    vm.run();
+                    vm
+                });
+                let mut yields: usize = 0;
+                let mut error: Option<VmError> = None;
+
+                // the source of the instruction that stopped the program, if it stopped with an error.
+                let span: Option<Span> = loop {
+                    let received = match deadline {
+                        None => rcvr.recv(),
+                        Some(deadline) => {
+                            match rcvr
+                                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                            {
+                                Err(RecvTimeoutError::Timeout) => {
+                                    stop.store(true, atomic::Ordering::Relaxed);
+                                    let vm =
+                                        vm_thread.join().expect("could not join the vm thread");
+                                    if dump_state {
+                                        eprint!("{}", vm.dump_state());
+                                    }
+                                    // print what was yielded before the program was stopped.
+                                    for msg in rcvr.try_iter() {
+                                        if let Message::Yield { stream, value } = msg {
+                                            output.write(stream, value)
+                                        }
+                                    }
+                                    output.flush();
+                                    return Err(PipelineError::Timeout(timeout.unwrap()).into());
+                                }
+                                received => received.map_err(|_| RecvError),
+                            }
+                        }
+                    };
+                    match received {
                         Ok(msg) => match msg {
-                            Message::Yield(val) => println!("{val}"),
+                            Message::Yield { stream, value } => {
+                                yields += 1;
+                                output.write(stream, value)
+                            }
+                            Message::Error(err) => error = Some(err),
                             Message::Done => {
-                                vm_thread.join().expect("could not join the vm thread");
-                                break;
+                                let vm = vm_thread.join().expect("could not join the vm thread");
+                                if dump_state {
+                                    eprint!("{}", vm.dump_state());
+                                }
+                                break error.as_ref().and_then(|err| vm.error_span(err));
                             }
                         },
                         Err(e) => {
                             vm_thread.join().expect("could not join the vm threaed");
+                            output.flush();
                             eprintln!("{e}");
                             return Ok(());
                         }
                     }
+                };
+                output.flush();
+
+                if let Some(error) = error {
+                    return Err(PipelineError::RuntimeError { error, src, span }.into());
+                }
+                if yields == 0 && !quiet {
+                    eprintln!("note: program produced no output; did you forget `yield`?");
                 }
                 Ok(())
             }
             _ => Err(PipelineError::InvalidRun(format!("{self:?}")).into()),
         }
     }
+
+    /// Run the program to completion on this thread, returning the values it yielded to any stream in order,
+    /// along with the error that stopped it, if there was one.
+    pub fn run_collect(self) -> Result<(Vec<u32>, Option<VmError>)> {
+        match self {
+            Self::IntermediateRepr(src, args, module, _) => {
+                let (sndr, rcvr) = channel();
+                Self::machine(&src, args, module, sndr).run();
+
+                let mut yields: Vec<u32> = Vec::new();
+                let mut error: Option<VmError> = None;
+                for msg in rcvr.try_iter() {
+                    match msg {
+                        Message::Yield { stream: _, value } => yields.push(value),
+                        Message::Error(err) => error = Some(err),
+                        Message::Done => break,
+                    }
+                }
+                Ok((yields, error))
+            }
+            _ => Err(PipelineError::InvalidRun(format!("{self:?}")).into()),
+        }
+    }
+
+    /// Create a machine to run a module, configured by the pragmas in the source and then the
+    /// run arguments. A memory size of 0 sizes memory to the module's static allocation plus some
+    /// slack.
+    fn machine(
+        src: &str,
+        args: Option<RunArgs>,
+        module: AirModule,
+        sndr: Sender<Message>,
+    ) -> Machine {
+        let mut vm_flags = VMFlags::none();
+        for pragma in pragmas(src).0 {
+            match pragma {
+                Pragma::Memory(bytes) => vm_flags.memory_size = bytes,
+            }
+        }
+        let mut slack = DEFAULT_MEMORY_SLACK;
+        let mut arguments = Vec::new();
+        if let Some(args) = args {
+            // negative arguments are passed in two's complement, like any other number.
+            arguments = args.arguments.iter().map(|&n| n as u32).collect();
+            let memory_size = args.memory_size.unwrap_or(vm_flags.memory_size);
+            slack = args.memory_slack.unwrap_or(slack);
+            vm_flags = args.into();
+            vm_flags.memory_size = memory_size;
+        }
+        if vm_flags.memory_size == AUTO_MEMORY_SIZE {
+            vm_flags.memory_size = module.static_memory + slack;
+        }
+        let mut machine = Machine::from_air_module(module, sndr, vm_flags);
+        machine.set_arguments(arguments);
+        machine
+    }
+}
+
+/// Where a running program's yielded values go. Each is printed as it arrives, unless values
+/// yielded to stdout are being joined onto one line, in which case they are held until flushed.
+struct Output {
+    /// the separator to join values yielded to stdout with, if they are joined.
+    join: Option<String>,
+    /// the values yielded to stdout since the last flush, when they are joined.
+    joined: Vec<String>,
+}
+
+impl Output {
+    fn new(join: Option<String>) -> Self {
+        Self {
+            join,
+            joined: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, stream: Stream, value: u32) {
+        match (&self.join, stream) {
+            (Some(_), Stream::Out) => self.joined.push(value.to_string()),
+            _ => print_yield(stream, value),
+        }
+    }
+
+    /// Print the joined values, if there are any.
+    fn flush(&mut self) {
+        if let Some(separator) = &self.join {
+            if !self.joined.is_empty() {
+                println!("{}", self.joined.join(separator));
+                self.joined.clear();
+            }
+        }
+    }
+}
+
+/// Print a yielded value to the stream it was yielded to.
+fn print_yield(stream: Stream, value: u32) {
+    match stream {
+        Stream::Out => println!("{value}"),
+        Stream::Err => eprintln!("{value}"),
+    }
 }
 
 impl TryFrom<PathBuf> for Pipeline {
     type Error = PipelineError;
     fn try_from(value: PathBuf) -> std::result::Result<Self, Self::Error> {
-        Ok(Pipeline::Text(
-            read_to_string(value.as_path())
-                .map_err(|e| PipelineError::ReadError(format!("{:?}", e)))?,
-            None,
-        ))
+        let bytes: Vec<u8> =
+            read(value.as_path()).map_err(|e| PipelineError::ReadError(format!("{:?}", e)))?;
+        // binary AIR has already been built, so it skips straight to the intermediate representation.
+        if binary::is_binary(&bytes) {
+            let module = binary::decode(&bytes).map_err(PipelineError::DecodeError)?;
+            return Ok(Pipeline::IntermediateRepr(
+                String::new(),
+                None,
+                module,
+                Vec::new(),
+            ));
+        }
+        let input: String =
+            String::from_utf8(bytes).map_err(|_| PipelineError::InvalidUTF8Input)?;
+        Ok(Pipeline::Text(input, None))
+    }
+}
+
+/// The version of the format modules are cached in, raised whenever a change to `AirModule` means
+/// modules cached before it should no longer be used.
+const CACHE_FORMAT_VERSION: u32 = 1;
+
+/// The version of `leadc`, part of each cache key so that a module cached by another release of
+/// the compiler, which may lower the same source differently, is never used.
+const COMPILER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A 64 bit FNV-1a hash, which stays the same between runs and releases of Rust, unlike
+/// `DefaultHasher`.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl FromStr for Pipeline {
+    type Err = Infallible;
+    fn from_str(src: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Pipeline::Text(src.to_owned(), None))
     }
 }
 
@@ -227,7 +543,8 @@ impl TryInto<Vec<Instruction>> for Pipeline {
     type Error = PipelineError;
     fn try_into(self) -> std::result::Result<Vec<Instruction>, Self::Error> {
         match self {
-            Self::IntermediateRepr(_, _, instructions) => Ok(instructions
+            Self::IntermediateRepr(_, _, module, _) => Ok(module
+                .instructions
                 .iter()
                 .map(|inst| inst.clone().instruction())
                 .collect()),
@@ -243,7 +560,7 @@ impl TryInto<Vec<Inst>> for Pipeline {
     type Error = PipelineError;
     fn try_into(self) -> std::result::Result<Vec<Inst>, Self::Error> {
         match self {
-            Self::IntermediateRepr(_, _, instructions) => Ok(instructions),
+            Self::IntermediateRepr(_, _, module, _) => Ok(module.instructions),
             _ => Err(PipelineError::InvalidInto(
                 "Vec<Inst>".to_owned(),
                 "IntermediateRepr".to_owned(),
@@ -251,3 +568,16 @@ impl TryInto<Vec<Inst>> for Pipeline {
         }
     }
 }
+
+impl TryInto<AirModule> for Pipeline {
+    type Error = PipelineError;
+    fn try_into(self) -> std::result::Result<AirModule, Self::Error> {
+        match self {
+            Self::IntermediateRepr(_, _, module, _) => Ok(module),
+            _ => Err(PipelineError::InvalidInto(
+                "AirModule".to_owned(),
+                "IntermediateRepr".to_owned(),
+            )),
+        }
+    }
+}